@@ -1,6 +1,8 @@
 use std::env;
 
 use anyhow::Context;
+use common::TICKET_ALERTS_QUEUE;
+use common::dto::TicketAlert;
 use common::queue::QueueManager;
 use common::queue::kafka::KafkaQueueManager;
 use reqwest::Client;
@@ -12,30 +14,68 @@ fn get_webhook_url() -> anyhow::Result<String> {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let mut queue_mgr = KafkaQueueManager::new()
+    let queue_mgr = KafkaQueueManager::new()
         .await
         .context("Failed to connect to postgres")?;
 
     // Check that we can get the URL:
     get_webhook_url()?;
 
-    queue_mgr
-        .register_read("^.*_dlq", &async |msg: common::queue::Message<
+    // Separate consumer, a Kafka consumer only follows one subscription
+    let alerts_mgr = KafkaQueueManager::with_group_id("alerting-ticket-alerts")
+        .await
+        .context("Failed to connect to Kafka")?;
+    alerts_mgr.create(TICKET_ALERTS_QUEUE).await?;
+
+    tokio::try_join!(
+        queue_mgr.register_read("^.*_dlq", &async |msg: common::queue::Message<
             serde_json::Value,
         >| {
             println!("Received message in DLQ");
-            on_message("test_dlq", &msg.message).await
-        })
-        .await
-        .context("Failed to register read handler for test_dlq")?;
+            // Dead letters tell where they come from and why
+            let queue = msg.message["queue"].as_str().unwrap_or("unknown queue");
+            on_message(queue, &msg.message).await
+        }),
+        alerts_mgr.register_read(TICKET_ALERTS_QUEUE, &async |msg: common::queue::Message<
+            TicketAlert,
+        >| {
+            on_alert(&msg.message).await
+        }),
+    )
+    .context("Failed to register read handlers")?;
+
+    Ok(())
+}
 
+/// Alert raised by a routing rule of ticket-router
+async fn on_alert(alert: &TicketAlert) -> anyhow::Result<()> {
+    println!(
+        "Received alert for ticket {}: {}",
+        alert.ticket_id, alert.message
+    );
+    let payload = json!({
+        "content": format!(
+            "# 🔔 {} \nTicket `{}` ({:?}): {}\nFrom {}, rule `{}`",
+            alert.message, alert.ticket_id, alert.priority, alert.title, alert.contact, alert.rule
+        )
+    });
+    Client::new()
+        .post(get_webhook_url()?)
+        .json(&payload)
+        .send()
+        .await?
+        .error_for_status()?;
     Ok(())
 }
 
 async fn on_message(queue: &str, msg: &serde_json::Value) -> anyhow::Result<()> {
     println!("Received message from {}: {:?}", queue, msg);
+    let reason = msg["reason"]
+        .as_str()
+        .map(|reason| format!("Reason: {}\n", reason))
+        .unwrap_or_default();
     let payload = json!({
-        "content": format!("# ⚠️Dead message for queue `{}`⚠️ \n ```json\n{}\n```", queue, msg)
+        "content": format!("# ⚠️Dead message for queue `{}`⚠️ \n{} ```json\n{}\n```", queue, reason, msg)
     });
     let client = Client::new();
 
@@ -0,0 +1,41 @@
+CREATE TABLE IF NOT EXISTS tickets (
+    id TEXT PRIMARY KEY,
+    contact TEXT NOT NULL,
+    origin TEXT NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    status TEXT NOT NULL,
+    merged_into TEXT REFERENCES tickets (id),
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS tickets_contact_idx ON tickets (contact);
+CREATE INDEX IF NOT EXISTS tickets_status_idx ON tickets (status);
+
+-- Messages are kept as JSON, so that new CommonMessage fields need no migration
+CREATE TABLE IF NOT EXISTS ticket_messages (
+    ticket_id TEXT NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    sent_at BIGINT NOT NULL,
+    message JSONB NOT NULL,
+    PRIMARY KEY (ticket_id, position)
+);
+
+CREATE TABLE IF NOT EXISTS ticket_tags (
+    ticket_id TEXT NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (ticket_id, position)
+);
+
+CREATE INDEX IF NOT EXISTS ticket_tags_tag_idx ON ticket_tags (tag);
+
+CREATE TABLE IF NOT EXISTS ticket_status_history (
+    ticket_id TEXT NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    status TEXT NOT NULL,
+    changed_at BIGINT NOT NULL,
+    reason TEXT,
+    PRIMARY KEY (ticket_id, position)
+);
@@ -0,0 +1,3 @@
+-- Tickets used to be either Open or Closed, open ones had not been triaged yet
+UPDATE tickets SET status = 'New' WHERE status = 'Open';
+UPDATE ticket_status_history SET status = 'New' WHERE status = 'Open';
@@ -0,0 +1,8 @@
+-- Message-IDs of the emails of each ticket, to thread replies
+CREATE TABLE IF NOT EXISTS email_threads (
+    message_id TEXT PRIMARY KEY,
+    ticket_id TEXT NOT NULL,
+    recorded_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS email_threads_ticket_idx ON email_threads (ticket_id);
@@ -0,0 +1,18 @@
+-- Replies sent to the customer and their delivery progress, as JSON like the messages
+CREATE TABLE IF NOT EXISTS ticket_replies (
+    ticket_id TEXT NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    outbound_id TEXT NOT NULL,
+    state TEXT NOT NULL,
+    reply JSONB NOT NULL,
+    PRIMARY KEY (ticket_id, position)
+);
+
+-- Contacts that cannot be reached, e.g. an email address that bounced
+CREATE TABLE IF NOT EXISTS contact_flags (
+    contact TEXT NOT NULL,
+    flag TEXT NOT NULL,
+    reason TEXT,
+    flagged_at BIGINT NOT NULL,
+    PRIMARY KEY (contact, flag)
+);
@@ -0,0 +1,16 @@
+-- Customers, one profile whatever the channels they write from
+CREATE TABLE IF NOT EXISTS customers (
+    id TEXT PRIMARY KEY,
+    merged_into TEXT REFERENCES customers (id),
+    created_at BIGINT NOT NULL
+);
+
+-- Normalized contacts: E.164 phone numbers, lowercased email addresses and channel handles
+CREATE TABLE IF NOT EXISTS contact_identities (
+    identity TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    customer_id TEXT NOT NULL REFERENCES customers (id),
+    linked_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS contact_identities_customer_idx ON contact_identities (customer_id);
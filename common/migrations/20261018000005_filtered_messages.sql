@@ -0,0 +1,10 @@
+-- Messages message-filter kept from labeling: auto-replies, blocked senders, floods and spam
+CREATE TABLE IF NOT EXISTS filtered_messages (
+    id BIGSERIAL PRIMARY KEY,
+    contact TEXT NOT NULL,
+    reason JSONB NOT NULL,
+    filtered_at BIGINT NOT NULL,
+    message JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS filtered_messages_contact_idx ON filtered_messages (contact, filtered_at);
@@ -0,0 +1,5 @@
+-- Set by the routing rules of ticket-router
+ALTER TABLE tickets ADD COLUMN IF NOT EXISTS priority TEXT NOT NULL DEFAULT 'Normal';
+ALTER TABLE tickets ADD COLUMN IF NOT EXISTS team TEXT;
+
+CREATE INDEX IF NOT EXISTS tickets_team_idx ON tickets (team, status);
@@ -0,0 +1,29 @@
+-- Team queues tickets are routed to, and how their tickets are given to agents
+CREATE TABLE IF NOT EXISTS teams (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    strategy TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS agents (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    teams TEXT[] NOT NULL DEFAULT '{}',
+    skills TEXT[] NOT NULL DEFAULT '{}',
+    capacity INT NOT NULL,
+    working_hours JSONB,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    last_assigned_at BIGINT
+);
+
+ALTER TABLE tickets ADD COLUMN IF NOT EXISTS assignee TEXT;
+CREATE INDEX IF NOT EXISTS tickets_assignee_idx ON tickets (assignee, status);
+
+-- Every agent a ticket was given to, as JSON like the replies
+CREATE TABLE IF NOT EXISTS ticket_assignments (
+    ticket_id TEXT NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    agent_id TEXT NOT NULL,
+    assignment JSONB NOT NULL,
+    PRIMARY KEY (ticket_id, position)
+);
@@ -0,0 +1,2 @@
+-- Saves of a ticket, to detect concurrent updates: a save only applies to the version it loaded
+ALTER TABLE tickets ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0;
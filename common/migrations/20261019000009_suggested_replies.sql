@@ -0,0 +1,15 @@
+-- Replies drafted by reply-suggest-trt, until an agent approves, edits or rejects them
+CREATE TABLE IF NOT EXISTS suggested_replies (
+    id TEXT PRIMARY KEY,
+    ticket_id TEXT NOT NULL,
+    contact TEXT NOT NULL,
+    origin TEXT NOT NULL,
+    -- As drafted, then as sent once edited
+    body TEXT NOT NULL,
+    sources TEXT[] NOT NULL,
+    status TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    llm_usage JSONB
+);
+
+CREATE INDEX IF NOT EXISTS suggested_replies_ticket_idx ON suggested_replies (ticket_id, created_at);
@@ -0,0 +1,27 @@
+-- Chat conversations (WhatsApp or Signal number, Telegram chat, web chat session) and the ticket
+-- they currently feed. They were kept in email_threads under a `<channel>:` prefix before.
+CREATE TABLE IF NOT EXISTS chat_threads (
+    origin TEXT NOT NULL,
+    thread_key TEXT NOT NULL,
+    ticket_id TEXT NOT NULL,
+    recorded_at BIGINT NOT NULL,
+    PRIMARY KEY (origin, thread_key)
+);
+
+CREATE TEMPORARY TABLE chat_prefixes (prefix TEXT PRIMARY KEY, origin TEXT NOT NULL);
+INSERT INTO chat_prefixes VALUES
+    ('whatsapp', 'WhatsApp'), ('signal', 'Signal'), ('telegram', 'Telegram'), ('webchat', 'WebChat');
+
+-- Message-IDs always have an `@`, chat keys never had one
+INSERT INTO chat_threads (origin, thread_key, ticket_id, recorded_at)
+SELECT p.origin, substr(t.message_id, length(p.prefix) + 2), t.ticket_id, t.recorded_at
+FROM email_threads t
+JOIN chat_prefixes p ON t.message_id LIKE p.prefix || ':%'
+WHERE strpos(t.message_id, '@') = 0
+ON CONFLICT DO NOTHING;
+
+DELETE FROM email_threads t
+USING chat_prefixes p
+WHERE t.message_id LIKE p.prefix || ':%' AND strpos(t.message_id, '@') = 0;
+
+DROP TABLE chat_prefixes;
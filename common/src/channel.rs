@@ -0,0 +1,376 @@
+//! Normalizers of the channels. A channel implements `ChannelAdapter` and `run` does the rest:
+//! topics, consumption, resolution of the customer, archiving of the raw payload, forwarding to
+//! the common topic and moving the messages that can never be normalized to the DLQ.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dto::{CommonMessage, Origin},
+    queue::{get_dlq_name, kafka::KafkaQueueManager, DeadLetter, Message, QueueManager},
+    storage::{archive_raw, s3::S3ObjectStore, ObjectStore},
+    store::{
+        postgres::PostgresTicketRepository, postgres_url_from_env, ContactDirectory, ThreadIndex,
+        TicketRepository,
+    },
+    ticket::{self, TicketStatus},
+    validation::ValidationError,
+    COMMON_MSG_QUEUE,
+};
+
+#[allow(async_fn_in_trait)]
+pub trait ChannelAdapter: Send + Sync {
+    /// Message as published on the channel topic
+    type Input: for<'de> Deserialize<'de> + Serialize + Clone;
+
+    /// What `normalize` extracted besides the message and `archive` stores, e.g. the MIME
+    /// attachments of an email. `()` when there is nothing more than the input.
+    type Parts;
+
+    /// Channel name, for the logs
+    const NAME: &'static str;
+
+    /// Topic the channel publishes its messages on
+    const SOURCE_QUEUE: &'static str;
+
+    /// Reject a message that no retry would normalize, it is moved to the DLQ of the channel
+    fn validate(&self, _input: &Self::Input) -> Result<()> {
+        Ok(())
+    }
+
+    /// Conversation the normalized message belongs to within the channel, for chats: its
+    /// messages feed the same ticket until it is closed
+    fn thread_key(&self, _input: &Self::Input, _message: &CommonMessage) -> Option<String> {
+        None
+    }
+
+    /// `None` when the message was handled without concerning a ticket, e.g. a delivery report.
+    /// A `ValidationError` rejects the message, as `validate` does.
+    async fn normalize(&self, input: Self::Input) -> Result<Option<(CommonMessage, Self::Parts)>>;
+
+    /// Keep the payload the message was normalized from, once its ticket id is assigned
+    async fn archive(
+        &self,
+        store: &impl ObjectStore,
+        input: &Self::Input,
+        message: &mut CommonMessage,
+        _parts: Self::Parts,
+    ) -> Result<()> {
+        archive_raw(store, input, message).await?;
+        Ok(())
+    }
+}
+
+/// What becomes of a message of the channel
+#[derive(Debug)]
+pub enum Outcome {
+    Forward(Box<CommonMessage>),
+    Handled,
+    Rejected(anyhow::Error),
+}
+
+/// Everything `run` does with a message but the queues
+pub async fn process<A: ChannelAdapter>(
+    adapter: &A,
+    directory: &(impl ContactDirectory + ThreadIndex + TicketRepository),
+    object_store: Option<&impl ObjectStore>,
+    input: A::Input,
+) -> Result<Outcome> {
+    if let Err(reason) = adapter.validate(&input) {
+        return Ok(Outcome::Rejected(reason));
+    }
+    let (mut common_msg, parts) = match adapter.normalize(input.clone()).await {
+        Ok(Some(normalized)) => normalized,
+        Ok(None) => return Ok(Outcome::Handled),
+        Err(e) if e.downcast_ref::<ValidationError>().is_some() => return Ok(Outcome::Rejected(e)),
+        Err(e) => return Err(e),
+    };
+    common_msg.ticket_hint = known_ticket(directory, common_msg.ticket_hint.take()).await?;
+    let thread_key = adapter.thread_key(&input, &common_msg);
+    if let (Some(thread_key), None) = (&thread_key, &common_msg.ticket_hint) {
+        common_msg.ticket_hint = open_thread(directory, common_msg.origin, thread_key).await?;
+    }
+    let ticket_id = common_msg.assign_ticket_id();
+    if let Some(thread_key) = &thread_key {
+        directory
+            .rethread(common_msg.origin, thread_key, &ticket_id)
+            .await?;
+    }
+    common_msg.customer_id = Some(directory.resolve_contact(&common_msg.contact).await?);
+    // Raw payloads are archived when `USE_MINIO=true`
+    if let Some(store) = object_store {
+        adapter
+            .archive(store, &input, &mut common_msg, parts)
+            .await?;
+    }
+    Ok(Outcome::Forward(Box::new(common_msg)))
+}
+
+/// The hint when it names a stored ticket. Hints come from customers, e.g. `support+<id>@`: any
+/// other would start a ticket with an id of their choosing.
+pub async fn known_ticket(
+    repository: &impl TicketRepository,
+    hint: Option<String>,
+) -> Result<Option<String>> {
+    let Some(hint) = hint else {
+        return Ok(None);
+    };
+    Ok(repository.resolve(&hint).await?.map(|_| hint))
+}
+
+/// Ticket a chat conversation still feeds: any but a closed one. A ticket not stored yet is
+/// still on its way through the pipeline.
+async fn open_thread(
+    store: &(impl ThreadIndex + TicketRepository),
+    origin: Origin,
+    thread_key: &str,
+) -> Result<Option<String>> {
+    let Some(ticket_id) = store.chat_thread(origin, thread_key).await? else {
+        return Ok(None);
+    };
+    Ok(match store.resolve(&ticket_id).await? {
+        Some(ticket) if ticket.status == TicketStatus::Closed => None,
+        Some(ticket) => Some(ticket.id),
+        None => Some(ticket_id),
+    })
+}
+
+/// Normalize the messages of the channel for good
+pub async fn run<A: ChannelAdapter>(adapter: A) -> Result<()> {
+    println!("Starting {} processor with Kafka...", A::NAME);
+    let queue_mgr = KafkaQueueManager::new()
+        .await
+        .context("Failed to connect to Kafka - ensure Kafka is running on localhost:9092")?;
+
+    // Create queues
+    queue_mgr
+        .create(A::SOURCE_QUEUE)
+        .await
+        .with_context(|| format!("Failed to create {} topic '{}'", A::NAME, A::SOURCE_QUEUE))?;
+    queue_mgr
+        .create(COMMON_MSG_QUEUE)
+        .await
+        .with_context(|| format!("Failed to create common topic '{}'", COMMON_MSG_QUEUE))?;
+
+    let object_store = S3ObjectStore::from_env().await?;
+    let directory = PostgresTicketRepository::connect(&postgres_url_from_env()).await?;
+
+    println!(
+        "Listening for {} messages on topic '{}'...",
+        A::NAME,
+        A::SOURCE_QUEUE
+    );
+
+    queue_mgr
+        .register_read(A::SOURCE_QUEUE, &async |wrapper: Message<A::Input>| {
+            match process(
+                &adapter,
+                &directory,
+                object_store.as_ref(),
+                wrapper.message.clone(),
+            )
+            .await?
+            {
+                Outcome::Forward(common_msg) => {
+                    println!("Transformed to: {:?}", common_msg);
+                    let forwarded_id = queue_mgr
+                        .send(COMMON_MSG_QUEUE, &common_msg)
+                        .await
+                        .context("Failed to forward message to common queue")?;
+                    println!("Forwarded to common queue (id={})", forwarded_id);
+                }
+                Outcome::Handled => {}
+                Outcome::Rejected(reason) => {
+                    println!("Rejected message {}: {}", wrapper.msg_id, reason);
+                    let dead_letter = DeadLetter {
+                        queue: A::SOURCE_QUEUE.to_string(),
+                        reason: reason.to_string(),
+                        rejected_at: ticket::now(),
+                        message: wrapper.message,
+                    };
+                    queue_mgr
+                        .send(&get_dlq_name(A::SOURCE_QUEUE), &dead_letter)
+                        .await
+                        .context("Failed to move rejected message to the DLQ")?;
+                }
+            }
+            Ok(())
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dto::WhatsAppMessage, storage::memory::MemoryObjectStore,
+        store::memory::MemoryTicketRepository,
+    };
+
+    struct WhatsApp;
+
+    impl ChannelAdapter for WhatsApp {
+        type Input = WhatsAppMessage;
+        type Parts = ();
+        const NAME: &'static str = "WhatsApp";
+        const SOURCE_QUEUE: &'static str = "test_whatsapp_messages";
+
+        fn validate(&self, input: &WhatsAppMessage) -> Result<()> {
+            anyhow::ensure!(input.sender != "+33600000000", "Blocked sender");
+            Ok(())
+        }
+
+        async fn normalize(&self, input: WhatsAppMessage) -> Result<Option<(CommonMessage, ())>> {
+            // Reactions and the like are not forwarded
+            if input.content == "👍" {
+                return Ok(None);
+            }
+            Ok(Some((input.try_into()?, ())))
+        }
+
+        fn thread_key(&self, _input: &WhatsAppMessage, message: &CommonMessage) -> Option<String> {
+            Some(message.contact.clone())
+        }
+    }
+
+    fn message(sender: &str, content: &str) -> WhatsAppMessage {
+        WhatsAppMessage {
+            sender: sender.to_string(),
+            content: content.to_string(),
+            timestamp: 1760781600,
+            attachments: vec![],
+            message_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process() -> Result<()> {
+        let store = MemoryObjectStore::default();
+        let directory = MemoryTicketRepository::default();
+
+        let Outcome::Forward(forwarded) = process(
+            &WhatsApp,
+            &directory,
+            Some(&store),
+            message("33612345678", "Bonjour"),
+        )
+        .await?
+        else {
+            panic!("Message not forwarded");
+        };
+        assert_eq!(forwarded.origin, Origin::WhatsApp);
+        assert!(forwarded.ticket_id.is_some());
+        // Nothing refers to an existing ticket
+        assert_eq!(forwarded.ticket_hint, None);
+        assert_eq!(
+            forwarded.customer_id,
+            Some(directory.resolve_contact("+33 6 12 34 56 78").await?)
+        );
+        let raw = store
+            .get(&forwarded.raw_object_key.unwrap())
+            .await?
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<WhatsAppMessage>(&raw)?.content,
+            "Bonjour"
+        );
+
+        assert!(matches!(
+            process(
+                &WhatsApp,
+                &directory,
+                Some(&store),
+                message("33612345678", "👍")
+            )
+            .await?,
+            Outcome::Handled
+        ));
+        assert_eq!(forwarded.contact, "+33612345678");
+
+        assert!(matches!(
+            process(
+                &WhatsApp,
+                &directory,
+                Some(&store),
+                message("+33600000000", "Allo")
+            )
+            .await?,
+            Outcome::Rejected(_)
+        ));
+        // Rejected by the conversion
+        let Outcome::Rejected(reason) = process(
+            &WhatsApp,
+            &directory,
+            Some(&store),
+            message("33612345678", ""),
+        )
+        .await?
+        else {
+            panic!("Empty message not rejected");
+        };
+        assert_eq!(
+            reason.downcast_ref::<ValidationError>(),
+            Some(&ValidationError::EmptyBody)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_only_stored_tickets_are_hinted() -> Result<()> {
+        let directory = MemoryTicketRepository::default();
+        directory
+            .upsert_labeled(crate::dto::LabeledTicket::test(
+                "42",
+                CommonMessage::test("jane@example.com", "Bonjour"),
+            ))
+            .await?;
+
+        assert_eq!(
+            known_ticket(&directory, Some("42".to_string())).await?,
+            Some("42".to_string())
+        );
+        assert_eq!(
+            known_ticket(&directory, Some("billing".to_string())).await?,
+            None
+        );
+        assert_eq!(known_ticket(&directory, None).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_chat_feeds_its_ticket_until_closed() -> Result<()> {
+        let directory = MemoryTicketRepository::default();
+        let forward = async |content: &str| -> Result<CommonMessage> {
+            match process(
+                &WhatsApp,
+                &directory,
+                None::<&MemoryObjectStore>,
+                message("33612345678", content),
+            )
+            .await?
+            {
+                Outcome::Forward(common_msg) => Ok(*common_msg),
+                outcome => anyhow::bail!("Message not forwarded: {:?}", outcome),
+            }
+        };
+
+        let first = forward("Bonjour").await?;
+        let ticket_id = first.ticket_id.clone().unwrap();
+        // The ticket is still on its way to storage
+        let second = forward("Mon colis n'est pas arrivé").await?;
+        assert_eq!(second.ticket_hint.as_ref(), Some(&ticket_id));
+        assert_eq!(second.ticket_id.as_ref(), Some(&ticket_id));
+
+        directory
+            .upsert_labeled(crate::dto::LabeledTicket::test(&ticket_id, first))
+            .await?;
+        let mut ticket = directory.get(&ticket_id).await?.unwrap();
+        ticket.status = TicketStatus::Closed;
+        directory.save(&ticket).await?;
+        let third = forward("Bonjour, encore moi").await?;
+        assert_eq!(third.ticket_hint, None);
+        assert_ne!(third.ticket_id.as_ref(), Some(&ticket_id));
+        assert_eq!(forward("Merci").await?.ticket_hint, third.ticket_id);
+        Ok(())
+    }
+}
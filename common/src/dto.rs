@@ -1,33 +1,235 @@
+use std::collections::BTreeMap;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Debug, Deserialize)]
+use crate::ticket::{Assignment, DeliveryState, Priority, Routing, TicketStatus};
+use crate::validation::{self, ValidationError};
+
+/// A file sent along a message: email MIME part, WhatsApp image, voice note or document
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Hex-encoded SHA-256 of the content
+    pub checksum: String,
+    /// Object storage key of the content, once stored
+    pub storage_key: Option<String>,
+    /// Id of the content on the channel it came from, e.g. a WhatsApp media id
+    #[serde(default)]
+    pub source_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
 pub struct WhatsAppMessage {
     pub sender: String,
     pub content: String,
     pub timestamp: u64,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// WhatsApp message id (`wamid.…`), replies quote it
+    #[serde(default)]
+    pub message_id: Option<String>,
 }
 
-#[derive(Serialize, Debug, Deserialize)]
+#[derive(Serialize, Debug, Deserialize, Clone)]
 pub struct EmailMessage {
     pub from: String,
     pub to: String,
     pub content: String,
     pub timestamp: u64,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    /// `Message-ID` header, without angle brackets
+    #[serde(default)]
+    pub message_id: Option<String>,
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
+    /// `References` header, oldest message first
+    #[serde(default)]
+    pub references: Vec<String>,
+    /// Headers telling how the email was sent, e.g. `Auto-Submitted`, by lowercased name
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
 }
 
-#[derive(Serialize, Debug, Deserialize)]
+/// A Telegram Bot API message, text or media with its caption
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct TelegramMessage {
+    pub update_id: i64,
+    /// Id of the message within its chat, replies quote it
+    pub message_id: i64,
+    /// Private chat with the customer, the bot writes back to it
+    pub chat_id: i64,
+    pub username: Option<String>,
+    pub first_name: Option<String>,
+    /// Text, or caption of the media
+    pub text: String,
+    /// Unix seconds
+    pub date: u64,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+/// A Signal message, as received by signal-cli
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct SignalMessage {
+    /// Sender phone number, E.164
+    pub source: String,
+    pub source_name: Option<String>,
+    pub message: String,
+    /// Unix milliseconds, which also identify the message for quotes
+    pub timestamp: u64,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+/// A message left on the website, from the contact form or the chat widget
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct WebChatMessage {
+    /// Browser session, the conversation of a visitor who left no email address
+    pub session_id: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub subject: Option<String>,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+/// An email as received, before MIME parsing
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct RawEmailMessage {
+    /// Base64 of the RFC 5322 message
+    pub raw: String,
+    pub received_at: u64,
+    /// Envelope recipients (`RCPT TO`), unknown for mail polled over IMAP. They may differ from
+    /// the `To` header, e.g. for `Bcc` or forwarded mail.
+    #[serde(default)]
+    pub recipients: Vec<String>,
+}
+
+impl RawEmailMessage {
+    pub fn new(raw: &[u8], recipients: Vec<String>, received_at: u64) -> Self {
+        RawEmailMessage {
+            raw: BASE64_STANDARD.encode(raw),
+            received_at,
+            recipients,
+        }
+    }
+
+    pub fn bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(BASE64_STANDARD.decode(&self.raw)?)
+    }
+
+    /// Ticket of the first `support+<ticket id>@` envelope recipient
+    pub fn ticket_hint(&self) -> Option<String> {
+        self.recipients
+            .iter()
+            .find_map(|recipient| plus_tag(recipient))
+    }
+}
+
+/// What email-trt accepts on the email topic
+#[derive(Serialize, Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum InboundEmail {
+    Raw(RawEmailMessage),
+    Parsed(Box<EmailMessage>),
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Origin {
     WhatsApp,
     Email,
+    Telegram,
+    Signal,
+    WebChat,
 }
 
-#[derive(Serialize, Debug, Deserialize)]
+impl Origin {
+    /// Whether replies can be sent on the channel, by email-sender or whatsapp-sender
+    pub fn has_sender(self) -> bool {
+        matches!(self, Origin::Email | Origin::WhatsApp)
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
 pub struct CommonMessage {
     pub contact: String,
     pub origin: Origin,
     pub body: String,
     pub timestamp: u64,
+    /// Existing ticket the message refers to, e.g. from a `support+<id>` address or a reply
     pub ticket_hint: Option<String>,
+    /// Ticket the message lands in: the hinted one, or a new one, set by the channel
+    /// normalizers
+    #[serde(default)]
+    pub ticket_id: Option<String>,
+    /// Subject of the email the message came from
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// Message-ID of the email, or WhatsApp message id, the message came from, to thread the
+    /// replies
+    #[serde(default)]
+    pub message_id: Option<String>,
+    /// ISO 639-1 code of the body language, set by translate-trt
+    #[serde(default)]
+    pub language: Option<String>,
+    /// English translation of the body, when it was written in another language
+    #[serde(default)]
+    pub translated_body: Option<String>,
+    /// Object storage key of the payload the message was normalized from
+    #[serde(default)]
+    pub raw_object_key: Option<String>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// Customer profile of the contact, the same on every channel, set by the channel
+    /// normalizers
+    #[serde(default)]
+    pub customer_id: Option<String>,
+    /// Headers of the email the message came from, for the filters
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl CommonMessage {
+    /// Id of the ticket the message belongs to: the one it refers to, or a new one
+    pub fn assign_ticket_id(&mut self) -> String {
+        let hint = self.ticket_hint.clone();
+        self.ticket_id
+            .get_or_insert_with(|| hint.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()))
+            .clone()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl CommonMessage {
+    /// Email with only a contact and a body, for tests. Other fields are set with the struct
+    /// update syntax.
+    pub fn test(contact: &str, body: &str) -> CommonMessage {
+        CommonMessage {
+            contact: contact.to_string(),
+            origin: Origin::Email,
+            body: body.to_string(),
+            timestamp: 100,
+            ticket_hint: None,
+            ticket_id: None,
+            subject: None,
+            message_id: None,
+            language: None,
+            translated_body: None,
+            raw_object_key: None,
+            attachments: vec![],
+            customer_id: None,
+            headers: BTreeMap::new(),
+        }
+    }
 }
 
 #[derive(Serialize, Debug, Deserialize)]
@@ -36,7 +238,7 @@ pub struct NewTicket {
     pub init_message: CommonMessage,
 }
 
-#[derive(Serialize, Debug, Deserialize)]
+#[derive(Serialize, Debug, Deserialize, Clone)]
 pub struct LabeledTicket {
     pub id: String,
     pub original_message: CommonMessage,
@@ -44,36 +246,398 @@ pub struct LabeledTicket {
     pub tags: Vec<String>,
     pub description: String,
     pub labeled_at: u64,
+    #[serde(default)]
+    pub llm_usage: Option<LlmUsage>,
+    /// Version of the prompt template used for labeling
+    #[serde(default)]
+    pub prompt_version: Option<String>,
+    /// Knowledge-base articles given to the model as context
+    #[serde(default)]
+    pub kb_citations: Vec<String>,
+    /// Set by ticket-router, `None` for tickets labeled before routing existed
+    #[serde(default)]
+    pub routing: Option<Routing>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl LabeledTicket {
+    /// Ticket labeled right after its message, for tests
+    pub fn test(id: &str, message: CommonMessage) -> LabeledTicket {
+        LabeledTicket {
+            id: id.to_string(),
+            title: format!("Ticket {}", id),
+            tags: vec![],
+            description: String::new(),
+            labeled_at: message.timestamp,
+            original_message: message,
+            llm_usage: None,
+            prompt_version: None,
+            kb_citations: vec![],
+            routing: None,
+        }
+    }
+}
+
+/// Accounting for the LLM call that produced a labeling
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct LlmUsage {
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub latency_ms: u64,
+    pub estimated_cost_usd: f64,
+    /// True when the result was served from the labeling cache (no tokens were spent)
+    pub cached: bool,
+}
+
+/// Two tickets that look like the same request, found by ticket-similarity
+#[derive(Serialize, Debug, Deserialize)]
+pub struct MergeSuggestion {
+    /// The newer ticket, which would be merged
+    pub ticket_id: String,
+    /// The older ticket it would be merged into
+    pub candidate_ticket_id: String,
+    pub score: f32,
+    pub reasons: Vec<String>,
+    pub created_at: u64,
+}
+
+/// Merge `source_ticket_id` into `target_ticket_id`, leaving a redirect behind
+#[derive(Serialize, Debug, Deserialize)]
+pub struct MergeRequest {
+    pub source_ticket_id: String,
+    pub target_ticket_id: String,
+}
+
+/// A ticket changed status, published by ticket-storage on `ticket_events`
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct TicketEvent {
+    pub ticket_id: String,
+    /// `None` when the ticket was just created
+    pub from: Option<TicketStatus>,
+    pub to: TicketStatus,
+    pub changed_at: u64,
+    pub reason: Option<String>,
+}
+
+/// A ticket was given to an agent, for notifications
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct AssignmentEvent {
+    pub ticket_id: String,
+    pub assignment: Assignment,
+}
+
+/// Raised by a routing rule, for alerting-dlq to notify the team
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct TicketAlert {
+    pub ticket_id: String,
+    pub rule: String,
+    pub message: String,
+    pub title: String,
+    pub contact: String,
+    pub priority: Priority,
+    pub raised_at: u64,
+}
+
+/// Ask ticket-storage to move a ticket to another status
+#[derive(Serialize, Debug, Deserialize)]
+pub struct TransitionRequest {
+    pub ticket_id: String,
+    pub to: TicketStatus,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub enum SuggestionStatus {
+    /// Waiting for an agent
+    Pending,
+    /// Approved as drafted
+    Approved,
+    /// Approved after the agent changed the body
+    Edited,
+    Rejected,
+}
+
+/// A reply drafted by the LLM, to be reviewed by an agent before being sent
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct SuggestedReply {
+    pub id: String,
+    pub ticket_id: String,
+    pub contact: String,
+    pub origin: Origin,
+    pub body: String,
+    /// Knowledge-base documents the draft was grounded on
+    pub sources: Vec<String>,
+    pub status: SuggestionStatus,
+    pub created_at: u64,
+    pub llm_usage: Option<LlmUsage>,
+}
+
+/// A message sent to a customer on the channel they wrote from, consumed by the sender of
+/// `origin` on `outbound_messages`
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct OutboundMessage {
+    pub id: String,
+    pub ticket_id: String,
+    /// Email address or phone number of the customer
+    pub contact: String,
+    pub origin: Origin,
+    pub body: String,
+    /// Subject of the conversation, for emails
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// Id of the customer message replied to: Message-ID of the email, or WhatsApp message id
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
+    /// Suggested reply the message was approved from
+    #[serde(default)]
+    pub suggestion_id: Option<String>,
+    pub created_at: u64,
+}
+
+impl OutboundMessage {
+    /// Reply to `message` on ticket `ticket_id`
+    pub fn reply(ticket_id: &str, message: &CommonMessage, body: String) -> Self {
+        OutboundMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            ticket_id: ticket_id.to_string(),
+            contact: message.contact.clone(),
+            origin: message.origin,
+            body,
+            subject: message.subject.clone(),
+            in_reply_to: message.message_id.clone(),
+            suggestion_id: None,
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn outbound_ref(&self) -> OutboundRef {
+        OutboundRef {
+            ticket_id: self.ticket_id.clone(),
+            outbound_id: self.id.clone(),
+        }
+    }
+
+    /// Status of the message reported by its sender, now
+    pub fn status(&self, state: DeliveryState, reason: Option<String>) -> DeliveryStatus {
+        DeliveryStatus {
+            outbound_id: self.id.clone(),
+            ticket_id: self.ticket_id.clone(),
+            contact: self.contact.clone(),
+            origin: self.origin,
+            state,
+            at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            reason,
+        }
+    }
+}
+
+/// Identifies an outbound message in the channels that echo an opaque reference back with its
+/// statuses, e.g. WhatsApp's `biz_opaque_callback_data`
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct OutboundRef {
+    pub ticket_id: String,
+    pub outbound_id: String,
+}
+
+/// Progress in the delivery of an outbound message, published on `delivery_statuses` by the
+/// senders, whatsapp-gateway (status webhooks) and email-trt (DSNs and read receipts)
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct DeliveryStatus {
+    pub outbound_id: String,
+    pub ticket_id: String,
+    /// Recipient of the message
+    pub contact: String,
+    pub origin: Origin,
+    pub state: DeliveryState,
+    pub at: u64,
+    /// Error or diagnostic reported by the channel
+    pub reason: Option<String>,
 }
 
 // FIXME: Move to a separate project
 
-impl From<WhatsAppMessage> for CommonMessage {
-    fn from(wa_msg: WhatsAppMessage) -> Self {
-        CommonMessage {
-            contact: wa_msg.sender,
+impl TryFrom<WhatsAppMessage> for CommonMessage {
+    type Error = ValidationError;
+
+    fn try_from(wa_msg: WhatsAppMessage) -> Result<Self, ValidationError> {
+        validation::body(&wa_msg.content, wa_msg.attachments.len())?;
+        Ok(CommonMessage {
+            // The Cloud API gives numbers without their `+`
+            contact: validation::phone(&wa_msg.sender)?,
             origin: Origin::WhatsApp,
             body: wa_msg.content,
-            timestamp: wa_msg.timestamp,
+            timestamp: validation::timestamp(wa_msg.timestamp)?,
             ticket_hint: None,
-        }
+            ticket_id: None,
+            subject: None,
+            message_id: wa_msg.message_id,
+            language: None,
+            translated_body: None,
+            raw_object_key: None,
+            attachments: wa_msg.attachments,
+            customer_id: None,
+            headers: Default::default(),
+        })
     }
 }
 
-impl From<EmailMessage> for CommonMessage {
-    fn from(email_msg: EmailMessage) -> Self {
-        let ticket_hint = email_msg
-            .to
-            .split('+')
-            .nth(1)
-            .and_then(|s| s.split('@').next())
-            .map(|s| s.to_string());
-        CommonMessage {
-            contact: email_msg.from,
+impl TryFrom<TelegramMessage> for CommonMessage {
+    type Error = ValidationError;
+
+    fn try_from(tg_msg: TelegramMessage) -> Result<Self, ValidationError> {
+        validation::body(&tg_msg.text, tg_msg.attachments.len())?;
+        Ok(CommonMessage {
+            // Telegram users have no phone number or address to share, only their chat
+            contact: format!("telegram:{}", tg_msg.chat_id),
+            origin: Origin::Telegram,
+            body: tg_msg.text,
+            timestamp: validation::timestamp(tg_msg.date)?,
+            ticket_hint: None,
+            ticket_id: None,
+            subject: None,
+            message_id: Some(tg_msg.message_id.to_string()),
+            language: None,
+            translated_body: None,
+            raw_object_key: None,
+            attachments: tg_msg.attachments,
+            customer_id: None,
+            headers: Default::default(),
+        })
+    }
+}
+
+impl TryFrom<SignalMessage> for CommonMessage {
+    type Error = ValidationError;
+
+    fn try_from(signal_msg: SignalMessage) -> Result<Self, ValidationError> {
+        validation::body(&signal_msg.message, signal_msg.attachments.len())?;
+        // Senders hiding their number are only known by their UUID
+        let contact = match uuid::Uuid::parse_str(&signal_msg.source) {
+            Ok(uuid) => format!("signal:{}", uuid),
+            Err(_) => validation::phone(&signal_msg.source)?,
+        };
+        Ok(CommonMessage {
+            contact,
+            origin: Origin::Signal,
+            body: signal_msg.message,
+            timestamp: validation::timestamp(signal_msg.timestamp / 1000)?,
+            ticket_hint: None,
+            ticket_id: None,
+            subject: None,
+            message_id: Some(signal_msg.timestamp.to_string()),
+            language: None,
+            translated_body: None,
+            raw_object_key: None,
+            attachments: signal_msg.attachments,
+            customer_id: None,
+            headers: Default::default(),
+        })
+    }
+}
+
+impl TryFrom<WebChatMessage> for CommonMessage {
+    type Error = ValidationError;
+
+    fn try_from(web_msg: WebChatMessage) -> Result<Self, ValidationError> {
+        validation::body(&web_msg.message, 0)?;
+        validation::subject(web_msg.subject.as_deref())?;
+        let contact = match web_msg.email {
+            Some(email) if !email.trim().is_empty() => validation::email_address(&email)?,
+            _ if web_msg.session_id.trim().is_empty() => {
+                return Err(ValidationError::InvalidContact(web_msg.session_id))
+            }
+            _ => format!("webchat:{}", web_msg.session_id.trim()),
+        };
+        Ok(CommonMessage {
+            contact,
+            origin: Origin::WebChat,
+            body: web_msg.message,
+            timestamp: validation::timestamp(web_msg.timestamp)?,
+            ticket_hint: None,
+            ticket_id: None,
+            subject: web_msg.subject,
+            message_id: None,
+            language: None,
+            translated_body: None,
+            raw_object_key: None,
+            attachments: vec![],
+            customer_id: None,
+            headers: Default::default(),
+        })
+    }
+}
+
+/// Ticket id of a `support+<ticket id>@` address. Other tags are not ticket ids.
+fn plus_tag(to: &str) -> Option<String> {
+    let address = to.trim();
+    if !validation::is_valid_address(address) {
+        return None;
+    }
+    let (local, _) = address.rsplit_once('@')?;
+    let (_, tag) = local.split_once('+')?;
+    let is_ticket_id = !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    is_ticket_id.then(|| tag.to_string())
+}
+
+impl TryFrom<EmailMessage> for CommonMessage {
+    type Error = ValidationError;
+
+    fn try_from(email_msg: EmailMessage) -> Result<Self, ValidationError> {
+        validation::body(&email_msg.content, email_msg.attachments.len())?;
+        validation::subject(email_msg.subject.as_deref())?;
+        Ok(CommonMessage {
+            contact: validation::email_address(&email_msg.from)?,
             origin: Origin::Email,
             body: email_msg.content,
-            timestamp: email_msg.timestamp,
-            ticket_hint: ticket_hint,
-        }
+            timestamp: validation::timestamp(email_msg.timestamp)?,
+            ticket_hint: plus_tag(&email_msg.to),
+            ticket_id: None,
+            subject: email_msg.subject,
+            message_id: email_msg.message_id,
+            language: None,
+            translated_body: None,
+            raw_object_key: None,
+            attachments: email_msg.attachments,
+            customer_id: None,
+            headers: email_msg.headers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_ticket_hint() {
+        let raw = |recipients: &[&str]| {
+            RawEmailMessage::new(
+                b"To: support@company.com\r\n\r\nHello",
+                recipients.iter().map(|r| r.to_string()).collect(),
+                100,
+            )
+        };
+        assert_eq!(
+            raw(&["team@company.com", "support+42@company.com"])
+                .ticket_hint()
+                .as_deref(),
+            Some("42")
+        );
+        assert_eq!(raw(&["support+a.b@company.com"]).ticket_hint(), None);
+        assert_eq!(raw(&[]).ticket_hint(), None);
+        // Published before the envelope was kept
+        let old: RawEmailMessage =
+            serde_json::from_str(r#"{"raw": "SGVsbG8=", "received_at": 100}"#).unwrap();
+        assert!(old.recipients.is_empty());
     }
 }
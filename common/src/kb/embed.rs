@@ -0,0 +1,65 @@
+use anyhow::Result;
+
+/// Turns text into a vector. Vectors of a given embedder are L2-normalized, so that their dot
+/// product is their cosine similarity.
+#[allow(async_fn_in_trait)]
+pub trait Embedder: Send + Sync {
+    /// Identifies the embedder, an index is only valid for the embedder that built it
+    fn name(&self) -> String;
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>>;
+}
+
+/// Local stand-in for a real embedding model: a bag of words hashed into a fixed number of
+/// dimensions. Good enough to match documents sharing vocabulary, with no model to download.
+pub struct HashEmbedder {
+    dimensions: usize,
+}
+
+impl HashEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        HashEmbedder { dimensions }
+    }
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self {
+        HashEmbedder::new(512)
+    }
+}
+
+/// FNV-1a, stable across builds unlike `DefaultHasher`, which matters for persisted indexes
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Embedder for HashEmbedder {
+    fn name(&self) -> String {
+        format!("hash-{}", self.dimensions)
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let mut vector = vec![0f32; self.dimensions];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.chars().count() > 2)
+        {
+            let hash = fnv1a(word.to_lowercase().as_bytes());
+            // One bit of the hash picks the sign, so that collisions tend to cancel out
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dimensions as u64) as usize] += sign;
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+        Ok(vector)
+    }
+}
+
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
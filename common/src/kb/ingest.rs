@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Chunks are cut on paragraph boundaries, around this many characters
+pub const CHUNK_SIZE: usize = 800;
+
+pub struct Document {
+    /// Path relative to the knowledge-base folder, used to cite the article
+    pub name: String,
+    pub text: String,
+}
+
+/// Read every markdown, text and HTML file of the folder, recursively
+pub fn read_documents(dir: impl AsRef<Path>) -> Result<Vec<Document>> {
+    let mut documents = Vec::new();
+    read_dir(dir.as_ref(), dir.as_ref(), &mut documents)?;
+    documents.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(documents)
+}
+
+fn read_dir(root: &Path, dir: &Path, documents: &mut Vec<Document>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_dir(root, &path, documents)?;
+            continue;
+        }
+        let text = match path.extension().and_then(|e| e.to_str()) {
+            Some("md") | Some("markdown") | Some("txt") => fs::read_to_string(&path)?,
+            Some("html") | Some("htm") => html_to_text(&fs::read_to_string(&path)?),
+            _ => continue,
+        };
+        documents.push(Document {
+            name: path.strip_prefix(root)?.to_string_lossy().to_string(),
+            text,
+        });
+    }
+    Ok(())
+}
+
+/// Crude HTML to text: drops scripts, styles and tags, keeps block boundaries as blank lines
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        let Some(end) = tag.find('>') else {
+            rest = "";
+            break;
+        };
+        let name = tag[1..end]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        rest = &tag[end + 1..];
+
+        if (name == "script" || name == "style") && !tag.starts_with("</") {
+            // Skip the element content entirely
+            let closing = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&closing) {
+                Some(pos) => &rest[pos..],
+                None => "",
+            };
+        } else if matches!(
+            name.as_str(),
+            "p" | "div" | "br" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "section"
+        ) {
+            text.push_str("\n\n");
+        }
+    }
+    text.push_str(rest);
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    // Collapse the whitespace left by the markup, keeping paragraph breaks
+    text.split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Split a document into chunks of about `CHUNK_SIZE` characters, on paragraph boundaries
+pub fn chunk(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty() && current.len() + paragraph.len() > CHUNK_SIZE {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
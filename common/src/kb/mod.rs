@@ -0,0 +1,236 @@
+//! Knowledge-base retrieval: documents are chunked, embedded and kept in an on-disk index,
+//! then the chunks closest to a query are given to the LLM as grounding.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub mod embed;
+pub mod ingest;
+
+use embed::{cosine, Embedder, HashEmbedder};
+
+pub const DEFAULT_KB_DIR: &str = "./kb";
+pub const DEFAULT_KB_INDEX_PATH: &str = "./data/kb_index.json";
+pub const DEFAULT_TOP_K: usize = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KbChunk {
+    /// Name of the article the chunk comes from
+    pub article: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KbIndex {
+    pub embedder: String,
+    pub chunks: Vec<KbChunk>,
+}
+
+/// A chunk retrieved for a query
+#[derive(Serialize, Debug, Clone)]
+pub struct KbSnippet {
+    pub article: String,
+    pub text: String,
+    pub score: f32,
+}
+
+impl KbIndex {
+    /// Read, chunk and embed every document of the folder
+    pub async fn build(dir: impl AsRef<Path>, embedder: &impl Embedder) -> Result<Self> {
+        let mut chunks = Vec::new();
+        for document in ingest::read_documents(dir)? {
+            for text in ingest::chunk(&document.text) {
+                chunks.push(KbChunk {
+                    article: document.name.clone(),
+                    embedding: embedder.embed(&text).await?,
+                    text,
+                });
+            }
+        }
+        Ok(KbIndex {
+            embedder: embedder.name(),
+            chunks,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Reuse the index on disk, unless it was built by another embedder or a document changed since
+    pub async fn load_or_build(
+        dir: impl AsRef<Path>,
+        index_path: impl AsRef<Path>,
+        embedder: &impl Embedder,
+    ) -> Result<Self> {
+        let index_path = index_path.as_ref();
+        if let Some(indexed_at) = modified(index_path) {
+            if newest_document(dir.as_ref())? <= Some(indexed_at) {
+                let index = Self::load(index_path)?;
+                if index.embedder == embedder.name() {
+                    return Ok(index);
+                }
+            }
+        }
+
+        println!("Indexing knowledge base {}...", dir.as_ref().display());
+        let index = Self::build(&dir, embedder).await?;
+        index.save(index_path)?;
+        println!(
+            "Indexed {} chunks into {}",
+            index.chunks.len(),
+            index_path.display()
+        );
+        Ok(index)
+    }
+
+    /// Load the index selected through `KB_DIR` and `KB_INDEX_PATH`
+    pub async fn from_env(embedder: &impl Embedder) -> Result<Self> {
+        let (dir, index_path) = paths_from_env();
+        Self::load_or_build(&dir, &index_path, embedder)
+            .await
+            .with_context(|| format!("Failed to load knowledge base {}", dir))
+    }
+
+    /// The `k` chunks closest to the query. Chunks with no similarity at all are left out.
+    pub async fn search(
+        &self,
+        embedder: &impl Embedder,
+        query: &str,
+        k: usize,
+    ) -> Result<Vec<KbSnippet>> {
+        let query = embedder.embed(query).await?;
+        let mut snippets: Vec<KbSnippet> = self
+            .chunks
+            .iter()
+            .map(|chunk| KbSnippet {
+                article: chunk.article.clone(),
+                text: chunk.text.clone(),
+                score: cosine(&query, &chunk.embedding),
+            })
+            .filter(|snippet| snippet.score > 0.0)
+            .collect();
+        snippets.sort_by(|a, b| b.score.total_cmp(&a.score));
+        snippets.truncate(k);
+        Ok(snippets)
+    }
+}
+
+/// Knowledge-base folder and index file, from `KB_DIR` and `KB_INDEX_PATH`
+pub fn paths_from_env() -> (String, String) {
+    (
+        env::var("KB_DIR").unwrap_or_else(|_| DEFAULT_KB_DIR.to_string()),
+        env::var("KB_INDEX_PATH").unwrap_or_else(|_| DEFAULT_KB_INDEX_PATH.to_string()),
+    )
+}
+
+/// An index with the embedder that built it, ready to answer queries
+pub struct Retriever<E: Embedder = HashEmbedder> {
+    pub index: KbIndex,
+    pub embedder: E,
+    pub top_k: usize,
+}
+
+impl Retriever<HashEmbedder> {
+    /// Retriever over the local knowledge base, `KB_TOP_K` snippets per query
+    pub async fn from_env() -> Result<Self> {
+        let embedder = HashEmbedder::default();
+        let top_k = env::var("KB_TOP_K")
+            .ok()
+            .and_then(|k| k.parse().ok())
+            .unwrap_or(DEFAULT_TOP_K);
+        Ok(Retriever {
+            index: KbIndex::from_env(&embedder).await?,
+            embedder,
+            top_k,
+        })
+    }
+}
+
+impl<E: Embedder> Retriever<E> {
+    pub async fn retrieve(&self, query: &str) -> Result<Vec<KbSnippet>> {
+        self.index.search(&self.embedder, query, self.top_k).await
+    }
+}
+
+/// Distinct articles of the snippets, in order of relevance
+pub fn cited_articles(snippets: &[KbSnippet]) -> Vec<String> {
+    let mut articles: Vec<String> = Vec::new();
+    for snippet in snippets {
+        if !articles.contains(&snippet.article) {
+            articles.push(snippet.article.clone());
+        }
+    }
+    articles
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn newest_document(dir: &Path) -> Result<Option<SystemTime>> {
+    let mut newest = modified(dir);
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let candidate = if path.is_dir() {
+            newest_document(&path)?
+        } else {
+            modified(&path)
+        };
+        newest = newest.max(candidate);
+    }
+    Ok(newest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><style>p { color: red; }</style></head>\
+                    <body><h1>Refunds</h1><p>Issued within&nbsp;10 days.</p><script>alert(1)</script></body></html>";
+        assert_eq!(
+            ingest::html_to_text(html),
+            "Refunds\n\nIssued within 10 days."
+        );
+    }
+
+    #[test]
+    fn test_chunk_keeps_paragraphs_together() {
+        // Two paragraphs fit in a chunk, not three
+        let paragraph = "word ".repeat(60);
+        let text = [paragraph.as_str(); 4].join("\n\n");
+        let chunks = ingest::chunk(&text);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|c| c.len() <= ingest::CHUNK_SIZE));
+    }
+
+    #[tokio::test]
+    async fn test_search_repo_kb() -> Result<()> {
+        let embedder = HashEmbedder::default();
+        let kb_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../kb");
+        let index = KbIndex::build(kb_dir, &embedder).await?;
+
+        let snippets = index
+            .search(&embedder, "I was charged twice, please refund me", 2)
+            .await?;
+        assert_eq!(snippets[0].article, "refunds.md");
+        assert!(snippets.len() <= 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use std::env;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use openrouter_rs::{
+    api::chat::{ChatCompletionRequest, Message},
+    types::{ResponseFormat, Role},
+    OpenRouterClient,
+};
+
+use crate::dto::LlmUsage;
+
+pub const DEFAULT_MODEL: &str = "openrouter/free";
+
+/// Build a client from `OPENROUTER_API_KEY` and the optional `OPENROUTER_BASE_URL`
+/// (any OpenAI-compatible server, such as a local stub)
+pub fn client_from_env() -> Result<OpenRouterClient> {
+    let api_key = env::var("OPENROUTER_API_KEY").context("OPENROUTER_API_KEY must be set")?;
+    let mut builder = OpenRouterClient::builder();
+    builder.api_key(api_key);
+    if let Ok(base_url) = env::var("OPENROUTER_BASE_URL") {
+        builder.base_url(base_url);
+    }
+    Ok(builder.build()?)
+}
+
+pub fn model_from_env() -> String {
+    env::var("LLM_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string())
+}
+
+pub struct Completion {
+    pub content: String,
+    pub usage: LlmUsage,
+}
+
+/// Send a single user prompt and return the answer along with its accounting
+pub async fn complete(
+    client: &OpenRouterClient,
+    model: &str,
+    prompt: String,
+    format: Option<ResponseFormat>,
+) -> Result<Completion> {
+    let mut builder = ChatCompletionRequest::builder();
+    builder
+        .model(model)
+        .messages(vec![Message::new(Role::User, prompt)]);
+    if let Some(format) = format {
+        builder.response_format(format);
+    }
+    let request = builder.build()?;
+
+    let started = Instant::now();
+    let response = client.send_chat_completion(&request).await?;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let content = response
+        .choices
+        .first()
+        .and_then(|choice| choice.content())
+        .ok_or_else(|| anyhow::anyhow!("LLM response has no content"))?
+        .to_string();
+    let (prompt_tokens, completion_tokens) = response
+        .usage
+        .as_ref()
+        .map(|u| (u.prompt_tokens, u.completion_tokens))
+        .unwrap_or_default();
+
+    Ok(Completion {
+        content,
+        usage: record_usage(
+            &response.model,
+            prompt_tokens,
+            completion_tokens,
+            latency_ms,
+        ),
+    })
+}
+
+/// Price in USD per million tokens, configured through the environment since it depends on the model in use
+fn price_per_mtok(var: &str) -> f64 {
+    env::var(var)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0.0)
+}
+
+pub fn estimate_cost(prompt_tokens: u32, completion_tokens: u32) -> f64 {
+    let prompt_price = price_per_mtok("LLM_PROMPT_PRICE_PER_MTOK");
+    let completion_price = price_per_mtok("LLM_COMPLETION_PRICE_PER_MTOK");
+    (prompt_tokens as f64 * prompt_price + completion_tokens as f64 * completion_price)
+        / 1_000_000.0
+}
+
+pub fn record_usage(
+    model: &str,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    latency_ms: u64,
+) -> LlmUsage {
+    let usage = LlmUsage {
+        model: model.to_string(),
+        prompt_tokens,
+        completion_tokens,
+        latency_ms,
+        estimated_cost_usd: estimate_cost(prompt_tokens, completion_tokens),
+        cached: false,
+    };
+    println!(
+        "LLM usage: model={} prompt_tokens={} completion_tokens={} latency={}ms cost=${:.6}",
+        usage.model,
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        usage.latency_ms,
+        usage.estimated_cost_usd
+    );
+    usage
+}
+
+pub fn cached_usage(model: &str, latency_ms: u64) -> LlmUsage {
+    LlmUsage {
+        model: model.to_string(),
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        latency_ms,
+        estimated_cost_usd: 0.0,
+        cached: true,
+    }
+}
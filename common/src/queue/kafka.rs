@@ -14,19 +14,28 @@ use tokio::sync::Mutex;
 use uuid::Uuid;
 
 use crate::{
-    queue::{get_dlq_name, Message, QueueManager},
-    KAFKA_BOOTSTRAP_SERVERS, MAX_RETRIES,
+    queue::{get_dlq_name, DeadLetter, Message, QueueManager},
+    ticket, KAFKA_BOOTSTRAP_SERVERS, MAX_RETRIES,
 };
 
 pub struct KafkaQueueManager {
     producer: FutureProducer,
     consumer: StreamConsumer,
     admin_client: AdminClient<rdkafka::client::DefaultClientContext>,
-    seen_messages: Mutex<HashMap<String, usize>>,
+    /// Times each message was read, with the error it last failed with
+    seen_messages: Mutex<HashMap<String, (usize, Option<String>)>>,
 }
 
+const DEFAULT_GROUP_ID: &str = "lmgtfy-consumer-group";
+
 impl KafkaQueueManager {
     pub async fn new() -> Result<Self> {
+        Self::with_group_id(DEFAULT_GROUP_ID).await
+    }
+
+    /// Consumers sharing a group split the messages of a topic between them.
+    /// A service reading a topic that another service also reads needs its own group.
+    pub async fn with_group_id(group_id: &str) -> Result<Self> {
         let producer: FutureProducer = ClientConfig::new()
             .set("bootstrap.servers", KAFKA_BOOTSTRAP_SERVERS)
             .set("message.timeout.ms", "5000")
@@ -34,7 +43,7 @@ impl KafkaQueueManager {
 
         let consumer: StreamConsumer = ClientConfig::new()
             .set("bootstrap.servers", KAFKA_BOOTSTRAP_SERVERS)
-            .set("group.id", "lmgtfy-consumer-group")
+            .set("group.id", group_id)
             .set("enable.partition.eof", "false")
             .set("session.timeout.ms", "6000")
             .set("enable.auto.commit", "false")
@@ -133,38 +142,55 @@ impl QueueManager for KafkaQueueManager {
                     // Use offset as message ID for Kafka
                     let msg_id = kafka_msg.offset();
 
-                    let mut seen_messages = self.seen_messages.lock().await;
-                    let seen = seen_messages
-                        .entry(msg_id.to_string())
-                        .and_modify(|count| *count += 1)
-                        .or_insert(1);
+                    let key = format!("{}:{}", kafka_msg.topic(), msg_id);
+                    let (seen, last_error) = {
+                        let mut seen_messages = self.seen_messages.lock().await;
+                        let (seen, last_error) = seen_messages.entry(key.clone()).or_default();
+                        *seen += 1;
+                        (*seen, last_error.clone())
+                    };
 
-                    if *seen > MAX_RETRIES as usize {
+                    if seen > MAX_RETRIES as usize {
                         // Move message to DLQ
                         println!(
                             "Message with ID {} has been seen {} times, moving to DLQ",
                             msg_id, seen
                         );
-                        let dlq_name = get_dlq_name(queue_name);
-                        self.send(&dlq_name, &message).await?;
+                        let dead_letter = DeadLetter {
+                            queue: kafka_msg.topic().to_string(),
+                            reason: last_error.unwrap_or_else(|| {
+                                format!("Not processed after {} attempts", seen - 1)
+                            }),
+                            rejected_at: ticket::now(),
+                            message,
+                        };
+                        self.send(&get_dlq_name(kafka_msg.topic()), &dead_letter)
+                            .await?;
 
                         // Commit the offset to skip the message
                         self.consumer
                             .commit_message(&kafka_msg, rdkafka::consumer::CommitMode::Async)?;
-
-                        // Reset seen count for this queue
-                        seen_messages.remove(queue_name);
-
+                        self.seen_messages.lock().await.remove(&key);
                         continue;
                     }
 
                     let msg = Message { msg_id, message };
-                    if let Err(e) = process(msg).await {
-                        eprintln!("Error processing message: {}", e);
-                    } else {
-                        // If processed successfully, commit the offset to delete the message
-                        self.consumer
-                            .commit_message(&kafka_msg, rdkafka::consumer::CommitMode::Async)?;
+                    match process(msg).await {
+                        Err(e) => {
+                            eprintln!("Error processing message: {}", e);
+                            // Kept for the DLQ, once the retries are exhausted
+                            if let Some((_, last_error)) =
+                                self.seen_messages.lock().await.get_mut(&key)
+                            {
+                                *last_error = Some(format!("{:#}", e));
+                            }
+                        }
+                        Ok(()) => {
+                            // If processed successfully, commit the offset to delete the message
+                            self.consumer
+                                .commit_message(&kafka_msg, rdkafka::consumer::CommitMode::Async)?;
+                            self.seen_messages.lock().await.remove(&key);
+                        }
                     }
                 }
                 Err(e) => eprintln!("Kafka error: {}", e),
@@ -11,7 +11,18 @@ pub struct Message<T> {
     pub message: T,
 }
 
-fn get_dlq_name(queue_name: &str) -> String {
+/// A message moved to the DLQ, because it can never be processed or failed every retry
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeadLetter<T> {
+    /// Queue the message was read from
+    pub queue: String,
+    /// Why it was rejected, or the error of its last attempt
+    pub reason: String,
+    pub rejected_at: u64,
+    pub message: T,
+}
+
+pub fn get_dlq_name(queue_name: &str) -> String {
     format!("{}_dlq", queue_name)
 }
 
@@ -5,8 +5,8 @@ use serde::{Deserialize, Serialize};
 use tokio::time::sleep;
 
 use crate::{
-    queue::{get_dlq_name, Message, QueueManager},
-    MAX_RETRIES, PG_URL, VISIBILITY_TIMEOUT_SECONDS,
+    queue::{get_dlq_name, DeadLetter, Message, QueueManager},
+    ticket, MAX_RETRIES, PG_URL, VISIBILITY_TIMEOUT_SECONDS,
 };
 
 pub struct PgMqQueueManager {
@@ -89,8 +89,14 @@ impl PgMqQueueManager {
                         "Message {} has been read {} times, moving it to DLQ",
                         m.msg_id, m.read_ct
                     );
+                    let dead_letter = DeadLetter {
+                        queue: queue_name.to_string(),
+                        reason: format!("Not processed after {} reads", m.read_ct - 1),
+                        rejected_at: ticket::now(),
+                        message: m.message,
+                    };
                     let dlq_name = get_dlq_name(queue_name);
-                    self.inner.send(&dlq_name, &m.message).await?;
+                    self.inner.send(&dlq_name, &dead_letter).await?;
                     self.inner.delete(queue_name, m.msg_id).await?;
                     return Ok(None);
                 }
@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::Result;
+
+use crate::storage::ObjectStore;
+
+/// Objects kept in memory, for tests and local runs without MinIO
+#[derive(Default)]
+pub struct MemoryObjectStore {
+    objects: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl ObjectStore for MemoryObjectStore {
+    async fn put(&self, key: &str, _content_type: &str, body: Vec<u8>) -> Result<()> {
+        self.objects.lock().unwrap().insert(key.to_string(), body);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.objects.lock().unwrap().get(key).cloned())
+    }
+}
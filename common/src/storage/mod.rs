@@ -0,0 +1,254 @@
+//! Object storage for raw inbound payloads and attachments, on any S3-compatible service (MinIO in
+//! compose). Objects are grouped by ticket: `tickets/<ticket id>/raw/...` and
+//! `tickets/<ticket id>/attachments/...`.
+
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::dto::{Attachment, CommonMessage};
+
+pub mod memory;
+pub mod s3;
+#[cfg(test)]
+mod stub;
+
+/// Hex digits of the SHA-256 of the content in the keys
+const KEY_HASH_LEN: usize = 16;
+
+#[allow(async_fn_in_trait)]
+pub trait ObjectStore: Send + Sync {
+    async fn put(&self, key: &str, content_type: &str, body: Vec<u8>) -> Result<()>;
+
+    /// `None` when there is no object under the key
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// Key of a raw inbound payload. Derived from its content, archiving the same payload again on a
+/// redelivery overwrites the same object.
+pub fn raw_message_key(ticket_id: &str, timestamp: u64, raw: &[u8], extension: &str) -> String {
+    format!(
+        "tickets/{}/raw/{}-{}.{}",
+        sanitize(ticket_id),
+        timestamp,
+        &checksum(raw)[..KEY_HASH_LEN],
+        extension
+    )
+}
+
+/// Key of an attachment, derived from its content as raw payloads are
+pub fn attachment_key(ticket_id: &str, filename: &str, content: &[u8]) -> String {
+    format!(
+        "tickets/{}/attachments/{}-{}",
+        sanitize(ticket_id),
+        &checksum(content)[..KEY_HASH_LEN],
+        sanitize(filename)
+    )
+}
+
+/// Ids and file names come from customers, keep them to a single well-behaved path segment
+fn sanitize(segment: &str) -> String {
+    segment
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+/// Hex-encoded SHA-256, as found in `Attachment::checksum`
+pub fn checksum(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// Store the content of an attachment under the ticket and describe it
+pub async fn store_attachment(
+    store: &impl ObjectStore,
+    ticket_id: &str,
+    filename: &str,
+    content_type: &str,
+    content: Vec<u8>,
+) -> Result<Attachment> {
+    let key = attachment_key(ticket_id, filename, &content);
+    let attachment = Attachment {
+        filename: filename.to_string(),
+        content_type: content_type.to_string(),
+        size: content.len() as u64,
+        checksum: checksum(&content),
+        storage_key: Some(key.clone()),
+        source_id: None,
+    };
+    store.put(&key, content_type, content).await?;
+    Ok(attachment)
+}
+
+/// Download the attachments only known by their id on the channel, e.g. WhatsApp media, and store
+/// them under the ticket. `fetch` returns the content of an id.
+pub async fn store_channel_attachments(
+    store: &impl ObjectStore,
+    message: &mut CommonMessage,
+    fetch: &impl AsyncFn(&str) -> Result<Vec<u8>>,
+) -> Result<()> {
+    let ticket_id = message.assign_ticket_id();
+    for attachment in &mut message.attachments {
+        let (Some(source_id), None) = (&attachment.source_id, &attachment.storage_key) else {
+            continue;
+        };
+        let content = fetch(source_id).await?;
+        let stored = store_attachment(
+            store,
+            &ticket_id,
+            &attachment.filename,
+            &attachment.content_type,
+            content,
+        )
+        .await?;
+        *attachment = Attachment {
+            source_id: attachment.source_id.take(),
+            ..stored
+        };
+    }
+    Ok(())
+}
+
+/// Store the payload a message was normalized from, and reference it from the message
+pub async fn archive_raw(
+    store: &impl ObjectStore,
+    raw: &impl Serialize,
+    message: &mut CommonMessage,
+) -> Result<String> {
+    archive_raw_bytes(
+        store,
+        "application/json",
+        "json",
+        serde_json::to_vec(raw)?,
+        message,
+    )
+    .await
+}
+
+/// Same as `archive_raw` for payloads that are not JSON, e.g. `message/rfc822` emails
+pub async fn archive_raw_bytes(
+    store: &impl ObjectStore,
+    content_type: &str,
+    extension: &str,
+    raw: Vec<u8>,
+    message: &mut CommonMessage,
+) -> Result<String> {
+    let ticket_id = message.assign_ticket_id();
+    let key = raw_message_key(&ticket_id, message.timestamp, &raw, extension);
+    store.put(&key, content_type, raw).await?;
+    message.raw_object_key = Some(key.clone());
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::WhatsAppMessage;
+
+    #[test]
+    fn test_keys_stay_under_the_ticket() {
+        let key = attachment_key("../../etc", "../passwd", b"root");
+        assert!(key.starts_with("tickets/_.._etc/attachments/"));
+        assert!(key.ends_with("-_passwd"));
+    }
+
+    #[tokio::test]
+    async fn test_store_attachment() -> Result<()> {
+        let store = memory::MemoryObjectStore::default();
+        let attachment = store_attachment(
+            &store,
+            "42",
+            "invoice.pdf",
+            "application/pdf",
+            b"%PDF-1.4".to_vec(),
+        )
+        .await?;
+
+        assert_eq!(attachment.size, 8);
+        assert_eq!(attachment.checksum, checksum(b"%PDF-1.4"));
+        let key = attachment.storage_key.unwrap();
+        assert!(key.starts_with("tickets/42/attachments/"));
+        assert_eq!(store.get(&key).await?.unwrap(), b"%PDF-1.4");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_channel_attachments() -> Result<()> {
+        let store = memory::MemoryObjectStore::default();
+        let described = |source_id: Option<&str>| Attachment {
+            filename: "voice.ogg".to_string(),
+            content_type: "audio/ogg".to_string(),
+            size: 0,
+            checksum: String::new(),
+            storage_key: None,
+            source_id: source_id.map(str::to_string),
+        };
+        let mut message = CommonMessage::try_from(WhatsAppMessage {
+            sender: "+33612345678".to_string(),
+            content: String::new(),
+            timestamp: 1772000000,
+            attachments: vec![described(Some("1234")), described(None)],
+            message_id: None,
+        })?;
+
+        store_channel_attachments(&store, &mut message, &async |source_id: &str| {
+            Ok(format!("OggS {}", source_id).into_bytes())
+        })
+        .await?;
+
+        let fetched = &message.attachments[0];
+        assert_eq!(fetched.size, 9);
+        assert_eq!(fetched.checksum, checksum(b"OggS 1234"));
+        assert_eq!(fetched.source_id.as_deref(), Some("1234"));
+        let key = fetched.storage_key.clone().unwrap();
+        assert!(key.starts_with(&format!(
+            "tickets/{}/attachments/",
+            message.ticket_id.clone().unwrap()
+        )));
+        assert_eq!(store.get(&key).await?.unwrap(), b"OggS 1234");
+        // Nothing to download it with
+        assert_eq!(message.attachments[1].storage_key, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_archive_raw_against_s3_stub() -> Result<()> {
+        let endpoint = stub::spawn().await?;
+        let store = s3::S3ObjectStore::connect(s3::S3Config {
+            endpoint,
+            access_key: "minioadmin".to_string(),
+            secret_key: "minioadmin".to_string(),
+            bucket: "tickets".to_string(),
+            region: "us-east-1".to_string(),
+        })
+        .await?;
+
+        let raw = WhatsAppMessage {
+            sender: "+33612345678".to_string(),
+            content: "Bonjour".to_string(),
+            timestamp: 1772000000,
+            attachments: vec![],
+            message_id: None,
+        };
+        let mut message = CommonMessage::try_from(raw.clone())?;
+        let key = archive_raw(&store, &raw, &mut message).await?;
+
+        let ticket_id = message.ticket_id.clone().unwrap();
+        assert!(key.starts_with(&format!("tickets/{}/raw/1772000000-", ticket_id)));
+        assert_eq!(message.raw_object_key.as_deref(), Some(key.as_str()));
+        let stored: serde_json::Value = serde_json::from_slice(&store.get(&key).await?.unwrap())?;
+        assert_eq!(stored["content"], "Bonjour");
+        assert!(store.get("tickets/unknown").await?.is_none());
+        // A redelivery overwrites the same object
+        assert_eq!(archive_raw(&store, &raw, &mut message.clone()).await?, key);
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::env;
+
+use anyhow::{Context, Result};
+use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client;
+
+use crate::storage::ObjectStore;
+
+pub struct S3Config {
+    pub endpoint: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub bucket: String,
+    pub region: String,
+}
+
+impl S3Config {
+    /// Read the MinIO settings, `None` unless `USE_MINIO=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        if env::var("USE_MINIO").map_or(true, |v| v != "true") {
+            return Ok(None);
+        }
+        let var = |name: &str| env::var(name).with_context(|| format!("{} must be set", name));
+        Ok(Some(S3Config {
+            endpoint: var("MINIO_ENDPOINT")?,
+            access_key: var("MINIO_ACCESS_KEY")?,
+            secret_key: var("MINIO_SECRET_KEY")?,
+            bucket: var("MINIO_BUCKET")?,
+            region: env::var("MINIO_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+        }))
+    }
+}
+
+pub struct S3ObjectStore {
+    client: Client,
+    bucket: String,
+}
+
+impl S3ObjectStore {
+    /// Connect and create the bucket if it does not exist yet
+    pub async fn connect(config: S3Config) -> Result<Self> {
+        let credentials =
+            Credentials::new(config.access_key, config.secret_key, None, None, "minio");
+        let client = Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .endpoint_url(config.endpoint)
+                .region(Region::new(config.region))
+                .credentials_provider(credentials)
+                // MinIO serves buckets as paths rather than subdomains
+                .force_path_style(true)
+                .build(),
+        );
+
+        if client
+            .head_bucket()
+            .bucket(&config.bucket)
+            .send()
+            .await
+            .is_err()
+        {
+            client
+                .create_bucket()
+                .bucket(&config.bucket)
+                .send()
+                .await
+                .with_context(|| format!("Failed to create bucket {}", config.bucket))?;
+        }
+        Ok(S3ObjectStore {
+            client,
+            bucket: config.bucket,
+        })
+    }
+
+    /// Store configured through the `MINIO_*` variables, `None` when archival is disabled
+    pub async fn from_env() -> Result<Option<Self>> {
+        match S3Config::from_env()? {
+            Some(config) => Ok(Some(Self::connect(config).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ObjectStore for S3ObjectStore {
+    async fn put(&self, key: &str, content_type: &str, body: Vec<u8>) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .with_context(|| format!("Failed to store object {}", key))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let output = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read object {}", key)),
+        };
+        Ok(Some(output.body.collect().await?.into_bytes().to_vec()))
+    }
+}
@@ -0,0 +1,60 @@
+//! Minimal in-process S3 endpoint: path-style bucket creation, object put and get.
+//! Authentication is not checked.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::put;
+use axum::Router;
+
+type Objects = Arc<Mutex<HashMap<String, Bytes>>>;
+
+const NO_SUCH_KEY: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+    <Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message></Error>";
+
+async fn put_object(
+    State(objects): State<Objects>,
+    Path((bucket, key)): Path<(String, String)>,
+    body: Bytes,
+) -> StatusCode {
+    objects
+        .lock()
+        .unwrap()
+        .insert(format!("{}/{}", bucket, key), body);
+    StatusCode::OK
+}
+
+async fn get_object(
+    State(objects): State<Objects>,
+    Path((bucket, key)): Path<(String, String)>,
+) -> impl IntoResponse {
+    match objects.lock().unwrap().get(&format!("{}/{}", bucket, key)) {
+        Some(body) => (StatusCode::OK, body.clone()).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            [("content-type", "application/xml")],
+            NO_SUCH_KEY,
+        )
+            .into_response(),
+    }
+}
+
+/// Serve the stub on an ephemeral port and return its endpoint
+pub async fn spawn() -> anyhow::Result<String> {
+    let app = Router::new()
+        // Bucket operations come as `/<bucket>/`
+        .route(
+            "/{bucket}/",
+            put(|| async { StatusCode::OK }).head(|| async { StatusCode::OK }),
+        )
+        .route("/{bucket}/{*key}", put(put_object).get(get_object))
+        .with_state(Objects::default());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(endpoint)
+}
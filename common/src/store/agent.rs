@@ -0,0 +1,311 @@
+//! Agents and the teams they work in. Teams are the queues tickets are routed to, each with the
+//! strategy picking which of its agents a triaged ticket is given to.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::dto::{AssignmentEvent, TicketEvent};
+use crate::store::{is_conflict, TicketRepository, MAX_ATTEMPTS};
+use crate::ticket::assignment::{self, AssignmentStrategy, Candidate};
+use crate::ticket::{Ticket, TicketStatus};
+
+/// Days and hours an agent works, in their time zone. Shifts may end after midnight.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkingHours {
+    /// ISO weekdays, 1 for Monday to 7 for Sunday
+    pub days: Vec<u8>,
+    pub start_hour: u8,
+    pub end_hour: u8,
+    pub utc_offset_minutes: i32,
+}
+
+impl WorkingHours {
+    pub fn is_working(&self, now: u64) -> bool {
+        let local = now as i64 + self.utc_offset_minutes as i64 * 60;
+        let days = local.div_euclid(86_400);
+        // 1970-01-01 was a Thursday
+        let weekday = ((days + 3).rem_euclid(7) + 1) as u8;
+        let hour = (local.rem_euclid(86_400) / 3600) as u8;
+        let (start, end) = (self.start_hour, self.end_hour);
+        if start <= end {
+            self.days.contains(&weekday) && (start..end).contains(&hour)
+        } else if hour >= start {
+            self.days.contains(&weekday)
+        } else {
+            // Night of a shift started the day before
+            let previous = if weekday == 1 { 7 } else { weekday - 1 };
+            self.days.contains(&previous) && hour < end
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Agent {
+    pub id: String,
+    pub name: String,
+    /// Ids of the teams the agent takes tickets from
+    pub teams: Vec<String>,
+    /// Ticket tags the agent knows best
+    pub skills: Vec<String>,
+    /// Open tickets the agent can have at once
+    pub capacity: usize,
+    /// `None` when the agent can be assigned tickets at any time
+    pub working_hours: Option<WorkingHours>,
+    /// Inactive agents keep their tickets but get no new ones
+    pub active: bool,
+    pub last_assigned_at: Option<u64>,
+}
+
+impl Agent {
+    pub fn is_available(&self, now: u64) -> bool {
+        self.active
+            && self
+                .working_hours
+                .as_ref()
+                .is_none_or(|hours| hours.is_working(now))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Team {
+    pub id: String,
+    pub name: String,
+    pub strategy: AssignmentStrategy,
+}
+
+/// Directory of agents and teams. Implementations only store them, assignment is built on top.
+#[allow(async_fn_in_trait)]
+pub trait AgentDirectory: Send + Sync {
+    async fn save_agent(&self, agent: &Agent) -> Result<()>;
+
+    async fn agent(&self, id: &str) -> Result<Option<Agent>>;
+
+    async fn agents(&self) -> Result<Vec<Agent>>;
+
+    async fn save_team(&self, team: &Team) -> Result<()>;
+
+    async fn team(&self, id: &str) -> Result<Option<Team>>;
+
+    async fn teams(&self) -> Result<Vec<Team>>;
+
+    /// Tickets assigned to the agent that are not resolved nor closed
+    async fn open_tickets_of(&self, agent_id: &str) -> Result<usize>;
+
+    /// Save a ticket just given to the agent and when the agent was last assigned, at once.
+    /// Saves nothing and returns false when the agent reached their capacity meanwhile.
+    async fn save_assignment(&self, ticket: &Ticket, agent_id: &str, now: u64) -> Result<bool>;
+
+    /// Give a triaged ticket to an agent of its team, with the strategy of the team. Tickets
+    /// without team can go to any agent. Returns `None` when the ticket is not triaged or when no
+    /// agent is available, it then stays triaged.
+    async fn auto_assign(
+        &self,
+        ticket_id: &str,
+        now: u64,
+    ) -> Result<Option<(TicketEvent, AssignmentEvent)>>
+    where
+        Self: TicketRepository,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut ticket = self
+                .resolve(ticket_id)
+                .await?
+                .with_context(|| format!("Unknown ticket {}", ticket_id))?;
+            if ticket.status != TicketStatus::Triaged {
+                return Ok(None);
+            }
+            let Some(agent_id) = self.pick_agent(&ticket, now).await? else {
+                return Ok(None);
+            };
+            let strategy = self.strategy_of(&ticket).await?;
+            let event = ticket.assign(&agent_id, Some(strategy), now)?;
+            // Lost a race: with another change of the ticket, or for the last slot of the agent
+            match self.save_assignment(&ticket, &agent_id, now).await {
+                Ok(true) => {}
+                Ok(false) if attempt < MAX_ATTEMPTS => continue,
+                Ok(false) => return Ok(None),
+                Err(e) => {
+                    is_conflict(Err(e), attempt)?;
+                    continue;
+                }
+            }
+            let assignment = ticket
+                .assignments
+                .last()
+                .cloned()
+                .context("Assignment not recorded")?;
+            return Ok(Some((
+                event,
+                AssignmentEvent {
+                    ticket_id: ticket.id,
+                    assignment,
+                },
+            )));
+        }
+    }
+
+    /// Strategy of the team of the ticket, the default one for tickets without team
+    async fn strategy_of(&self, ticket: &Ticket) -> Result<AssignmentStrategy> {
+        Ok(match &ticket.team {
+            Some(team) => self
+                .team(team)
+                .await?
+                .map(|team| team.strategy)
+                .unwrap_or_default(),
+            None => AssignmentStrategy::default(),
+        })
+    }
+
+    /// Agent of the team of the ticket the strategy gives it to, among the available ones
+    async fn pick_agent(&self, ticket: &Ticket, now: u64) -> Result<Option<String>> {
+        let mut candidates = vec![];
+        for agent in self.agents().await? {
+            let in_team = ticket
+                .team
+                .as_ref()
+                .is_none_or(|team| agent.teams.contains(team));
+            if !in_team || !agent.is_available(now) {
+                continue;
+            }
+            let open_tickets = self.open_tickets_of(&agent.id).await?;
+            if open_tickets < agent.capacity {
+                candidates.push(Candidate {
+                    agent,
+                    open_tickets,
+                });
+            }
+        }
+        let strategy = self.strategy_of(ticket).await?;
+        Ok(assignment::choose(strategy, ticket, &candidates).map(|chosen| chosen.agent.id.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::{CommonMessage, LabeledTicket, TransitionRequest};
+    use crate::store::memory::MemoryTicketRepository;
+    use crate::ticket::Routing;
+
+    fn agent(id: &str, teams: &[&str], capacity: usize) -> Agent {
+        Agent {
+            id: id.to_string(),
+            name: id.to_string(),
+            teams: teams.iter().map(|team| team.to_string()).collect(),
+            skills: vec![],
+            capacity,
+            working_hours: None,
+            active: true,
+            last_assigned_at: None,
+        }
+    }
+
+    async fn triaged(repository: &MemoryTicketRepository, id: &str, team: &str) -> Result<()> {
+        repository
+            .upsert_labeled(LabeledTicket {
+                routing: Some(Routing {
+                    team: Some(team.to_string()),
+                    ..Default::default()
+                }),
+                ..LabeledTicket::test(
+                    id,
+                    CommonMessage::test("anna@shop.example", "I was charged twice"),
+                )
+            })
+            .await?;
+        repository
+            .transition(&TransitionRequest {
+                ticket_id: id.to_string(),
+                to: TicketStatus::Triaged,
+                reason: None,
+            })
+            .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_working_hours() {
+        let office = WorkingHours {
+            days: vec![1, 2, 3, 4, 5],
+            start_hour: 9,
+            end_hour: 17,
+            utc_offset_minutes: 120,
+        };
+        // Monday 2026-10-19, 08:30 UTC is 10:30 in UTC+2
+        let monday = 1_792_368_000;
+        assert!(office.is_working(monday + 8 * 3600 + 1800));
+        assert!(!office.is_working(monday + 15 * 3600));
+        // Sunday
+        assert!(!office.is_working(monday - 12 * 3600));
+
+        let night = WorkingHours {
+            days: vec![5],
+            start_hour: 22,
+            end_hour: 6,
+            utc_offset_minutes: 0,
+        };
+        let friday = monday + 4 * 86_400;
+        assert!(night.is_working(friday + 23 * 3600));
+        // Saturday morning, end of the Friday shift
+        assert!(night.is_working(friday + 86_400 + 5 * 3600));
+        assert!(!night.is_working(friday + 5 * 3600));
+    }
+
+    #[tokio::test]
+    async fn test_auto_assign() -> Result<()> {
+        let repository = MemoryTicketRepository::default();
+        repository
+            .save_team(&Team {
+                id: "finance".to_string(),
+                name: "Finance".to_string(),
+                strategy: AssignmentStrategy::RoundRobin,
+            })
+            .await?;
+        repository
+            .save_agent(&agent("alice", &["finance"], 1))
+            .await?;
+        repository
+            .save_agent(&agent("bob", &["finance"], 1))
+            .await?;
+        repository
+            .save_agent(&agent("carol", &["logistics"], 5))
+            .await?;
+        let mut away = agent("dave", &["finance"], 5);
+        away.active = false;
+        repository.save_agent(&away).await?;
+
+        triaged(&repository, "1", "finance").await?;
+        let (event, assigned) = repository.auto_assign("1", 200).await?.unwrap();
+        assert_eq!(event.to, TicketStatus::Assigned);
+        assert_eq!(assigned.assignment.agent_id, "alice");
+        assert_eq!(
+            assigned.assignment.strategy,
+            Some(AssignmentStrategy::RoundRobin)
+        );
+        // Already assigned
+        assert!(repository.auto_assign("1", 210).await?.is_none());
+
+        triaged(&repository, "2", "finance").await?;
+        let (_, assigned) = repository.auto_assign("2", 220).await?.unwrap();
+        assert_eq!(assigned.assignment.agent_id, "bob");
+
+        assert_eq!(
+            repository.agent("bob").await?.unwrap().last_assigned_at,
+            Some(220)
+        );
+
+        // Both at capacity
+        triaged(&repository, "3", "finance").await?;
+        assert!(repository.auto_assign("3", 230).await?.is_none());
+        let mut ticket = repository.get("3").await?.unwrap();
+        assert_eq!(ticket.status, TicketStatus::Triaged);
+        assert_eq!(ticket.assignee, None);
+        // Picked before another assignment filled the agent up
+        ticket.assign("alice", None, 230)?;
+        assert!(!repository.save_assignment(&ticket, "alice", 230).await?);
+        assert_eq!(repository.get("3").await?.unwrap().assignee, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+//! Contacts: the identities customers write from, linked into one profile per customer across
+//! channels, and flags on contacts, e.g. an email address that bounced, so that agents know a
+//! reply will not reach them.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::store::MAX_REDIRECTS;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContactFlag {
+    /// Replies to the contact bounced: the address or number does not exist
+    Bounced,
+}
+
+/// Flags are kept on the normalized identity: a bounce reported for `Anna <anna@shop.example>`
+/// also flags `anna@shop.example`
+#[allow(async_fn_in_trait)]
+pub trait ContactFlags: Send + Sync {
+    /// Set a flag on the contact, or refresh its reason
+    async fn flag(&self, contact: &str, flag: ContactFlag, reason: Option<&str>) -> Result<()>;
+
+    async fn unflag(&self, contact: &str, flag: ContactFlag) -> Result<()>;
+
+    async fn flags(&self, contact: &str) -> Result<Vec<ContactFlag>>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IdentityKind {
+    /// E.164 phone number, shared by WhatsApp and Signal
+    Phone,
+    Email,
+    /// Anything else, e.g. `telegram:<chat id>` or `webchat:<session id>`
+    Handle,
+}
+
+/// A contact in its normalized form, the same for every channel the customer writes from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Identity {
+    pub kind: IdentityKind,
+    pub value: String,
+}
+
+impl Identity {
+    /// Identity of a `CommonMessage::contact`
+    pub fn parse(contact: &str) -> Identity {
+        if let Some(email) = normalize_email(contact) {
+            return Identity {
+                kind: IdentityKind::Email,
+                value: email,
+            };
+        }
+        if let Some(phone) = normalize_phone(contact) {
+            return Identity {
+                kind: IdentityKind::Phone,
+                value: phone,
+            };
+        }
+        Identity {
+            kind: IdentityKind::Handle,
+            value: contact.trim().to_string(),
+        }
+    }
+}
+
+/// International number in E.164 form: `+33 6 12-34-56-78`, `0033612345678` and WhatsApp's
+/// `33612345678` all give `+33612345678`. National numbers are not guessed.
+pub fn normalize_phone(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let raw = raw
+        .strip_prefix('+')
+        .or_else(|| raw.strip_prefix("00"))
+        .unwrap_or(raw);
+    let digits: String = raw
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+        .collect();
+    let valid = (8..=15).contains(&digits.len())
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && !digits.starts_with('0');
+    valid.then(|| format!("+{}", digits))
+}
+
+/// Lowercased address, without its display name nor its `+tag`:
+/// `Anna <Anna+Shop@Example.com>` gives `anna@example.com`
+pub fn normalize_email(raw: &str) -> Option<String> {
+    let address = match raw.split_once('<') {
+        Some((_, rest)) => rest.split('>').next().unwrap_or(rest),
+        None => raw,
+    };
+    let (local, domain) = address.trim().rsplit_once('@')?;
+    let local = local.split('+').next().unwrap_or(local);
+    if local.is_empty() || !domain.contains('.') || address.trim().contains(char::is_whitespace) {
+        return None;
+    }
+    Some(format!(
+        "{}@{}",
+        local.to_lowercase(),
+        domain.to_lowercase()
+    ))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Customer {
+    pub id: String,
+    /// Set when the customer turned out to be another one, whose profile now has its identities
+    pub merged_into: Option<String>,
+    pub created_at: u64,
+}
+
+/// A customer and the identities they write from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomerProfile {
+    pub id: String,
+    pub identities: Vec<Identity>,
+    pub created_at: u64,
+}
+
+/// Directory of customers and their identities. Implementations only store identities and
+/// customers, resolution and linking are built on top of that.
+#[allow(async_fn_in_trait)]
+pub trait ContactDirectory: Send + Sync {
+    async fn customer_of(&self, identity: &Identity) -> Result<Option<String>>;
+
+    /// Give the identity to a new customer, unless it already has one. Returns the customer of
+    /// the identity.
+    async fn register(&self, identity: &Identity) -> Result<String>;
+
+    /// Give the identity to the customer, taking it from its previous one
+    async fn attach(&self, identity: &Identity, customer_id: &str) -> Result<()>;
+
+    /// Move every identity of `from` to `into`, `from` becomes a redirect to `into`
+    async fn merge_customers(&self, from: &str, into: &str) -> Result<()>;
+
+    async fn customer(&self, id: &str) -> Result<Option<Customer>>;
+
+    async fn identities(&self, customer_id: &str) -> Result<Vec<Identity>>;
+
+    /// Customer of the contact, a new one the first time it writes
+    async fn resolve_contact(&self, contact: &str) -> Result<String> {
+        let identity = Identity::parse(contact);
+        match self.customer_of(&identity).await? {
+            Some(customer_id) => Ok(customer_id),
+            None => self.register(&identity).await,
+        }
+    }
+
+    /// Both contacts are the same customer, e.g. someone who wrote by email and on WhatsApp.
+    /// Returns the id of the customer, the one of `contact`.
+    async fn link(&self, contact: &str, other_contact: &str) -> Result<String> {
+        let customer_id = self.resolve_contact(contact).await?;
+        let other = Identity::parse(other_contact);
+        match self.customer_of(&other).await? {
+            Some(other_id) if other_id != customer_id => {
+                self.merge_customers(&other_id, &customer_id).await?
+            }
+            Some(_) => {}
+            None => self.attach(&other, &customer_id).await?,
+        }
+        Ok(customer_id)
+    }
+
+    /// Profile of the customer, following the redirects left by merges
+    async fn profile(&self, customer_id: &str) -> Result<Option<CustomerProfile>> {
+        let mut id = customer_id.to_string();
+        for _ in 0..MAX_REDIRECTS {
+            match self.customer(&id).await? {
+                Some(Customer {
+                    merged_into: Some(target),
+                    ..
+                }) => id = target,
+                Some(customer) => {
+                    return Ok(Some(CustomerProfile {
+                        identities: self.identities(&customer.id).await?,
+                        id: customer.id,
+                        created_at: customer.created_at,
+                    }))
+                }
+                None => return Ok(None),
+            }
+        }
+        bail!("Too many redirects resolving customer {}", customer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryTicketRepository;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize_phone("+33 6 12-34-56-78").as_deref(),
+            Some("+33612345678")
+        );
+        assert_eq!(
+            normalize_phone("0033612345678").as_deref(),
+            Some("+33612345678")
+        );
+        assert_eq!(
+            normalize_phone("33612345678").as_deref(),
+            Some("+33612345678")
+        );
+        assert_eq!(normalize_phone("0612345678"), None);
+        assert_eq!(normalize_phone("telegram:5000000001"), None);
+
+        assert_eq!(
+            normalize_email("Anna <Anna+Shop@Example.com>").as_deref(),
+            Some("anna@example.com")
+        );
+        assert_eq!(normalize_email("anna@localhost"), None);
+        assert_eq!(normalize_email("+33612345678"), None);
+
+        assert_eq!(Identity::parse("webchat:s-42").kind, IdentityKind::Handle);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_and_link() -> Result<()> {
+        let directory = MemoryTicketRepository::default();
+        let whatsapp = directory.resolve_contact("33612345678").await?;
+        // Same number on Signal
+        assert_eq!(
+            directory.resolve_contact("+33 6 12 34 56 78").await?,
+            whatsapp
+        );
+
+        let email = directory
+            .resolve_contact("Anna+orders@shop.example")
+            .await?;
+        assert_ne!(email, whatsapp);
+        assert_eq!(directory.resolve_contact("anna@shop.example").await?, email);
+
+        // The email profile is merged into the WhatsApp one
+        assert_eq!(
+            directory.link("+33612345678", "anna@shop.example").await?,
+            whatsapp
+        );
+        assert_eq!(
+            directory.resolve_contact("anna@shop.example").await?,
+            whatsapp
+        );
+        directory
+            .link("+33612345678", "telegram:5000000001")
+            .await?;
+
+        let profile = directory.profile(&email).await?.unwrap();
+        assert_eq!(profile.id, whatsapp);
+        let mut identities: Vec<&str> = profile
+            .identities
+            .iter()
+            .map(|identity| identity.value.as_str())
+            .collect();
+        identities.sort();
+        assert_eq!(
+            identities,
+            vec!["+33612345678", "anna@shop.example", "telegram:5000000001"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flags_follow_the_identity() -> Result<()> {
+        let flags = MemoryTicketRepository::default();
+        flags
+            .flag(
+                "Anna <Anna+orders@Shop.example>",
+                ContactFlag::Bounced,
+                None,
+            )
+            .await?;
+        assert_eq!(
+            flags.flags("anna@shop.example").await?,
+            vec![ContactFlag::Bounced]
+        );
+        assert!(flags.flags("+33612345678").await?.is_empty());
+        flags
+            .unflag("anna@shop.example", ContactFlag::Bounced)
+            .await?;
+        assert!(flags
+            .flags("Anna <Anna+orders@Shop.example>")
+            .await?
+            .is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+//! Messages set aside by message-filter instead of being labeled, kept so that agents can review
+//! them and spot a legitimate customer caught by the filters.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::dto::CommonMessage;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FilterReason {
+    /// Out-of-office, vacation message or bounce, with what gave it away. Answering would loop.
+    AutoReply(String),
+    /// The sender is on the blocklist
+    Blocked,
+    /// The sender wrote more messages than the rate limit allows
+    RateLimited,
+    /// Spam score of the message, above the threshold
+    Spam(f32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FilteredMessage {
+    pub message: CommonMessage,
+    pub reason: FilterReason,
+    pub filtered_at: u64,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait FilteredMessages: Send + Sync {
+    async fn record_filtered(&self, filtered: &FilteredMessage) -> Result<()>;
+
+    /// Messages of the contact that were filtered, most recent first
+    async fn filtered(&self, contact: &str, limit: usize) -> Result<Vec<FilteredMessage>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::Origin;
+    use crate::store::memory::MemoryTicketRepository;
+
+    #[tokio::test]
+    async fn test_filtered_most_recent_first() -> Result<()> {
+        let store = MemoryTicketRepository::default();
+        for (contact, filtered_at) in [
+            ("+33612345678", 100),
+            ("+33600000000", 150),
+            ("+33612345678", 200),
+        ] {
+            store
+                .record_filtered(&FilteredMessage {
+                    message: CommonMessage {
+                        origin: Origin::WhatsApp,
+                        timestamp: filtered_at,
+                        ..CommonMessage::test(contact, "Hello")
+                    },
+                    reason: FilterReason::RateLimited,
+                    filtered_at,
+                })
+                .await?;
+        }
+
+        let filtered = store.filtered("+33612345678", 10).await?;
+        let times: Vec<u64> = filtered
+            .iter()
+            .map(|filtered| filtered.filtered_at)
+            .collect();
+        assert_eq!(times, vec![200, 100]);
+        assert_eq!(store.filtered("+33612345678", 1).await?.len(), 1);
+        Ok(())
+    }
+}
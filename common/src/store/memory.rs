@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+
+use crate::dto::{Origin, SuggestedReply, SuggestionStatus};
+use crate::store::agent::{Agent, AgentDirectory, Team};
+use crate::store::contact::Customer;
+use crate::store::filtered::{FilteredMessage, FilteredMessages};
+use crate::store::suggestion::Suggestions;
+use crate::store::{
+    ContactDirectory, ContactFlag, ContactFlags, Identity, StaleTicket, ThreadIndex,
+    TicketRepository,
+};
+use crate::ticket::{self, Ticket, TicketStatus};
+
+/// Repository kept in memory, for tests and local runs without Postgres
+#[derive(Default)]
+pub struct MemoryTicketRepository {
+    tickets: Mutex<HashMap<String, Ticket>>,
+    threads: Mutex<HashMap<String, String>>,
+    chat_threads: Mutex<HashMap<(Origin, String), String>>,
+    contact_flags: Mutex<HashMap<(String, ContactFlag), Option<String>>>,
+    customers: Mutex<HashMap<String, Customer>>,
+    /// Identity and customer, by identity value
+    identities: Mutex<HashMap<String, (Identity, String)>>,
+    filtered: Mutex<Vec<FilteredMessage>>,
+    agents: Mutex<HashMap<String, Agent>>,
+    teams: Mutex<HashMap<String, Team>>,
+    suggestions: Mutex<HashMap<String, SuggestedReply>>,
+}
+
+impl MemoryTicketRepository {
+    fn save_locked(tickets: &mut HashMap<String, Ticket>, ticket: &Ticket) -> Result<()> {
+        let stored = tickets.get(&ticket.id).map_or(0, |stored| stored.version);
+        if stored != ticket.version {
+            return Err(StaleTicket {
+                ticket_id: ticket.id.clone(),
+                version: ticket.version,
+            }
+            .into());
+        }
+        let mut saved = ticket.clone();
+        saved.version += 1;
+        tickets.insert(ticket.id.clone(), saved);
+        Ok(())
+    }
+
+    fn find(&self, predicate: impl Fn(&Ticket) -> bool) -> Vec<Ticket> {
+        let mut tickets: Vec<Ticket> = self
+            .tickets
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|t| predicate(t))
+            .cloned()
+            .collect();
+        tickets.sort_by_key(|t| std::cmp::Reverse(t.updated_at));
+        tickets
+    }
+}
+
+impl TicketRepository for MemoryTicketRepository {
+    async fn save(&self, ticket: &Ticket) -> Result<()> {
+        Self::save_locked(&mut self.tickets.lock().unwrap(), ticket)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Ticket>> {
+        Ok(self.tickets.lock().unwrap().get(id).cloned())
+    }
+
+    async fn find_by_contact(&self, contact: &str) -> Result<Vec<Ticket>> {
+        Ok(self.find(|t| t.contact == contact))
+    }
+
+    async fn find_by_tag(&self, tag: &str) -> Result<Vec<Ticket>> {
+        Ok(self.find(|t| t.tags.iter().any(|t| t == tag)))
+    }
+
+    async fn find_by_status(&self, status: TicketStatus) -> Result<Vec<Ticket>> {
+        Ok(self.find(|t| t.status == status))
+    }
+
+    async fn find_created_since(&self, since: u64) -> Result<Vec<Ticket>> {
+        Ok(self.find(|t| t.created_at >= since))
+    }
+}
+
+impl ThreadIndex for MemoryTicketRepository {
+    async fn record(&self, message_id: &str, ticket_id: &str) -> Result<()> {
+        self.threads
+            .lock()
+            .unwrap()
+            .entry(message_id.to_string())
+            .or_insert_with(|| ticket_id.to_string());
+        Ok(())
+    }
+
+    async fn lookup(&self, message_id: &str) -> Result<Option<String>> {
+        Ok(self.threads.lock().unwrap().get(message_id).cloned())
+    }
+
+    async fn rethread(&self, origin: Origin, thread_key: &str, ticket_id: &str) -> Result<()> {
+        self.chat_threads
+            .lock()
+            .unwrap()
+            .insert((origin, thread_key.to_string()), ticket_id.to_string());
+        Ok(())
+    }
+
+    async fn chat_thread(&self, origin: Origin, thread_key: &str) -> Result<Option<String>> {
+        Ok(self
+            .chat_threads
+            .lock()
+            .unwrap()
+            .get(&(origin, thread_key.to_string()))
+            .cloned())
+    }
+}
+
+impl ContactFlags for MemoryTicketRepository {
+    async fn flag(&self, contact: &str, flag: ContactFlag, reason: Option<&str>) -> Result<()> {
+        self.contact_flags.lock().unwrap().insert(
+            (Identity::parse(contact).value, flag),
+            reason.map(str::to_string),
+        );
+        Ok(())
+    }
+
+    async fn unflag(&self, contact: &str, flag: ContactFlag) -> Result<()> {
+        self.contact_flags
+            .lock()
+            .unwrap()
+            .remove(&(Identity::parse(contact).value, flag));
+        Ok(())
+    }
+
+    async fn flags(&self, contact: &str) -> Result<Vec<ContactFlag>> {
+        let contact = Identity::parse(contact).value;
+        Ok(self
+            .contact_flags
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(flagged, _)| *flagged == contact)
+            .map(|(_, flag)| *flag)
+            .collect())
+    }
+}
+
+impl ContactDirectory for MemoryTicketRepository {
+    async fn customer_of(&self, identity: &Identity) -> Result<Option<String>> {
+        Ok(self
+            .identities
+            .lock()
+            .unwrap()
+            .get(&identity.value)
+            .map(|(_, customer_id)| customer_id.clone()))
+    }
+
+    async fn register(&self, identity: &Identity) -> Result<String> {
+        let mut identities = self.identities.lock().unwrap();
+        if let Some((_, customer_id)) = identities.get(&identity.value) {
+            return Ok(customer_id.clone());
+        }
+        let customer = Customer {
+            id: uuid::Uuid::new_v4().to_string(),
+            merged_into: None,
+            created_at: ticket::now(),
+        };
+        identities.insert(
+            identity.value.clone(),
+            (identity.clone(), customer.id.clone()),
+        );
+        let id = customer.id.clone();
+        self.customers.lock().unwrap().insert(id.clone(), customer);
+        Ok(id)
+    }
+
+    async fn attach(&self, identity: &Identity, customer_id: &str) -> Result<()> {
+        self.identities.lock().unwrap().insert(
+            identity.value.clone(),
+            (identity.clone(), customer_id.to_string()),
+        );
+        Ok(())
+    }
+
+    async fn merge_customers(&self, from: &str, into: &str) -> Result<()> {
+        for (_, customer_id) in self.identities.lock().unwrap().values_mut() {
+            if customer_id == from {
+                *customer_id = into.to_string();
+            }
+        }
+        if let Some(customer) = self.customers.lock().unwrap().get_mut(from) {
+            customer.merged_into = Some(into.to_string());
+        }
+        Ok(())
+    }
+
+    async fn customer(&self, id: &str) -> Result<Option<Customer>> {
+        Ok(self.customers.lock().unwrap().get(id).cloned())
+    }
+
+    async fn identities(&self, customer_id: &str) -> Result<Vec<Identity>> {
+        Ok(self
+            .identities
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|(_, owner)| owner == customer_id)
+            .map(|(identity, _)| identity.clone())
+            .collect())
+    }
+}
+
+impl FilteredMessages for MemoryTicketRepository {
+    async fn record_filtered(&self, filtered: &FilteredMessage) -> Result<()> {
+        self.filtered.lock().unwrap().push(filtered.clone());
+        Ok(())
+    }
+
+    async fn filtered(&self, contact: &str, limit: usize) -> Result<Vec<FilteredMessage>> {
+        let mut filtered: Vec<FilteredMessage> = self
+            .filtered
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|filtered| filtered.message.contact == contact)
+            .cloned()
+            .collect();
+        filtered.sort_by_key(|filtered| std::cmp::Reverse(filtered.filtered_at));
+        filtered.truncate(limit);
+        Ok(filtered)
+    }
+}
+
+impl Suggestions for MemoryTicketRepository {
+    async fn save_suggestion(&self, suggestion: &SuggestedReply) -> Result<()> {
+        self.suggestions
+            .lock()
+            .unwrap()
+            .insert(suggestion.id.clone(), suggestion.clone());
+        Ok(())
+    }
+
+    async fn suggestion(&self, id: &str) -> Result<Option<SuggestedReply>> {
+        Ok(self.suggestions.lock().unwrap().get(id).cloned())
+    }
+
+    async fn review_suggestion(
+        &self,
+        id: &str,
+        status: SuggestionStatus,
+        body: &str,
+    ) -> Result<bool> {
+        let mut suggestions = self.suggestions.lock().unwrap();
+        match suggestions.get_mut(id) {
+            Some(suggestion) if suggestion.status == SuggestionStatus::Pending => {
+                suggestion.status = status;
+                suggestion.body = body.to_string();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl AgentDirectory for MemoryTicketRepository {
+    async fn save_agent(&self, agent: &Agent) -> Result<()> {
+        self.agents
+            .lock()
+            .unwrap()
+            .insert(agent.id.clone(), agent.clone());
+        Ok(())
+    }
+
+    async fn agent(&self, id: &str) -> Result<Option<Agent>> {
+        Ok(self.agents.lock().unwrap().get(id).cloned())
+    }
+
+    async fn agents(&self) -> Result<Vec<Agent>> {
+        let mut agents: Vec<Agent> = self.agents.lock().unwrap().values().cloned().collect();
+        agents.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(agents)
+    }
+
+    async fn save_team(&self, team: &Team) -> Result<()> {
+        self.teams
+            .lock()
+            .unwrap()
+            .insert(team.id.clone(), team.clone());
+        Ok(())
+    }
+
+    async fn team(&self, id: &str) -> Result<Option<Team>> {
+        Ok(self.teams.lock().unwrap().get(id).cloned())
+    }
+
+    async fn teams(&self) -> Result<Vec<Team>> {
+        let mut teams: Vec<Team> = self.teams.lock().unwrap().values().cloned().collect();
+        teams.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(teams)
+    }
+
+    async fn open_tickets_of(&self, agent_id: &str) -> Result<usize> {
+        Ok(self
+            .find(|t| t.assignee.as_deref() == Some(agent_id) && t.status.is_open())
+            .len())
+    }
+
+    async fn save_assignment(&self, ticket: &Ticket, agent_id: &str, now: u64) -> Result<bool> {
+        let mut tickets = self.tickets.lock().unwrap();
+        let mut agents = self.agents.lock().unwrap();
+        let agent = agents
+            .get_mut(agent_id)
+            .with_context(|| format!("Unknown agent {}", agent_id))?;
+        let open_tickets = tickets
+            .values()
+            .filter(|t| t.id != ticket.id)
+            .filter(|t| t.assignee.as_deref() == Some(agent_id) && t.status.is_open())
+            .count();
+        if open_tickets >= agent.capacity {
+            return Ok(false);
+        }
+        Self::save_locked(&mut tickets, ticket)?;
+        agent.last_assigned_at = Some(now);
+        Ok(true)
+    }
+}
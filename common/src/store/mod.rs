@@ -0,0 +1,370 @@
+//! Persistence of tickets, queryable by id, contact, tag and status.
+
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+
+use crate::dto::{
+    DeliveryStatus, LabeledTicket, MergeRequest, OutboundMessage, TicketEvent, TransitionRequest,
+};
+use crate::ticket::{self, InvalidMerge, Ticket, TicketStatus};
+use crate::PG_URL;
+
+pub mod agent;
+pub mod contact;
+pub mod filtered;
+pub mod memory;
+pub mod postgres;
+pub mod suggestion;
+pub mod thread;
+
+pub use agent::{Agent, AgentDirectory, Team, WorkingHours};
+pub use contact::{ContactDirectory, ContactFlag, ContactFlags, CustomerProfile, Identity};
+pub use filtered::{FilterReason, FilteredMessage, FilteredMessages};
+pub use suggestion::Suggestions;
+pub use thread::ThreadIndex;
+
+/// Redirect chains longer than this are considered broken
+pub(crate) const MAX_REDIRECTS: usize = 16;
+
+/// Changes made again on a ticket saved by someone else meanwhile, before giving up
+pub(crate) const MAX_ATTEMPTS: usize = 5;
+
+/// The ticket was saved by someone else since it was loaded
+#[derive(Debug, PartialEq)]
+pub struct StaleTicket {
+    pub ticket_id: String,
+    pub version: u64,
+}
+
+impl fmt::Display for StaleTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Ticket {} was saved since version {}",
+            self.ticket_id, self.version
+        )
+    }
+}
+
+impl std::error::Error for StaleTicket {}
+
+/// Whether a save lost the race with another one and the change is to be made again
+pub(crate) fn is_conflict(saved: Result<()>, attempt: usize) -> Result<bool> {
+    match saved {
+        Err(e) if attempt < MAX_ATTEMPTS && e.downcast_ref::<StaleTicket>().is_some() => Ok(true),
+        saved => saved.map(|()| false),
+    }
+}
+
+/// Postgres connection string, from `POSTGRES_URL` with the local default as fallback
+pub fn postgres_url_from_env() -> String {
+    std::env::var("POSTGRES_URL").unwrap_or_else(|_| PG_URL.to_string())
+}
+
+/// Storage of ticket aggregates. Implementations only need to load and save whole tickets,
+/// follow-ups and merges are built on top of that.
+#[allow(async_fn_in_trait)]
+pub trait TicketRepository: Send + Sync {
+    /// Insert or replace the ticket, with its messages, tags and status history. Fails with
+    /// `StaleTicket` when the ticket was saved since it was loaded, its `version` then moved.
+    async fn save(&self, ticket: &Ticket) -> Result<()>;
+
+    async fn get(&self, id: &str) -> Result<Option<Ticket>>;
+
+    /// Tickets of a contact, most recently updated first
+    async fn find_by_contact(&self, contact: &str) -> Result<Vec<Ticket>>;
+
+    /// Tickets having the tag, most recently updated first
+    async fn find_by_tag(&self, tag: &str) -> Result<Vec<Ticket>>;
+
+    /// Tickets currently in the status, most recently updated first
+    async fn find_by_status(&self, status: TicketStatus) -> Result<Vec<Ticket>>;
+
+    /// Tickets created at or after `since`, merged ones included, most recently updated first
+    async fn find_created_since(&self, since: u64) -> Result<Vec<Ticket>>;
+
+    /// Load a ticket, following the redirects left by merges
+    async fn resolve(&self, id: &str) -> Result<Option<Ticket>> {
+        let mut id = id.to_string();
+        for _ in 0..MAX_REDIRECTS {
+            match self.get(&id).await? {
+                Some(Ticket {
+                    merged_into: Some(target),
+                    ..
+                }) => id = target,
+                other => return Ok(other),
+            }
+        }
+        bail!("Too many redirects resolving ticket {}", id)
+    }
+
+    /// Create the ticket, or add the message to the existing conversation for follow-ups.
+    /// Returns the status changes it caused.
+    async fn upsert_labeled(&self, labeled: LabeledTicket) -> Result<(Ticket, Vec<TicketEvent>)> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let (ticket, events) = self.apply_labeled(labeled.clone()).await?;
+            if !is_conflict(self.save(&ticket).await, attempt)? {
+                return Ok((ticket, events));
+            }
+        }
+    }
+
+    /// The ticket once the labeled message was added to it, not saved yet
+    async fn apply_labeled(&self, labeled: LabeledTicket) -> Result<(Ticket, Vec<TicketEvent>)> {
+        Ok(match self.resolve(&labeled.id).await? {
+            // Redelivered, e.g. within a webhook batch the channel sent again after a failure
+            Some(ticket) if ticket.has_message(&labeled.original_message) => (ticket, vec![]),
+            Some(mut ticket) => {
+                let replied_at = labeled.original_message.timestamp;
+                ticket.add_message(labeled.original_message);
+                // Routing only ever raises the priority of an existing ticket
+                if let Some(routing) = &labeled.routing {
+                    ticket.escalate(routing);
+                }
+                let events = ticket.on_customer_reply(replied_at).into_iter().collect();
+                (ticket, events)
+            }
+            None => {
+                let routing = labeled.routing.clone();
+                let labeled_at = labeled.labeled_at;
+                let mut ticket = Ticket::from(labeled);
+                let mut events = vec![ticket.created_event()];
+                if let Some(routing) = &routing {
+                    events.extend(ticket.apply_routing(routing, labeled_at));
+                }
+                (ticket, events)
+            }
+        })
+    }
+
+    /// Apply a validated status change
+    async fn transition(&self, request: &TransitionRequest) -> Result<TicketEvent> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut ticket = self
+                .resolve(&request.ticket_id)
+                .await?
+                .with_context(|| format!("Unknown ticket {}", request.ticket_id))?;
+            let event = ticket.transition(request.to, ticket::now(), request.reason.clone())?;
+            if !is_conflict(self.save(&ticket).await, attempt)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Add a reply sent to the customer to the thread of its ticket
+    async fn add_reply(&self, reply: &OutboundMessage) -> Result<Ticket> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut ticket = self
+                .resolve(&reply.ticket_id)
+                .await?
+                .with_context(|| format!("Unknown ticket {}", reply.ticket_id))?;
+            ticket.add_reply(reply);
+            if !is_conflict(self.save(&ticket).await, attempt)? {
+                return Ok(ticket);
+            }
+        }
+    }
+
+    /// Record the delivery status of a reply. Returns false when it was older than the one known.
+    async fn apply_delivery(&self, status: &DeliveryStatus) -> Result<bool> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut ticket = self
+                .resolve(&status.ticket_id)
+                .await?
+                .with_context(|| format!("Unknown ticket {}", status.ticket_id))?;
+            if !ticket.apply_delivery(status) {
+                return Ok(false);
+            }
+            if !is_conflict(self.save(&ticket).await, attempt)? {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Merge two tickets, the source becomes a closed redirect to the target
+    async fn merge(&self, request: &MergeRequest) -> Result<(Ticket, TicketEvent)> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let unknown = |id: &str| InvalidMerge(format!("Unknown ticket {}", id));
+            let mut source = self
+                .get(&request.source_ticket_id)
+                .await?
+                .ok_or_else(|| unknown(&request.source_ticket_id))?;
+            let mut target = self
+                .resolve(&request.target_ticket_id)
+                .await?
+                .ok_or_else(|| unknown(&request.target_ticket_id))?;
+
+            let event = ticket::merge(&mut source, &mut target)?;
+            // Target first: a crash or a conflict in between leaves the messages in both, the
+            // merge done again only moves those the target does not hold yet
+            if is_conflict(self.save(&target).await, attempt)?
+                || is_conflict(self.save(&source).await, attempt)?
+            {
+                continue;
+            }
+            return Ok((target, event));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::CommonMessage;
+    use memory::MemoryTicketRepository;
+
+    fn labeled(id: &str, timestamp: u64) -> LabeledTicket {
+        let message = CommonMessage {
+            timestamp,
+            ..CommonMessage::test("jane@example.com", "I was charged twice")
+        };
+        LabeledTicket {
+            tags: vec!["billing".to_string()],
+            ..LabeledTicket::test(id, message)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_follow_up_and_merge() -> Result<()> {
+        let repository = MemoryTicketRepository::default();
+        let (_, events) = repository.upsert_labeled(labeled("first", 100)).await?;
+        assert_eq!(events[0].to, TicketStatus::New);
+        repository.upsert_labeled(labeled("second", 200)).await?;
+
+        repository
+            .merge(&MergeRequest {
+                source_ticket_id: "second".to_string(),
+                target_ticket_id: "first".to_string(),
+            })
+            .await?;
+
+        // Follow-ups on the old id land in the ticket it was merged into
+        let (ticket, events) = repository.upsert_labeled(labeled("second", 300)).await?;
+        assert_eq!(ticket.id, "first");
+        assert_eq!(ticket.messages.len(), 3);
+        assert!(events.is_empty());
+
+        let new = repository.find_by_status(TicketStatus::New).await?;
+        assert_eq!(new.len(), 1);
+        let recent = repository.find_created_since(150).await?;
+        assert_eq!(recent.len(), 1);
+        assert!(recent[0].is_tombstone());
+
+        // The same message delivered twice by its channel
+        let with_id = |id: &str| {
+            let mut labeled = labeled("first", 400);
+            labeled.original_message.message_id = Some(id.to_string());
+            labeled
+        };
+        repository.upsert_labeled(with_id("wamid.1")).await?;
+        let (ticket, events) = repository.upsert_labeled(with_id("wamid.1")).await?;
+        assert_eq!(ticket.messages.len(), 4);
+        assert!(events.is_empty());
+        let (ticket, _) = repository.upsert_labeled(with_id("wamid.2")).await?;
+        assert_eq!(ticket.messages.len(), 5);
+        assert_eq!(repository.find_by_tag("billing").await?.len(), 1);
+        assert_eq!(
+            repository.find_by_contact("jane@example.com").await?.len(),
+            2
+        );
+        assert!(repository
+            .find_by_contact("bob@example.com")
+            .await?
+            .is_empty());
+
+        // Neither merging again nor merging an unknown ticket could ever succeed
+        for source in ["second", "unknown"] {
+            let err = repository
+                .merge(&MergeRequest {
+                    source_ticket_id: source.to_string(),
+                    target_ticket_id: "first".to_string(),
+                })
+                .await
+                .unwrap_err();
+            assert!(err.downcast_ref::<InvalidMerge>().is_some());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stale_save_is_rejected() -> Result<()> {
+        let repository = MemoryTicketRepository::default();
+        repository.upsert_labeled(labeled("3", 100)).await?;
+        let mut first = repository.get("3").await?.unwrap();
+        let mut second = first.clone();
+
+        first.transition(TicketStatus::Triaged, 110, None)?;
+        repository.save(&first).await?;
+        second.add_message(labeled("3", 120).original_message);
+        let err = repository.save(&second).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StaleTicket>(),
+            Some(&StaleTicket {
+                ticket_id: "3".to_string(),
+                version: 1,
+            })
+        );
+
+        // Made again on the fresh ticket, nothing is lost
+        let (ticket, _) = repository.upsert_labeled(labeled("3", 120)).await?;
+        assert_eq!(ticket.status, TicketStatus::Triaged);
+        assert_eq!(ticket.messages.len(), 2);
+        assert_eq!(repository.get("3").await?.unwrap().version, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_follow_up_reopens_waiting_ticket() -> Result<()> {
+        let repository = MemoryTicketRepository::default();
+        repository.upsert_labeled(labeled("7", 100)).await?;
+        for to in [
+            TicketStatus::Triaged,
+            TicketStatus::Assigned,
+            TicketStatus::WaitingOnCustomer,
+        ] {
+            repository
+                .transition(&TransitionRequest {
+                    ticket_id: "7".to_string(),
+                    to,
+                    reason: None,
+                })
+                .await?;
+        }
+        // Not allowed from WaitingOnCustomer
+        assert!(repository
+            .transition(&TransitionRequest {
+                ticket_id: "7".to_string(),
+                to: TicketStatus::Triaged,
+                reason: None,
+            })
+            .await
+            .is_err());
+
+        // Routed like a newsletter to another team, the follow-up stays where the ticket is
+        let follow_up = LabeledTicket {
+            routing: Some(crate::ticket::Routing {
+                team: Some("support".to_string()),
+                close: Some("Newsletter".to_string()),
+                ..Default::default()
+            }),
+            ..labeled("7", 200)
+        };
+        let (ticket, events) = repository.upsert_labeled(follow_up).await?;
+        assert_eq!(ticket.status, TicketStatus::Assigned);
+        assert_eq!(ticket.team, None);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].from, Some(TicketStatus::WaitingOnCustomer));
+        Ok(())
+    }
+}
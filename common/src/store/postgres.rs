@@ -0,0 +1,947 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::{Postgres, Row, Transaction};
+
+use crate::dto::{Origin, SuggestedReply, SuggestionStatus};
+use crate::store::agent::{Agent, AgentDirectory, Team};
+use crate::store::contact::{Customer, IdentityKind};
+use crate::store::filtered::{FilteredMessage, FilteredMessages};
+use crate::store::suggestion::Suggestions;
+use crate::store::{
+    ContactDirectory, ContactFlag, ContactFlags, Identity, StaleTicket, ThreadIndex,
+    TicketRepository,
+};
+use crate::ticket::{self, StatusChange, Ticket, TicketStatus};
+
+pub struct PostgresTicketRepository {
+    pool: PgPool,
+}
+
+impl PostgresTicketRepository {
+    /// Connect and bring the schema up to date
+    pub async fn connect(url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(url)
+            .await
+            .context("Failed to connect to Postgres")?;
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .context("Failed to run ticket store migrations")?;
+        Ok(PostgresTicketRepository { pool })
+    }
+
+    async fn find_ids(&self, query: &str, value: &str) -> Result<Vec<Ticket>> {
+        let ids: Vec<String> = sqlx::query_scalar(query)
+            .bind(value)
+            .fetch_all(&self.pool)
+            .await?;
+        self.load(&ids).await
+    }
+
+    /// Tickets with their messages, tags, history, replies and assignments, in the order of
+    /// `ids`. One query per table, however many tickets.
+    async fn load(&self, ids: &[String]) -> Result<Vec<Ticket>> {
+        let rows = sqlx::query(
+            "SELECT id, contact, origin, title, description, status, merged_into, created_at, updated_at, priority, team, assignee, version
+             FROM tickets WHERE id = ANY($1)",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+        if rows.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut messages = self
+            .json_children(
+                "SELECT ticket_id, message::text AS json FROM ticket_messages
+                 WHERE ticket_id = ANY($1) ORDER BY ticket_id, position",
+                ids,
+            )
+            .await?;
+        let mut replies = self
+            .json_children(
+                "SELECT ticket_id, reply::text AS json FROM ticket_replies
+                 WHERE ticket_id = ANY($1) ORDER BY ticket_id, position",
+                ids,
+            )
+            .await?;
+        let mut assignments = self
+            .json_children(
+                "SELECT ticket_id, assignment::text AS json FROM ticket_assignments
+                 WHERE ticket_id = ANY($1) ORDER BY ticket_id, position",
+                ids,
+            )
+            .await?;
+
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for row in sqlx::query(
+            "SELECT ticket_id, tag FROM ticket_tags WHERE ticket_id = ANY($1) ORDER BY ticket_id, position",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?
+        {
+            tags.entry(row.try_get("ticket_id")?)
+                .or_default()
+                .push(row.try_get("tag")?);
+        }
+
+        let mut status_history: HashMap<String, Vec<StatusChange>> = HashMap::new();
+        for row in sqlx::query(
+            "SELECT ticket_id, status, changed_at, reason FROM ticket_status_history
+             WHERE ticket_id = ANY($1) ORDER BY ticket_id, position",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?
+        {
+            status_history
+                .entry(row.try_get("ticket_id")?)
+                .or_default()
+                .push(StatusChange {
+                    status: from_text(row.try_get("status")?)?,
+                    changed_at: row.try_get::<i64, _>("changed_at")? as u64,
+                    reason: row.try_get("reason")?,
+                });
+        }
+
+        let mut tickets = HashMap::with_capacity(rows.len());
+        for row in rows {
+            let id: String = row.try_get("id")?;
+            let ticket = Ticket {
+                contact: row.try_get("contact")?,
+                origin: from_text(row.try_get("origin")?)?,
+                title: row.try_get("title")?,
+                tags: tags.remove(&id).unwrap_or_default(),
+                description: row.try_get("description")?,
+                messages: messages.remove(&id).unwrap_or_default(),
+                created_at: row.try_get::<i64, _>("created_at")? as u64,
+                updated_at: row.try_get::<i64, _>("updated_at")? as u64,
+                merged_into: row.try_get("merged_into")?,
+                status: from_text(row.try_get("status")?)?,
+                status_history: status_history.remove(&id).unwrap_or_default(),
+                replies: replies.remove(&id).unwrap_or_default(),
+                priority: from_text(row.try_get("priority")?)?,
+                team: row.try_get("team")?,
+                assignee: row.try_get("assignee")?,
+                assignments: assignments.remove(&id).unwrap_or_default(),
+                version: row.try_get::<i64, _>("version")? as u64,
+                id: id.clone(),
+            };
+            tickets.insert(id, ticket);
+        }
+        Ok(ids.iter().filter_map(|id| tickets.remove(id)).collect())
+    }
+
+    /// Children stored as JSON in a `json` column, by ticket
+    async fn json_children<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        ids: &[String],
+    ) -> Result<HashMap<String, Vec<T>>> {
+        let mut children: HashMap<String, Vec<T>> = HashMap::new();
+        for row in sqlx::query(query).bind(ids).fetch_all(&self.pool).await? {
+            let json: String = row.try_get("json")?;
+            children
+                .entry(row.try_get("ticket_id")?)
+                .or_default()
+                .push(serde_json::from_str(&json)?);
+        }
+        Ok(children)
+    }
+}
+
+/// Unit enums are stored as their serde name
+fn to_text(value: &impl Serialize) -> Result<String> {
+    match serde_json::to_value(value)? {
+        serde_json::Value::String(text) => Ok(text),
+        other => anyhow::bail!("Expected a unit variant, got {}", other),
+    }
+}
+
+fn from_text<T: DeserializeOwned>(text: String) -> Result<T> {
+    Ok(serde_json::from_value(serde_json::Value::String(text))?)
+}
+
+/// Save the ticket within a transaction, see `TicketRepository::save`
+async fn save_in(tx: &mut Transaction<'_, Postgres>, ticket: &Ticket) -> Result<()> {
+    // The row lock taken by the update makes a concurrent save wait, then find the new version
+    let saved = sqlx::query(
+        "INSERT INTO tickets (id, contact, origin, title, description, status, merged_into, created_at, updated_at, priority, team, assignee, version)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13 + 1)
+         ON CONFLICT (id) DO UPDATE SET
+            contact = EXCLUDED.contact,
+            origin = EXCLUDED.origin,
+            title = EXCLUDED.title,
+            description = EXCLUDED.description,
+            status = EXCLUDED.status,
+            merged_into = EXCLUDED.merged_into,
+            created_at = EXCLUDED.created_at,
+            updated_at = EXCLUDED.updated_at,
+            priority = EXCLUDED.priority,
+            team = EXCLUDED.team,
+            assignee = EXCLUDED.assignee,
+            version = EXCLUDED.version
+         WHERE tickets.version = $13",
+    )
+    .bind(&ticket.id)
+    .bind(&ticket.contact)
+    .bind(to_text(&ticket.origin)?)
+    .bind(&ticket.title)
+    .bind(&ticket.description)
+    .bind(to_text(&ticket.status)?)
+    .bind(&ticket.merged_into)
+    .bind(ticket.created_at as i64)
+    .bind(ticket.updated_at as i64)
+    .bind(to_text(&ticket.priority)?)
+    .bind(&ticket.team)
+    .bind(&ticket.assignee)
+    .bind(ticket.version as i64)
+    .execute(&mut **tx)
+    .await?;
+    if saved.rows_affected() == 0 {
+        return Err(StaleTicket {
+            ticket_id: ticket.id.clone(),
+            version: ticket.version,
+        }
+        .into());
+    }
+
+    // The children are small, rewriting them keeps the aggregate consistent
+    for table in [
+        "ticket_messages",
+        "ticket_tags",
+        "ticket_status_history",
+        "ticket_replies",
+        "ticket_assignments",
+    ] {
+        sqlx::query(&format!("DELETE FROM {} WHERE ticket_id = $1", table))
+            .bind(&ticket.id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    for (position, message) in ticket.messages.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO ticket_messages (ticket_id, position, sent_at, message) VALUES ($1, $2, $3, $4::jsonb)",
+        )
+        .bind(&ticket.id)
+        .bind(position as i32)
+        .bind(message.timestamp as i64)
+        .bind(serde_json::to_string(message)?)
+        .execute(&mut **tx)
+        .await?;
+    }
+    for (position, tag) in ticket.tags.iter().enumerate() {
+        sqlx::query("INSERT INTO ticket_tags (ticket_id, position, tag) VALUES ($1, $2, $3)")
+            .bind(&ticket.id)
+            .bind(position as i32)
+            .bind(tag)
+            .execute(&mut **tx)
+            .await?;
+    }
+    for (position, change) in ticket.status_history.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO ticket_status_history (ticket_id, position, status, changed_at, reason) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&ticket.id)
+        .bind(position as i32)
+        .bind(to_text(&change.status)?)
+        .bind(change.changed_at as i64)
+        .bind(&change.reason)
+        .execute(&mut **tx)
+        .await?;
+    }
+    for (position, reply) in ticket.replies.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO ticket_replies (ticket_id, position, outbound_id, state, reply) VALUES ($1, $2, $3, $4, $5::jsonb)",
+        )
+        .bind(&ticket.id)
+        .bind(position as i32)
+        .bind(&reply.outbound_id)
+        .bind(to_text(&reply.state)?)
+        .bind(serde_json::to_string(reply)?)
+        .execute(&mut **tx)
+        .await?;
+    }
+    for (position, assignment) in ticket.assignments.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO ticket_assignments (ticket_id, position, agent_id, assignment) VALUES ($1, $2, $3, $4::jsonb)",
+        )
+        .bind(&ticket.id)
+        .bind(position as i32)
+        .bind(&assignment.agent_id)
+        .bind(serde_json::to_string(assignment)?)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+impl TicketRepository for PostgresTicketRepository {
+    async fn save(&self, ticket: &Ticket) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        save_in(&mut tx, ticket).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Ticket>> {
+        Ok(self.load(&[id.to_string()]).await?.pop())
+    }
+
+    async fn find_by_contact(&self, contact: &str) -> Result<Vec<Ticket>> {
+        self.find_ids(
+            "SELECT id FROM tickets WHERE contact = $1 ORDER BY updated_at DESC",
+            contact,
+        )
+        .await
+    }
+
+    async fn find_by_tag(&self, tag: &str) -> Result<Vec<Ticket>> {
+        self.find_ids(
+            "SELECT t.id FROM tickets t JOIN ticket_tags g ON g.ticket_id = t.id
+             WHERE g.tag = $1 ORDER BY t.updated_at DESC",
+            tag,
+        )
+        .await
+    }
+
+    async fn find_by_status(&self, status: TicketStatus) -> Result<Vec<Ticket>> {
+        self.find_ids(
+            "SELECT id FROM tickets WHERE status = $1 ORDER BY updated_at DESC",
+            &to_text(&status)?,
+        )
+        .await
+    }
+
+    async fn find_created_since(&self, since: u64) -> Result<Vec<Ticket>> {
+        let ids: Vec<String> = sqlx::query_scalar(
+            "SELECT id FROM tickets WHERE created_at >= $1 ORDER BY updated_at DESC",
+        )
+        .bind(since as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        self.load(&ids).await
+    }
+}
+
+impl ThreadIndex for PostgresTicketRepository {
+    async fn record(&self, message_id: &str, ticket_id: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO email_threads (message_id, ticket_id, recorded_at) VALUES ($1, $2, $3)
+             ON CONFLICT (message_id) DO NOTHING",
+        )
+        .bind(message_id)
+        .bind(ticket_id)
+        .bind(ticket::now() as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn lookup(&self, message_id: &str) -> Result<Option<String>> {
+        Ok(
+            sqlx::query_scalar("SELECT ticket_id FROM email_threads WHERE message_id = $1")
+                .bind(message_id)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn rethread(&self, origin: Origin, thread_key: &str, ticket_id: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO chat_threads (origin, thread_key, ticket_id, recorded_at) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (origin, thread_key)
+             DO UPDATE SET ticket_id = EXCLUDED.ticket_id, recorded_at = EXCLUDED.recorded_at",
+        )
+        .bind(to_text(&origin)?)
+        .bind(thread_key)
+        .bind(ticket_id)
+        .bind(ticket::now() as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn chat_thread(&self, origin: Origin, thread_key: &str) -> Result<Option<String>> {
+        Ok(sqlx::query_scalar(
+            "SELECT ticket_id FROM chat_threads WHERE origin = $1 AND thread_key = $2",
+        )
+        .bind(to_text(&origin)?)
+        .bind(thread_key)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+}
+
+impl ContactFlags for PostgresTicketRepository {
+    async fn flag(&self, contact: &str, flag: ContactFlag, reason: Option<&str>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO contact_flags (contact, flag, reason, flagged_at) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (contact, flag) DO UPDATE SET
+                reason = EXCLUDED.reason,
+                flagged_at = EXCLUDED.flagged_at",
+        )
+        .bind(Identity::parse(contact).value)
+        .bind(to_text(&flag)?)
+        .bind(reason)
+        .bind(ticket::now() as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn unflag(&self, contact: &str, flag: ContactFlag) -> Result<()> {
+        sqlx::query("DELETE FROM contact_flags WHERE contact = $1 AND flag = $2")
+            .bind(Identity::parse(contact).value)
+            .bind(to_text(&flag)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn flags(&self, contact: &str) -> Result<Vec<ContactFlag>> {
+        sqlx::query_scalar::<_, String>(
+            "SELECT flag FROM contact_flags WHERE contact = $1 ORDER BY flag",
+        )
+        .bind(Identity::parse(contact).value)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(from_text)
+        .collect()
+    }
+}
+
+impl ContactDirectory for PostgresTicketRepository {
+    async fn customer_of(&self, identity: &Identity) -> Result<Option<String>> {
+        Ok(
+            sqlx::query_scalar("SELECT customer_id FROM contact_identities WHERE identity = $1")
+                .bind(&identity.value)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn register(&self, identity: &Identity) -> Result<String> {
+        let now = ticket::now() as i64;
+        let customer_id = uuid::Uuid::new_v4().to_string();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO customers (id, created_at) VALUES ($1, $2)")
+            .bind(&customer_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        let inserted = sqlx::query(
+            "INSERT INTO contact_identities (identity, kind, customer_id, linked_at)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (identity) DO NOTHING",
+        )
+        .bind(&identity.value)
+        .bind(to_text(&identity.kind)?)
+        .bind(&customer_id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if inserted == 1 {
+            tx.commit().await?;
+            return Ok(customer_id);
+        }
+        // Registered by another message of the contact in the meantime
+        tx.rollback().await?;
+        self.customer_of(identity)
+            .await?
+            .with_context(|| format!("Identity {} vanished", identity.value))
+    }
+
+    async fn attach(&self, identity: &Identity, customer_id: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO contact_identities (identity, kind, customer_id, linked_at)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (identity) DO UPDATE SET
+                customer_id = EXCLUDED.customer_id,
+                linked_at = EXCLUDED.linked_at",
+        )
+        .bind(&identity.value)
+        .bind(to_text(&identity.kind)?)
+        .bind(customer_id)
+        .bind(ticket::now() as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn merge_customers(&self, from: &str, into: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE contact_identities SET customer_id = $2, linked_at = $3 WHERE customer_id = $1",
+        )
+        .bind(from)
+        .bind(into)
+        .bind(ticket::now() as i64)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE customers SET merged_into = $2 WHERE id = $1")
+            .bind(from)
+            .bind(into)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn customer(&self, id: &str) -> Result<Option<Customer>> {
+        let row = sqlx::query("SELECT id, merged_into, created_at FROM customers WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| Customer {
+            id: row.get("id"),
+            merged_into: row.get("merged_into"),
+            created_at: row.get::<i64, _>("created_at") as u64,
+        }))
+    }
+
+    async fn identities(&self, customer_id: &str) -> Result<Vec<Identity>> {
+        let rows = sqlx::query(
+            "SELECT identity, kind FROM contact_identities WHERE customer_id = $1 ORDER BY linked_at, identity",
+        )
+        .bind(customer_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(Identity {
+                    kind: from_text::<IdentityKind>(row.get("kind"))?,
+                    value: row.get("identity"),
+                })
+            })
+            .collect()
+    }
+}
+
+impl FilteredMessages for PostgresTicketRepository {
+    async fn record_filtered(&self, filtered: &FilteredMessage) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO filtered_messages (contact, reason, filtered_at, message)
+             VALUES ($1, $2::jsonb, $3, $4::jsonb)",
+        )
+        .bind(&filtered.message.contact)
+        .bind(serde_json::to_string(&filtered.reason)?)
+        .bind(filtered.filtered_at as i64)
+        .bind(serde_json::to_string(&filtered.message)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn filtered(&self, contact: &str, limit: usize) -> Result<Vec<FilteredMessage>> {
+        let rows = sqlx::query(
+            "SELECT reason::text AS reason, filtered_at, message::text AS message
+             FROM filtered_messages WHERE contact = $1
+             ORDER BY filtered_at DESC, id DESC LIMIT $2",
+        )
+        .bind(contact)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(FilteredMessage {
+                    message: serde_json::from_str(row.get("message"))?,
+                    reason: serde_json::from_str(row.get("reason"))?,
+                    filtered_at: row.get::<i64, _>("filtered_at") as u64,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Suggestions for PostgresTicketRepository {
+    async fn save_suggestion(&self, suggestion: &SuggestedReply) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO suggested_replies (id, ticket_id, contact, origin, body, sources, status, created_at, llm_usage)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::jsonb)
+             ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(&suggestion.id)
+        .bind(&suggestion.ticket_id)
+        .bind(&suggestion.contact)
+        .bind(to_text(&suggestion.origin)?)
+        .bind(&suggestion.body)
+        .bind(&suggestion.sources)
+        .bind(to_text(&suggestion.status)?)
+        .bind(suggestion.created_at as i64)
+        .bind(
+            suggestion
+                .llm_usage
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn suggestion(&self, id: &str) -> Result<Option<SuggestedReply>> {
+        let row = sqlx::query(
+            "SELECT id, ticket_id, contact, origin, body, sources, status, created_at, llm_usage::text AS llm_usage
+             FROM suggested_replies WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let llm_usage: Option<String> = row.try_get("llm_usage")?;
+        Ok(Some(SuggestedReply {
+            id: row.try_get("id")?,
+            ticket_id: row.try_get("ticket_id")?,
+            contact: row.try_get("contact")?,
+            origin: from_text(row.try_get("origin")?)?,
+            body: row.try_get("body")?,
+            sources: row.try_get("sources")?,
+            status: from_text(row.try_get("status")?)?,
+            created_at: row.try_get::<i64, _>("created_at")? as u64,
+            llm_usage: llm_usage
+                .map(|usage| serde_json::from_str(&usage))
+                .transpose()?,
+        }))
+    }
+
+    async fn review_suggestion(
+        &self,
+        id: &str,
+        status: SuggestionStatus,
+        body: &str,
+    ) -> Result<bool> {
+        // Two agents reviewing at once: the first one wins
+        let reviewed = sqlx::query(
+            "UPDATE suggested_replies SET status = $2, body = $3 WHERE id = $1 AND status = $4",
+        )
+        .bind(id)
+        .bind(to_text(&status)?)
+        .bind(body)
+        .bind(to_text(&SuggestionStatus::Pending)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(reviewed.rows_affected() == 1)
+    }
+}
+
+fn agent_from_row(row: sqlx::postgres::PgRow) -> Result<Agent> {
+    let working_hours: Option<String> = row.try_get("working_hours")?;
+    Ok(Agent {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        teams: row.try_get("teams")?,
+        skills: row.try_get("skills")?,
+        capacity: row.try_get::<i32, _>("capacity")? as usize,
+        working_hours: working_hours
+            .map(|json| serde_json::from_str(&json))
+            .transpose()?,
+        active: row.try_get("active")?,
+        last_assigned_at: row
+            .try_get::<Option<i64>, _>("last_assigned_at")?
+            .map(|at| at as u64),
+    })
+}
+
+fn team_from_row(row: sqlx::postgres::PgRow) -> Result<Team> {
+    Ok(Team {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        strategy: from_text(row.try_get("strategy")?)?,
+    })
+}
+
+const AGENT_COLUMNS: &str =
+    "id, name, teams, skills, capacity, working_hours::text AS working_hours, active, last_assigned_at";
+
+impl AgentDirectory for PostgresTicketRepository {
+    async fn save_agent(&self, agent: &Agent) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO agents (id, name, teams, skills, capacity, working_hours, active, last_assigned_at)
+             VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7, $8)
+             ON CONFLICT (id) DO UPDATE SET
+                name = EXCLUDED.name,
+                teams = EXCLUDED.teams,
+                skills = EXCLUDED.skills,
+                capacity = EXCLUDED.capacity,
+                working_hours = EXCLUDED.working_hours,
+                active = EXCLUDED.active,
+                last_assigned_at = EXCLUDED.last_assigned_at",
+        )
+        .bind(&agent.id)
+        .bind(&agent.name)
+        .bind(&agent.teams)
+        .bind(&agent.skills)
+        .bind(agent.capacity as i32)
+        .bind(
+            agent
+                .working_hours
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+        )
+        .bind(agent.active)
+        .bind(agent.last_assigned_at.map(|at| at as i64))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn agent(&self, id: &str) -> Result<Option<Agent>> {
+        sqlx::query(&format!(
+            "SELECT {} FROM agents WHERE id = $1",
+            AGENT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(agent_from_row)
+        .transpose()
+    }
+
+    async fn agents(&self) -> Result<Vec<Agent>> {
+        sqlx::query(&format!("SELECT {} FROM agents ORDER BY id", AGENT_COLUMNS))
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(agent_from_row)
+            .collect()
+    }
+
+    async fn save_team(&self, team: &Team) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO teams (id, name, strategy) VALUES ($1, $2, $3)
+             ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name, strategy = EXCLUDED.strategy",
+        )
+        .bind(&team.id)
+        .bind(&team.name)
+        .bind(to_text(&team.strategy)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn team(&self, id: &str) -> Result<Option<Team>> {
+        sqlx::query("SELECT id, name, strategy FROM teams WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(team_from_row)
+            .transpose()
+    }
+
+    async fn teams(&self) -> Result<Vec<Team>> {
+        sqlx::query("SELECT id, name, strategy FROM teams ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(team_from_row)
+            .collect()
+    }
+
+    async fn open_tickets_of(&self, agent_id: &str) -> Result<usize> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM tickets WHERE assignee = $1 AND status NOT IN ($2, $3)",
+        )
+        .bind(agent_id)
+        .bind(to_text(&TicketStatus::Resolved)?)
+        .bind(to_text(&TicketStatus::Closed)?)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count as usize)
+    }
+
+    async fn save_assignment(&self, ticket: &Ticket, agent_id: &str, now: u64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        // Assignments to the agent wait for each other from here
+        let capacity: i32 =
+            sqlx::query_scalar("SELECT capacity FROM agents WHERE id = $1 FOR UPDATE")
+                .bind(agent_id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .with_context(|| format!("Unknown agent {}", agent_id))?;
+        let open_tickets: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM tickets WHERE assignee = $1 AND status NOT IN ($2, $3) AND id <> $4",
+        )
+        .bind(agent_id)
+        .bind(to_text(&TicketStatus::Resolved)?)
+        .bind(to_text(&TicketStatus::Closed)?)
+        .bind(&ticket.id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if open_tickets >= capacity as i64 {
+            return Ok(false);
+        }
+        save_in(&mut tx, ticket).await?;
+        sqlx::query("UPDATE agents SET last_assigned_at = $1 WHERE id = $2")
+            .bind(now as i64)
+            .bind(agent_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::{CommonMessage, LabeledTicket, OutboundMessage};
+    use crate::store::{postgres_url_from_env, ContactFlag};
+
+    async fn repository() -> Result<PostgresTicketRepository> {
+        PostgresTicketRepository::connect(&postgres_url_from_env()).await
+    }
+
+    /// A ticket of its own, the database is shared with the other runs
+    fn ticket(tag: &str, updated_at: u64) -> Ticket {
+        let message = CommonMessage {
+            timestamp: updated_at,
+            ..CommonMessage::test("anna@shop.example", "I was charged twice")
+        };
+        let mut ticket = Ticket::from(LabeledTicket {
+            tags: vec![tag.to_string(), "billing".to_string()],
+            ..LabeledTicket::test(&uuid::Uuid::new_v4().to_string(), message)
+        });
+        ticket
+            .transition(
+                TicketStatus::Triaged,
+                updated_at,
+                Some("routed".to_string()),
+            )
+            .unwrap();
+        let reply = OutboundMessage::reply(&ticket.id, &ticket.messages[0], "Sorry!".to_string());
+        ticket.add_reply(&reply);
+        ticket.updated_at = updated_at;
+        ticket
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at POSTGRES_URL"]
+    async fn test_save_get_and_find() -> Result<()> {
+        let repository = repository().await?;
+        let tag = uuid::Uuid::new_v4().to_string();
+        let older = ticket(&tag, 100);
+        let newer = ticket(&tag, 200);
+        repository.save(&older).await?;
+        repository.save(&newer).await?;
+
+        let loaded = repository.get(&older.id).await?.unwrap();
+        assert_eq!(
+            loaded,
+            Ticket {
+                version: 1,
+                ..older.clone()
+            }
+        );
+        assert_eq!(repository.get("no such ticket").await?, None);
+
+        let ids: Vec<String> = repository
+            .find_by_tag(&tag)
+            .await?
+            .into_iter()
+            .map(|ticket| ticket.id)
+            .collect();
+        assert_eq!(ids, vec![newer.id.clone(), older.id.clone()]);
+        let found = repository.find_by_tag(&tag).await?;
+        assert_eq!(found[0].replies.len(), 1);
+        assert_eq!(found[1].status_history.len(), 2);
+        let recent = repository.find_created_since(150).await?;
+        assert!(recent.iter().any(|ticket| ticket.id == newer.id));
+        assert!(!recent.iter().any(|ticket| ticket.id == older.id));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at POSTGRES_URL"]
+    async fn test_stale_save_is_rejected() -> Result<()> {
+        let repository = repository().await?;
+        let ticket = ticket("stale", 100);
+        repository.save(&ticket).await?;
+
+        let mut first = repository.get(&ticket.id).await?.unwrap();
+        let second = first.clone();
+        first.add_message(CommonMessage::test("anna@shop.example", "Any news?"));
+        repository.save(&first).await?;
+        let err = repository.save(&second).await.unwrap_err();
+        assert!(err.downcast_ref::<StaleTicket>().is_some());
+        // The first change was kept
+        let saved = repository.get(&ticket.id).await?.unwrap();
+        assert_eq!(saved.messages.len(), 2);
+        assert_eq!(saved.version, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at POSTGRES_URL"]
+    async fn test_flags_follow_the_identity() -> Result<()> {
+        let repository = repository().await?;
+        let address = format!("{}@shop.example", uuid::Uuid::new_v4());
+        repository
+            .flag(
+                &format!("Anna <{}>", address.to_uppercase()),
+                ContactFlag::Bounced,
+                Some("550 No such user"),
+            )
+            .await?;
+        assert_eq!(
+            repository.flags(&address).await?,
+            vec![ContactFlag::Bounced]
+        );
+        repository.unflag(&address, ContactFlag::Bounced).await?;
+        assert!(repository.flags(&address).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Postgres at POSTGRES_URL"]
+    async fn test_suggestion_is_reviewed_once() -> Result<()> {
+        let repository = repository().await?;
+        let suggestion = SuggestedReply {
+            id: uuid::Uuid::new_v4().to_string(),
+            ticket_id: "42".to_string(),
+            contact: "anna@shop.example".to_string(),
+            origin: crate::dto::Origin::Email,
+            body: "Your refund is on its way.".to_string(),
+            sources: vec!["refunds.md".to_string()],
+            status: SuggestionStatus::Pending,
+            created_at: 100,
+            llm_usage: None,
+        };
+        repository.save_suggestion(&suggestion).await?;
+        assert_eq!(
+            repository.suggestion(&suggestion.id).await?.as_ref(),
+            Some(&suggestion)
+        );
+
+        assert!(
+            repository
+                .review_suggestion(&suggestion.id, SuggestionStatus::Approved, &suggestion.body)
+                .await?
+        );
+        assert!(
+            !repository
+                .review_suggestion(&suggestion.id, SuggestionStatus::Rejected, &suggestion.body)
+                .await?
+        );
+        assert_eq!(
+            repository.suggestion(&suggestion.id).await?.unwrap().status,
+            SuggestionStatus::Approved
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+//! Replies drafted by reply-suggest-trt, kept until an agent approves, edits or rejects them.
+
+use anyhow::Result;
+
+use crate::dto::{SuggestedReply, SuggestionStatus};
+
+#[allow(async_fn_in_trait)]
+pub trait Suggestions: Send + Sync {
+    async fn save_suggestion(&self, suggestion: &SuggestedReply) -> Result<()>;
+
+    async fn suggestion(&self, id: &str) -> Result<Option<SuggestedReply>>;
+
+    /// Settle a pending suggestion, with the body sent when approved or edited. False when it
+    /// is unknown or was reviewed already: a suggestion is answered once.
+    async fn review_suggestion(
+        &self,
+        id: &str,
+        status: SuggestionStatus,
+        body: &str,
+    ) -> Result<bool>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::Origin;
+    use crate::store::memory::MemoryTicketRepository;
+
+    #[tokio::test]
+    async fn test_suggestion_is_reviewed_once() -> Result<()> {
+        let store = MemoryTicketRepository::default();
+        store
+            .save_suggestion(&SuggestedReply {
+                id: "s1".to_string(),
+                ticket_id: "42".to_string(),
+                contact: "jane@example.com".to_string(),
+                origin: Origin::Email,
+                body: "Your refund is on its way.".to_string(),
+                sources: vec!["refunds.md".to_string()],
+                status: SuggestionStatus::Pending,
+                created_at: 100,
+                llm_usage: None,
+            })
+            .await?;
+
+        assert!(
+            store
+                .review_suggestion("s1", SuggestionStatus::Edited, "Your refund left today.")
+                .await?
+        );
+        let reviewed = store.suggestion("s1").await?.unwrap();
+        assert_eq!(reviewed.status, SuggestionStatus::Edited);
+        assert_eq!(reviewed.body, "Your refund left today.");
+
+        assert!(
+            !store
+                .review_suggestion(
+                    "s1",
+                    SuggestionStatus::Approved,
+                    "Your refund is on its way."
+                )
+                .await?
+        );
+        assert!(
+            !store
+                .review_suggestion("unknown", SuggestionStatus::Rejected, "")
+                .await?
+        );
+        assert_eq!(
+            store.suggestion("s1").await?.unwrap().status,
+            SuggestionStatus::Edited
+        );
+        Ok(())
+    }
+}
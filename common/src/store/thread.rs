@@ -0,0 +1,111 @@
+//! Email threading: the Message-ID of every email received or sent, and the ticket it belongs to.
+//! Replies are attached to their ticket through their `In-Reply-To` and `References` headers,
+//! whatever address they were sent to.
+//!
+//! Chats have no such headers, the conversation itself (Telegram chat, web chat session...) is
+//! the thread key and points at the ticket it currently feeds. Chat keys are kept apart from
+//! Message-IDs, by channel.
+
+use anyhow::Result;
+
+use crate::dto::Origin;
+
+#[allow(async_fn_in_trait)]
+pub trait ThreadIndex: Send + Sync {
+    /// Remember the ticket of a message, by its `normalize`d Message-ID. The first ticket
+    /// recorded for a Message-ID wins.
+    async fn record(&self, message_id: &str, ticket_id: &str) -> Result<()>;
+
+    async fn lookup(&self, message_id: &str) -> Result<Option<String>>;
+
+    /// Point a chat conversation of the channel at its ticket, replacing the ticket it fed before
+    async fn rethread(&self, origin: Origin, thread_key: &str, ticket_id: &str) -> Result<()>;
+
+    /// Ticket a chat conversation of the channel points at
+    async fn chat_thread(&self, origin: Origin, thread_key: &str) -> Result<Option<String>>;
+
+    /// Ticket of the conversation a reply belongs to, looking at the message it answers first,
+    /// then at the rest of the thread from the most recent message
+    async fn find_thread(
+        &self,
+        in_reply_to: Option<&str>,
+        references: &[String],
+    ) -> Result<Option<String>> {
+        let candidates = in_reply_to
+            .into_iter()
+            .chain(references.iter().rev().map(String::as_str));
+        for message_id in candidates {
+            if let Some(ticket_id) = self.lookup(normalize(message_id)).await? {
+                return Ok(Some(ticket_id));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Message-IDs are compared without their angle brackets
+pub fn normalize(message_id: &str) -> &str {
+    message_id
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryTicketRepository;
+
+    #[tokio::test]
+    async fn test_find_thread() -> Result<()> {
+        let index = MemoryTicketRepository::default();
+        index.record("question@example.com", "42").await?;
+        index.record("answer@company.com", "42").await?;
+        index.record("other@example.com", "7").await?;
+        // Already known, stays on its first ticket
+        index.record("answer@company.com", "7").await?;
+
+        let references = vec![
+            "<question@example.com>".to_string(),
+            "<answer@company.com>".to_string(),
+        ];
+        assert_eq!(
+            index
+                .find_thread(Some("<answer@company.com>"), &references)
+                .await?
+                .as_deref(),
+            Some("42")
+        );
+        // In-Reply-To of a message we never saw, e.g. sent from the agent's own mailbox
+        assert_eq!(
+            index
+                .find_thread(Some("<unknown@company.com>"), &references)
+                .await?
+                .as_deref(),
+            Some("42")
+        );
+        assert!(index.find_thread(None, &[]).await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_chat_threads_are_kept_apart() -> Result<()> {
+        let index = MemoryTicketRepository::default();
+        index.record("42", "7").await?;
+        index.rethread(Origin::Telegram, "42", "8").await?;
+        index.rethread(Origin::Signal, "42", "9").await?;
+        index.rethread(Origin::Telegram, "42", "10").await?;
+
+        assert_eq!(index.lookup("42").await?.as_deref(), Some("7"));
+        assert_eq!(
+            index.chat_thread(Origin::Telegram, "42").await?.as_deref(),
+            Some("10")
+        );
+        assert_eq!(
+            index.chat_thread(Origin::Signal, "42").await?.as_deref(),
+            Some("9")
+        );
+        assert_eq!(index.chat_thread(Origin::WebChat, "42").await?, None);
+        Ok(())
+    }
+}
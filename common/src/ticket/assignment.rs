@@ -0,0 +1,250 @@
+//! Assignment of triaged tickets to agents, and the strategies picking the agent
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dto::{AssignmentEvent, TicketEvent};
+use crate::store::agent::Agent;
+use crate::ticket::lifecycle::InvalidTransition;
+use crate::ticket::{Ticket, TicketStatus};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AssignmentStrategy {
+    /// The agent who was assigned a ticket the longest time ago
+    RoundRobin,
+    /// The agent with the fewest open tickets, relative to their capacity
+    #[default]
+    LeastLoaded,
+    /// The agent whose skills cover the most tags of the ticket, least loaded among them
+    SkillMatch,
+}
+
+impl FromStr for AssignmentStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "round-robin" | "roundrobin" => Ok(AssignmentStrategy::RoundRobin),
+            "least-loaded" | "leastloaded" => Ok(AssignmentStrategy::LeastLoaded),
+            "skill-match" | "skillmatch" => Ok(AssignmentStrategy::SkillMatch),
+            _ => anyhow::bail!(
+                "Unknown strategy '{}', expected round-robin, least-loaded or skill-match",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for AssignmentStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AssignmentStrategy::RoundRobin => "round-robin",
+            AssignmentStrategy::LeastLoaded => "least-loaded",
+            AssignmentStrategy::SkillMatch => "skill-match",
+        })
+    }
+}
+
+/// An agent a ticket was given to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub agent_id: String,
+    pub team: Option<String>,
+    /// `None` when an agent was picked by hand
+    pub strategy: Option<AssignmentStrategy>,
+    pub assigned_at: u64,
+}
+
+/// An agent who can take the ticket, with the number of open tickets they have
+pub struct Candidate {
+    pub agent: Agent,
+    pub open_tickets: usize,
+}
+
+impl Candidate {
+    /// Share of the capacity in use, to compare agents of different capacities
+    fn load(&self) -> f64 {
+        self.open_tickets as f64 / self.agent.capacity.max(1) as f64
+    }
+}
+
+/// Agent to give the ticket to. Candidates are expected to be available and under capacity.
+pub fn choose<'a>(
+    strategy: AssignmentStrategy,
+    ticket: &Ticket,
+    candidates: &'a [Candidate],
+) -> Option<&'a Candidate> {
+    let least_loaded = |candidates: &mut dyn Iterator<Item = &'a Candidate>| {
+        candidates.min_by(|a, b| {
+            a.load()
+                .total_cmp(&b.load())
+                .then_with(|| a.agent.id.cmp(&b.agent.id))
+        })
+    };
+    match strategy {
+        // Agents never assigned come first
+        AssignmentStrategy::RoundRobin => candidates
+            .iter()
+            .min_by_key(|candidate| (candidate.agent.last_assigned_at, &candidate.agent.id)),
+        AssignmentStrategy::LeastLoaded => least_loaded(&mut candidates.iter()),
+        AssignmentStrategy::SkillMatch => {
+            let matching_skills = |candidate: &Candidate| {
+                ticket
+                    .tags
+                    .iter()
+                    .filter(|tag| {
+                        candidate
+                            .agent
+                            .skills
+                            .iter()
+                            .any(|skill| skill.eq_ignore_ascii_case(tag))
+                    })
+                    .count()
+            };
+            let best = candidates.iter().map(matching_skills).max()?;
+            // Nobody has the skills: anyone rather than no one
+            least_loaded(
+                &mut candidates
+                    .iter()
+                    .filter(|candidate| matching_skills(candidate) == best),
+            )
+        }
+    }
+}
+
+impl Ticket {
+    /// Give the triaged ticket to the agent, recording it
+    pub fn assign(
+        &mut self,
+        agent_id: &str,
+        strategy: Option<AssignmentStrategy>,
+        assigned_at: u64,
+    ) -> Result<TicketEvent, InvalidTransition> {
+        let event = self.transition(
+            TicketStatus::Assigned,
+            assigned_at,
+            Some(format!("Assigned to {}", agent_id)),
+        )?;
+        self.assignee = Some(agent_id.to_string());
+        self.assignments.push(Assignment {
+            agent_id: agent_id.to_string(),
+            team: self.team.clone(),
+            strategy,
+            assigned_at,
+        });
+        Ok(event)
+    }
+
+    /// Events of the assignment made since `at`, while the ticket is still assigned that way: a
+    /// redelivered triage notifies of it again rather than assigning twice
+    pub fn assigned_since(&self, at: u64) -> Option<(TicketEvent, AssignmentEvent)> {
+        if self.status != TicketStatus::Assigned {
+            return None;
+        }
+        let assignment = self
+            .assignments
+            .last()
+            .filter(|assignment| assignment.assigned_at >= at)?;
+        let [.., before, assigned] = self.status_history.as_slice() else {
+            return None;
+        };
+        Some((
+            TicketEvent {
+                ticket_id: self.id.clone(),
+                from: Some(before.status),
+                to: assigned.status,
+                changed_at: assigned.changed_at,
+                reason: assigned.reason.clone(),
+            },
+            AssignmentEvent {
+                ticket_id: self.id.clone(),
+                assignment: assignment.clone(),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::{CommonMessage, LabeledTicket};
+
+    fn ticket(tags: &[&str]) -> Ticket {
+        let message = CommonMessage::test("anna@shop.example", "I was charged twice");
+        Ticket::from(LabeledTicket {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..LabeledTicket::test("12", message)
+        })
+    }
+
+    fn candidate(id: &str, skills: &[&str], open_tickets: usize, last: Option<u64>) -> Candidate {
+        Candidate {
+            agent: Agent {
+                id: id.to_string(),
+                name: id.to_string(),
+                teams: vec![],
+                skills: skills.iter().map(|skill| skill.to_string()).collect(),
+                capacity: 10,
+                working_hours: None,
+                active: true,
+                last_assigned_at: last,
+            },
+            open_tickets,
+        }
+    }
+
+    #[test]
+    fn test_strategies() {
+        let candidates = [
+            candidate("alice", &["billing"], 6, Some(300)),
+            candidate("bob", &["billing", "refund"], 8, Some(100)),
+            candidate("carol", &[], 2, Some(200)),
+        ];
+        let chosen = |strategy, tags: &[&str]| {
+            choose(strategy, &ticket(tags), &candidates)
+                .map(|candidate| candidate.agent.id.as_str())
+        };
+        assert_eq!(chosen(AssignmentStrategy::RoundRobin, &[]), Some("bob"));
+        assert_eq!(chosen(AssignmentStrategy::LeastLoaded, &[]), Some("carol"));
+        assert_eq!(
+            chosen(AssignmentStrategy::SkillMatch, &["Refund", "billing"]),
+            Some("bob")
+        );
+        assert_eq!(
+            chosen(AssignmentStrategy::SkillMatch, &["billing"]),
+            Some("alice")
+        );
+        assert_eq!(
+            chosen(AssignmentStrategy::SkillMatch, &["delivery"]),
+            Some("carol")
+        );
+        assert!(choose(AssignmentStrategy::LeastLoaded, &ticket(&[]), &[]).is_none());
+        assert_eq!(
+            "Skill_Match".parse::<AssignmentStrategy>().unwrap(),
+            AssignmentStrategy::SkillMatch
+        );
+    }
+
+    #[test]
+    fn test_assign_records_the_assignment() {
+        let mut ticket = ticket(&[]);
+        assert!(ticket.assign("alice", None, 120).is_err());
+
+        ticket.transition(TicketStatus::Triaged, 115, None).unwrap();
+        let event = ticket
+            .assign("alice", Some(AssignmentStrategy::RoundRobin), 120)
+            .unwrap();
+        assert_eq!(event.to, TicketStatus::Assigned);
+        assert_eq!(ticket.assignee.as_deref(), Some("alice"));
+        assert_eq!(ticket.assignments.len(), 1);
+        assert_eq!(ticket.assignments[0].assigned_at, 120);
+
+        let (again, assigned) = ticket.assigned_since(115).unwrap();
+        assert_eq!(again, event);
+        assert_eq!(assigned.assignment.agent_id, "alice");
+        // Assigned before the ticket was triaged again
+        assert!(ticket.assigned_since(125).is_none());
+    }
+}
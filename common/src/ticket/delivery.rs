@@ -0,0 +1,190 @@
+//! Replies sent on a ticket and their delivery progress, as reported by the channels:
+//! queued → sent → delivered → read, or failed / bounced.
+
+use serde::{Deserialize, Serialize};
+
+use crate::dto::{DeliveryStatus, Origin, OutboundMessage};
+use crate::ticket::Ticket;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DeliveryState {
+    /// Waiting for the sender of the channel
+    #[default]
+    Queued,
+    /// Accepted by the SMTP relay or the WhatsApp Cloud API
+    Sent,
+    Delivered,
+    Read,
+    Failed,
+    /// Rejected because the address or number does not exist
+    Bounced,
+}
+
+impl DeliveryState {
+    /// Statuses can arrive out of order: a reply never goes back to an earlier state
+    fn rank(self) -> u8 {
+        match self {
+            DeliveryState::Queued => 0,
+            DeliveryState::Sent => 1,
+            DeliveryState::Delivered => 2,
+            DeliveryState::Read => 3,
+            DeliveryState::Failed => 4,
+            // The DSN comes after the relay accepted the message, and says more than a failure
+            DeliveryState::Bounced => 5,
+        }
+    }
+
+    pub fn is_failure(self) -> bool {
+        matches!(self, DeliveryState::Failed | DeliveryState::Bounced)
+    }
+}
+
+/// A reply sent to the customer, with where it is in its delivery
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SentReply {
+    pub outbound_id: String,
+    pub contact: String,
+    pub origin: Origin,
+    pub body: String,
+    pub created_at: u64,
+    pub state: DeliveryState,
+    pub updated_at: u64,
+    /// Why the delivery failed
+    pub reason: Option<String>,
+}
+
+impl From<&OutboundMessage> for SentReply {
+    fn from(reply: &OutboundMessage) -> Self {
+        SentReply {
+            outbound_id: reply.id.clone(),
+            contact: reply.contact.clone(),
+            origin: reply.origin,
+            body: reply.body.clone(),
+            created_at: reply.created_at,
+            state: DeliveryState::Queued,
+            updated_at: reply.created_at,
+            reason: None,
+        }
+    }
+}
+
+impl Ticket {
+    /// Add a reply to the thread, once: senders may publish it again on retries
+    pub fn add_reply(&mut self, reply: &OutboundMessage) {
+        if let Some(sent) = self
+            .replies
+            .iter_mut()
+            .find(|sent| sent.outbound_id == reply.id)
+        {
+            // Its statuses came first
+            if sent.body.is_empty() {
+                sent.body = reply.body.clone();
+                sent.created_at = reply.created_at;
+            }
+            return;
+        }
+        self.updated_at = self.updated_at.max(reply.created_at);
+        self.replies.push(SentReply::from(reply));
+    }
+
+    /// Record a delivery status of one of the replies. Returns false when it brings nothing new.
+    pub fn apply_delivery(&mut self, status: &DeliveryStatus) -> bool {
+        let position = match self
+            .replies
+            .iter()
+            .position(|sent| sent.outbound_id == status.outbound_id)
+        {
+            Some(position) => position,
+            // The status overtook the reply itself
+            None => {
+                self.replies.push(SentReply {
+                    outbound_id: status.outbound_id.clone(),
+                    contact: status.contact.clone(),
+                    origin: status.origin,
+                    body: String::new(),
+                    created_at: status.at,
+                    state: DeliveryState::Queued,
+                    updated_at: status.at,
+                    reason: None,
+                });
+                self.replies.len() - 1
+            }
+        };
+        let sent = &mut self.replies[position];
+        if status.state.rank() <= sent.state.rank() {
+            return false;
+        }
+        sent.state = status.state;
+        sent.updated_at = status.at;
+        sent.reason = status.reason.clone();
+        self.updated_at = self.updated_at.max(status.at);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::{CommonMessage, LabeledTicket};
+
+    fn status(state: DeliveryState, at: u64) -> DeliveryStatus {
+        DeliveryStatus {
+            outbound_id: "r1".to_string(),
+            ticket_id: "12".to_string(),
+            contact: "+33612345678".to_string(),
+            origin: Origin::WhatsApp,
+            state,
+            at,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn test_delivery_only_moves_forward() {
+        let message = CommonMessage {
+            origin: Origin::WhatsApp,
+            message_id: Some("wamid.A".to_string()),
+            ..CommonMessage::test("+33612345678", "Bonjour")
+        };
+        let mut ticket = Ticket::from(LabeledTicket::test("12", message.clone()));
+        let mut reply = OutboundMessage::reply("12", &message, "Bonjour !".to_string());
+        reply.id = "r1".to_string();
+        reply.created_at = 200;
+
+        ticket.add_reply(&reply);
+        ticket.add_reply(&reply);
+        assert_eq!(ticket.replies.len(), 1);
+        assert_eq!(ticket.replies[0].state, DeliveryState::Queued);
+
+        assert!(ticket.apply_delivery(&status(DeliveryState::Read, 230)));
+        // Delivered was reported after read
+        assert!(!ticket.apply_delivery(&status(DeliveryState::Delivered, 220)));
+        assert_eq!(ticket.replies[0].state, DeliveryState::Read);
+        assert_eq!(ticket.replies[0].updated_at, 230);
+        assert_eq!(ticket.updated_at, 230);
+
+        // A status for a reply the ticket has not seen yet
+        let mut early = status(DeliveryState::Sent, 240);
+        early.outbound_id = "r2".to_string();
+        assert!(ticket.apply_delivery(&early));
+        assert_eq!(ticket.replies[1].state, DeliveryState::Sent);
+        reply.id = "r2".to_string();
+        ticket.add_reply(&reply);
+        assert_eq!(ticket.replies.len(), 2);
+        assert_eq!(ticket.replies[1].body, "Bonjour !");
+        assert_eq!(ticket.replies[1].state, DeliveryState::Sent);
+
+        // The bounce explains a failure reported first, not the other way around
+        assert!(ticket.apply_delivery(&early_as(DeliveryState::Failed, 250)));
+        assert!(ticket.apply_delivery(&early_as(DeliveryState::Bounced, 260)));
+        assert!(!ticket.apply_delivery(&early_as(DeliveryState::Failed, 270)));
+        assert_eq!(ticket.replies[1].state, DeliveryState::Bounced);
+    }
+
+    fn early_as(state: DeliveryState, at: u64) -> DeliveryStatus {
+        DeliveryStatus {
+            outbound_id: "r2".to_string(),
+            ..status(state, at)
+        }
+    }
+}
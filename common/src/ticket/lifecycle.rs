@@ -0,0 +1,192 @@
+//! Ticket statuses and the transitions allowed between them:
+//! new → triaged → assigned ⇄ waiting-on-customer → resolved → closed, with reopen.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dto::TicketEvent;
+use crate::ticket::{StatusChange, Ticket};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TicketStatus {
+    #[default]
+    New,
+    Triaged,
+    Assigned,
+    WaitingOnCustomer,
+    Resolved,
+    Closed,
+}
+
+impl TicketStatus {
+    pub fn can_transition_to(self, to: TicketStatus) -> bool {
+        use TicketStatus::*;
+        match (self, to) {
+            (New, Triaged) => true,
+            // Triaged also covers un-assigning a ticket
+            (Triaged, Assigned) | (Assigned, Triaged) => true,
+            (Assigned, WaitingOnCustomer) | (WaitingOnCustomer, Assigned) => true,
+            (Assigned | WaitingOnCustomer, Resolved) => true,
+            // Closing is allowed from anywhere, e.g. spam or a ticket merged into another
+            (from, Closed) => from != Closed,
+            // Reopen
+            (Resolved | Closed, Triaged) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether someone still has to act on the ticket
+    pub fn is_open(self) -> bool {
+        !matches!(self, TicketStatus::Resolved | TicketStatus::Closed)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidTransition {
+    pub ticket_id: String,
+    pub from: TicketStatus,
+    pub to: TicketStatus,
+}
+
+impl fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Ticket {} cannot go from {:?} to {:?}",
+            self.ticket_id, self.from, self.to
+        )
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+impl Ticket {
+    /// Move the ticket to another status, recording it in the history
+    pub fn transition(
+        &mut self,
+        to: TicketStatus,
+        changed_at: u64,
+        reason: Option<String>,
+    ) -> Result<TicketEvent, InvalidTransition> {
+        let from = self.status;
+        if !from.can_transition_to(to) {
+            return Err(InvalidTransition {
+                ticket_id: self.id.clone(),
+                from,
+                to,
+            });
+        }
+
+        self.status = to;
+        // Un-assigned or reopened, waiting for an agent again
+        if to == TicketStatus::Triaged {
+            self.assignee = None;
+        }
+        self.updated_at = self.updated_at.max(changed_at);
+        self.status_history.push(StatusChange {
+            status: to,
+            changed_at,
+            reason: reason.clone(),
+        });
+        Ok(TicketEvent {
+            ticket_id: self.id.clone(),
+            from: Some(from),
+            to,
+            changed_at,
+            reason,
+        })
+    }
+
+    /// A follow-up from the customer puts a ticket waiting on them back in the agent's hands, and
+    /// reopens a finished one
+    pub fn on_customer_reply(&mut self, replied_at: u64) -> Option<TicketEvent> {
+        let (to, reason) = match self.status {
+            TicketStatus::WaitingOnCustomer => (TicketStatus::Assigned, "Customer replied"),
+            TicketStatus::Resolved | TicketStatus::Closed => {
+                (TicketStatus::Triaged, "Reopened by a customer reply")
+            }
+            _ => return None,
+        };
+        self.transition(to, replied_at, Some(reason.to_string()))
+            .ok()
+    }
+
+    /// Event announcing a ticket that was just created
+    pub fn created_event(&self) -> TicketEvent {
+        TicketEvent {
+            ticket_id: self.id.clone(),
+            from: None,
+            to: self.status,
+            changed_at: self.created_at,
+            reason: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::{CommonMessage, LabeledTicket};
+
+    fn ticket() -> Ticket {
+        let message = CommonMessage::test("jane@example.com", "My order never arrived");
+        Ticket::from(LabeledTicket {
+            tags: vec!["delivery".to_string()],
+            ..LabeledTicket::test("7", message)
+        })
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut ticket = ticket();
+        assert_eq!(ticket.status, TicketStatus::New);
+
+        // Assigning requires triage first
+        let err = ticket
+            .transition(TicketStatus::Assigned, 110, None)
+            .unwrap_err();
+        assert_eq!(err.from, TicketStatus::New);
+
+        for (at, status) in [
+            (110, TicketStatus::Triaged),
+            (120, TicketStatus::Assigned),
+            (130, TicketStatus::WaitingOnCustomer),
+        ] {
+            let event = ticket.transition(status, at, None).unwrap();
+            assert_eq!(event.to, status);
+        }
+
+        let event = ticket.on_customer_reply(140).unwrap();
+        assert_eq!(event.from, Some(TicketStatus::WaitingOnCustomer));
+        assert_eq!(ticket.status, TicketStatus::Assigned);
+        assert!(ticket.on_customer_reply(150).is_none());
+
+        ticket
+            .transition(TicketStatus::Resolved, 160, None)
+            .unwrap();
+        ticket.transition(TicketStatus::Closed, 170, None).unwrap();
+        assert!(!ticket.status.is_open());
+        // Reopen
+        ticket.transition(TicketStatus::Triaged, 180, None).unwrap();
+        assert_eq!(ticket.status_history.len(), 8);
+        assert_eq!(ticket.updated_at, 180);
+
+        // The customer writes again once the ticket is finished
+        ticket
+            .transition(TicketStatus::Assigned, 190, None)
+            .unwrap();
+        ticket
+            .transition(TicketStatus::Resolved, 200, None)
+            .unwrap();
+        let event = ticket.on_customer_reply(210).unwrap();
+        assert_eq!(event.from, Some(TicketStatus::Resolved));
+        assert_eq!(event.to, TicketStatus::Triaged);
+        ticket.transition(TicketStatus::Closed, 220, None).unwrap();
+        assert_eq!(
+            ticket.on_customer_reply(230).unwrap().to,
+            TicketStatus::Triaged
+        );
+        assert!(ticket.on_customer_reply(240).is_none());
+    }
+}
@@ -0,0 +1,267 @@
+//! The ticket aggregate: a labeled conversation with a contact, made of one or more messages.
+
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::dto::{CommonMessage, LabeledTicket, Origin, TicketEvent};
+
+pub mod assignment;
+pub mod delivery;
+pub mod lifecycle;
+pub mod routing;
+
+pub use assignment::{Assignment, AssignmentStrategy};
+pub use delivery::{DeliveryState, SentReply};
+pub use lifecycle::TicketStatus;
+pub use routing::{Priority, Routing};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatusChange {
+    pub status: TicketStatus,
+    pub changed_at: u64,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ticket {
+    pub id: String,
+    pub contact: String,
+    pub origin: Origin,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub description: String,
+    /// Messages of the conversation, oldest first
+    pub messages: Vec<CommonMessage>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Set once the ticket was merged: the id now only redirects to this ticket
+    #[serde(default)]
+    pub merged_into: Option<String>,
+    #[serde(default)]
+    pub status: TicketStatus,
+    /// Every status the ticket went through, oldest first
+    #[serde(default)]
+    pub status_history: Vec<StatusChange>,
+    /// Replies sent to the customer, oldest first
+    #[serde(default)]
+    pub replies: Vec<SentReply>,
+    #[serde(default)]
+    pub priority: Priority,
+    /// Team queue the ticket was routed to
+    #[serde(default)]
+    pub team: Option<String>,
+    /// Agent working on the ticket
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// Every agent the ticket was given to, oldest first
+    #[serde(default)]
+    pub assignments: Vec<Assignment>,
+    /// Number of times the ticket was saved, when it was loaded. A save fails when it moved
+    /// since, the change is made again on the fresh ticket.
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl From<LabeledTicket> for Ticket {
+    fn from(labeled: LabeledTicket) -> Self {
+        Ticket {
+            id: labeled.id,
+            contact: labeled.original_message.contact.clone(),
+            origin: labeled.original_message.origin,
+            title: labeled.title,
+            tags: labeled.tags,
+            description: labeled.description,
+            created_at: labeled.original_message.timestamp,
+            updated_at: labeled.labeled_at,
+            messages: vec![labeled.original_message],
+            merged_into: None,
+            status: TicketStatus::New,
+            status_history: vec![StatusChange {
+                status: TicketStatus::New,
+                changed_at: labeled.labeled_at,
+                reason: None,
+            }],
+            replies: vec![],
+            priority: Priority::Normal,
+            team: None,
+            assignee: None,
+            assignments: vec![],
+            version: 0,
+        }
+    }
+}
+
+impl Ticket {
+    pub fn is_tombstone(&self) -> bool {
+        self.merged_into.is_some()
+    }
+
+    /// Whether the conversation holds the message already, by its id on the channel. Messages
+    /// without an id are never taken for one another.
+    pub fn has_message(&self, message: &CommonMessage) -> bool {
+        message.message_id.as_ref().is_some_and(|id| {
+            self.messages
+                .iter()
+                .any(|held| held.message_id.as_ref() == Some(id))
+        })
+    }
+
+    /// Add a message to the conversation, keeping messages in chronological order
+    pub fn add_message(&mut self, message: CommonMessage) {
+        self.updated_at = self.updated_at.max(message.timestamp);
+        let pos = self
+            .messages
+            .partition_point(|m| m.timestamp <= message.timestamp);
+        self.messages.insert(pos, message);
+    }
+}
+
+/// A merge no retry would make possible, e.g. of an unknown or a closed ticket
+#[derive(Debug, PartialEq)]
+pub struct InvalidMerge(pub String);
+
+impl fmt::Display for InvalidMerge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidMerge {}
+
+/// Move the messages, replies and tags of `source` into `target`, and turn `source` into a closed
+/// redirect. Those `target` holds already are not moved twice.
+pub fn merge(source: &mut Ticket, target: &mut Ticket) -> Result<TicketEvent> {
+    if source.id == target.id {
+        return Err(InvalidMerge(format!("Cannot merge ticket {} into itself", source.id)).into());
+    }
+    if let Some(redirect) = &source.merged_into {
+        return Err(InvalidMerge(format!(
+            "Ticket {} was already merged into {}",
+            source.id, redirect
+        ))
+        .into());
+    }
+    if let Some(redirect) = &target.merged_into {
+        return Err(InvalidMerge(format!(
+            "Ticket {} was merged into {}, merge into that one instead",
+            target.id, redirect
+        ))
+        .into());
+    }
+    if !source.status.can_transition_to(TicketStatus::Closed) {
+        return Err(InvalidMerge(format!(
+            "Ticket {} is closed, reopen it before merging",
+            source.id
+        ))
+        .into());
+    }
+
+    // A merge retried after the target was saved finds part of the source in it already
+    for message in source.messages.drain(..) {
+        if !target.messages.contains(&message) {
+            target.add_message(message);
+        }
+    }
+    for reply in source.replies.drain(..) {
+        if !target
+            .replies
+            .iter()
+            .any(|moved| moved.outbound_id == reply.outbound_id)
+        {
+            target.replies.push(reply);
+        }
+    }
+    for tag in source.tags.drain(..) {
+        if !target.tags.contains(&tag) {
+            target.tags.push(tag);
+        }
+    }
+    target.created_at = target.created_at.min(source.created_at);
+    source.merged_into = Some(target.id.clone());
+    let event = source.transition(
+        TicketStatus::Closed,
+        now(),
+        Some(format!("Merged into {}", target.id)),
+    )?;
+    Ok(event)
+}
+
+pub(crate) fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket(id: &str, timestamps: &[u64], tags: &[&str]) -> Ticket {
+        let messages: Vec<CommonMessage> = timestamps
+            .iter()
+            .map(|&timestamp| CommonMessage {
+                timestamp,
+                ..CommonMessage::test("jane@example.com", &format!("message at {}", timestamp))
+            })
+            .collect();
+        Ticket {
+            id: id.to_string(),
+            contact: "jane@example.com".to_string(),
+            origin: Origin::Email,
+            title: format!("Ticket {}", id),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            description: String::new(),
+            created_at: timestamps[0],
+            updated_at: *timestamps.last().unwrap(),
+            messages,
+            merged_into: None,
+            status: TicketStatus::New,
+            status_history: vec![],
+            replies: vec![],
+            priority: Priority::Normal,
+            team: None,
+            assignee: None,
+            assignments: vec![],
+            version: 0,
+        }
+    }
+
+    #[test]
+    fn test_merge_moves_messages_and_leaves_redirect() -> Result<()> {
+        let mut source = ticket("2", &[150, 300], &["billing", "refund"]);
+        let mut target = ticket("1", &[100, 200], &["billing"]);
+
+        merge(&mut source, &mut target)?;
+
+        let timestamps: Vec<u64> = target.messages.iter().map(|m| m.timestamp).collect();
+        assert_eq!(timestamps, vec![100, 150, 200, 300]);
+        assert_eq!(target.tags, vec!["billing", "refund"]);
+        assert_eq!(target.updated_at, 300);
+        assert_eq!(target.status, TicketStatus::New);
+        assert!(source.messages.is_empty());
+        assert_eq!(source.merged_into.as_deref(), Some("1"));
+        assert_eq!(source.status, TicketStatus::Closed);
+
+        // A tombstone can be neither merged again nor merged into
+        let mut other = ticket("3", &[400], &[]);
+        assert!(merge(&mut source, &mut other).is_err());
+        assert!(merge(&mut other, &mut source).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_done_again_moves_only_new_messages() -> Result<()> {
+        let mut target = ticket("1", &[100], &[]);
+        merge(&mut ticket("2", &[150], &[]), &mut target)?;
+
+        // The source was not saved, and got a follow-up meanwhile
+        merge(&mut ticket("2", &[150, 250], &[]), &mut target)?;
+
+        let timestamps: Vec<u64> = target.messages.iter().map(|m| m.timestamp).collect();
+        assert_eq!(timestamps, vec![100, 150, 250]);
+        Ok(())
+    }
+}
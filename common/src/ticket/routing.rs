@@ -0,0 +1,107 @@
+//! Routing decided by ticket-router after labeling: the team queue a ticket lands in, its
+//! priority, and whether it can be closed right away.
+
+use serde::{Deserialize, Serialize};
+
+use crate::dto::TicketEvent;
+use crate::ticket::{Ticket, TicketStatus};
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+/// What the routing rules decided for a labeled message, `None` fields are left as they are
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Routing {
+    pub team: Option<String>,
+    pub priority: Option<Priority>,
+    /// Reason to close the ticket with, e.g. a newsletter nobody has to answer
+    pub close: Option<String>,
+    /// Names of the rules that matched, in the order they were applied
+    #[serde(default)]
+    pub rules: Vec<String>,
+}
+
+impl Ticket {
+    /// Apply the routing of the message that opened the ticket
+    pub fn apply_routing(&mut self, routing: &Routing, at: u64) -> Option<TicketEvent> {
+        if let Some(team) = &routing.team {
+            self.team = Some(team.clone());
+        }
+        if let Some(priority) = routing.priority {
+            self.priority = priority;
+        }
+        let reason = routing.close.as_ref()?;
+        // Already closed
+        self.transition(TicketStatus::Closed, at, Some(reason.clone()))
+            .ok()
+    }
+
+    /// Apply the routing of a follow-up: the ticket stays with its team and in its status, an
+    /// urgent follow-up only raises its priority
+    pub fn escalate(&mut self, routing: &Routing) {
+        if let Some(priority) = routing.priority {
+            self.priority = self.priority.max(priority);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::{CommonMessage, LabeledTicket};
+
+    fn labeled(routing: Routing) -> LabeledTicket {
+        let message = CommonMessage::test("anna@shop.example", "I was charged twice");
+        LabeledTicket {
+            tags: vec!["billing".to_string()],
+            routing: Some(routing),
+            ..LabeledTicket::test("12", message)
+        }
+    }
+
+    #[test]
+    fn test_apply_routing() {
+        let routing = Routing {
+            team: Some("billing".to_string()),
+            priority: Some(Priority::High),
+            ..Default::default()
+        };
+        let mut ticket = Ticket::from(labeled(routing.clone()));
+        assert_eq!(ticket.apply_routing(&routing, 110), None);
+        assert_eq!(ticket.team.as_deref(), Some("billing"));
+        assert_eq!(ticket.priority, Priority::High);
+
+        // A follow-up matching a lower priority rule of another team, with a footer
+        ticket.escalate(&Routing {
+            team: Some("support".to_string()),
+            priority: Some(Priority::Low),
+            close: Some("Newsletter".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(ticket.priority, Priority::High);
+        assert_eq!(ticket.team.as_deref(), Some("billing"));
+        assert_eq!(ticket.status, TicketStatus::New);
+        ticket.escalate(&Routing {
+            priority: Some(Priority::Urgent),
+            ..Default::default()
+        });
+        assert_eq!(ticket.priority, Priority::Urgent);
+
+        let newsletter = Routing {
+            close: Some("Newsletter".to_string()),
+            ..Default::default()
+        };
+        let mut ticket = Ticket::from(labeled(newsletter.clone()));
+        let event = ticket.apply_routing(&newsletter, 200);
+        assert_eq!(event.unwrap().to, TicketStatus::Closed);
+        assert_eq!(ticket.apply_routing(&newsletter, 300), None);
+    }
+}
@@ -0,0 +1,281 @@
+//! Checks applied when channel messages are converted to `CommonMessage`. A message failing them
+//! can never be processed, the channel normalizers move it to the DLQ with the reason.
+
+use std::fmt;
+
+use crate::store::contact::normalize_phone;
+use crate::ticket;
+
+/// Larger bodies are not support requests, e.g. a forwarded mailbox export
+pub const MAX_BODY_BYTES: usize = 256 * 1024;
+/// Maximum line length of RFC 5322, which a subject fits in
+pub const MAX_SUBJECT_CHARS: usize = 998;
+pub const MAX_ATTACHMENTS: usize = 32;
+/// Clocks of the channels may be a bit ahead of ours
+pub const MAX_CLOCK_SKEW_SECS: u64 = 300;
+/// 2000-01-01, anything older is a timestamp in the wrong unit or a default value
+pub const MIN_TIMESTAMP: u64 = 946_684_800;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// Neither text nor attachment
+    EmptyBody,
+    TooLong {
+        field: &'static str,
+        len: usize,
+        max: usize,
+    },
+    TooManyAttachments(usize),
+    /// Not an E.164 phone number
+    InvalidPhone(String),
+    /// Not an RFC 5322 address
+    InvalidAddress(String),
+    InvalidContact(String),
+    TimestampInFuture(u64),
+    TimestampTooOld(u64),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::EmptyBody => write!(f, "Message without text nor attachment"),
+            ValidationError::TooLong { field, len, max } => {
+                write!(f, "The {} is too long ({} > {})", field, len, max)
+            }
+            ValidationError::TooManyAttachments(count) => {
+                write!(f, "Too many attachments ({} > {})", count, MAX_ATTACHMENTS)
+            }
+            ValidationError::InvalidPhone(phone) => {
+                write!(f, "'{}' is not an E.164 phone number", phone)
+            }
+            ValidationError::InvalidAddress(address) => {
+                write!(f, "'{}' is not a valid email address", address)
+            }
+            ValidationError::InvalidContact(contact) => {
+                write!(f, "'{}' is not a valid contact", contact)
+            }
+            ValidationError::TimestampInFuture(timestamp) => {
+                write!(f, "Timestamp {} is in the future", timestamp)
+            }
+            ValidationError::TimestampTooOld(timestamp) => {
+                write!(f, "Timestamp {} is too old", timestamp)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Phone number in its E.164 form
+pub fn phone(raw: &str) -> Result<String, ValidationError> {
+    normalize_phone(raw).ok_or_else(|| ValidationError::InvalidPhone(raw.to_string()))
+}
+
+/// Address trimmed and lowercased, once its syntax is checked
+pub fn email_address(raw: &str) -> Result<String, ValidationError> {
+    let address = raw.trim();
+    if !is_valid_address(address) {
+        return Err(ValidationError::InvalidAddress(raw.to_string()));
+    }
+    Ok(address.to_lowercase())
+}
+
+/// `addr-spec` of RFC 5322: a dot-atom or quoted local part, and a dot-atom or literal domain.
+/// Obsolete forms and comments are not accepted, lengths are bounded as in RFC 5321 and domain
+/// names need a dot.
+pub fn is_valid_address(address: &str) -> bool {
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return false;
+    };
+    if address.len() > 254 || local.len() > 64 {
+        return false;
+    }
+    let local_valid = match local
+        .strip_prefix('"')
+        .and_then(|quoted| quoted.strip_suffix('"'))
+    {
+        Some(quoted) => is_quoted_content(quoted),
+        None => is_dot_atom(local),
+    };
+    let domain_valid = match domain
+        .strip_prefix('[')
+        .and_then(|literal| literal.strip_suffix(']'))
+    {
+        Some(literal) => {
+            !literal.is_empty()
+                && literal
+                    .chars()
+                    .all(|c| c.is_ascii_graphic() && !matches!(c, '[' | ']' | '\\'))
+        }
+        None => {
+            domain.contains('.')
+                && domain.split('.').all(|label| {
+                    !label.is_empty()
+                        && !label.starts_with('-')
+                        && !label.ends_with('-')
+                        && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+                })
+        }
+    };
+    local_valid && domain_valid
+}
+
+/// Non-ASCII characters are allowed, as in RFC 6532
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c) || !c.is_ascii()
+}
+
+fn is_dot_atom(text: &str) -> bool {
+    text.split('.')
+        .all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+}
+
+fn is_quoted_content(text: &str) -> bool {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // Quoted pair
+            '\\' if chars.next().is_none() => return false,
+            '\\' => {}
+            '"' => return false,
+            c if c.is_control() => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+/// A body may only be empty when attachments come with it, e.g. a photo without caption
+pub fn body(body: &str, attachments: usize) -> Result<(), ValidationError> {
+    if body.trim().is_empty() && attachments == 0 {
+        return Err(ValidationError::EmptyBody);
+    }
+    if body.len() > MAX_BODY_BYTES {
+        return Err(ValidationError::TooLong {
+            field: "body",
+            len: body.len(),
+            max: MAX_BODY_BYTES,
+        });
+    }
+    if attachments > MAX_ATTACHMENTS {
+        return Err(ValidationError::TooManyAttachments(attachments));
+    }
+    Ok(())
+}
+
+pub fn subject(subject: Option<&str>) -> Result<(), ValidationError> {
+    let len = subject.map_or(0, |subject| subject.chars().count());
+    if len > MAX_SUBJECT_CHARS {
+        return Err(ValidationError::TooLong {
+            field: "subject",
+            len,
+            max: MAX_SUBJECT_CHARS,
+        });
+    }
+    Ok(())
+}
+
+/// Unix seconds, between 2000 and a few minutes from now
+pub fn timestamp(timestamp: u64) -> Result<u64, ValidationError> {
+    if timestamp < MIN_TIMESTAMP {
+        return Err(ValidationError::TimestampTooOld(timestamp));
+    }
+    if timestamp > ticket::now() + MAX_CLOCK_SKEW_SECS {
+        return Err(ValidationError::TimestampInFuture(timestamp));
+    }
+    Ok(timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::{CommonMessage, EmailMessage};
+
+    #[test]
+    fn test_address_syntax() {
+        for valid in [
+            "anna@shop.example",
+            "Anna.Martin+orders@shop.example",
+            "o'neil@mail.example.co.uk",
+            "\"anna martin\"@shop.example",
+            "user@[192.0.2.1]",
+            "renée@exemple.fr",
+        ] {
+            assert!(is_valid_address(valid), "{} should be valid", valid);
+        }
+        for invalid in [
+            "anna",
+            "anna@",
+            "@shop.example",
+            "anna@@shop.example",
+            "anna..martin@shop.example",
+            ".anna@shop.example",
+            "anna martin@shop.example",
+            "anna@shop",
+            "anna@-shop.example",
+            "Anna <anna@shop.example>",
+        ] {
+            assert!(!is_valid_address(invalid), "{} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(body("  ", 0), Err(ValidationError::EmptyBody));
+        assert_eq!(body("", 1), Ok(()));
+        assert!(matches!(
+            body(&"a".repeat(MAX_BODY_BYTES + 1), 0),
+            Err(ValidationError::TooLong { field: "body", .. })
+        ));
+        assert_eq!(
+            body("Hello", MAX_ATTACHMENTS + 1),
+            Err(ValidationError::TooManyAttachments(MAX_ATTACHMENTS + 1))
+        );
+        // Milliseconds
+        assert!(matches!(
+            timestamp(1_760_781_600_000),
+            Err(ValidationError::TimestampInFuture(_))
+        ));
+        assert_eq!(timestamp(0), Err(ValidationError::TimestampTooOld(0)));
+        assert_eq!(phone("33612345678").as_deref(), Ok("+33612345678"));
+        assert_eq!(
+            phone("06 12 34 56 78"),
+            Err(ValidationError::InvalidPhone("06 12 34 56 78".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_email_conversion() {
+        let email = |from: &str, to: &str| EmailMessage {
+            from: from.to_string(),
+            to: to.to_string(),
+            content: "Where is my parcel?".to_string(),
+            timestamp: 1772000000,
+            attachments: vec![],
+            subject: None,
+            cc: vec![],
+            message_id: None,
+            in_reply_to: None,
+            references: vec![],
+            headers: Default::default(),
+        };
+
+        let mut message =
+            CommonMessage::try_from(email(" Anna@Shop.example", "support+42@company.com")).unwrap();
+        assert_eq!(message.contact, "anna@shop.example");
+        assert_eq!(message.ticket_hint.as_deref(), Some("42"));
+        assert_eq!(message.assign_ticket_id(), "42");
+        // Not a ticket id
+        let mut message =
+            CommonMessage::try_from(email("anna@shop.example", "support+a@b@company.com")).unwrap();
+        assert_eq!(message.ticket_hint, None);
+        // A new ticket, the hint stays empty
+        let ticket_id = message.assign_ticket_id();
+        assert_eq!(message.ticket_id, Some(ticket_id));
+        assert_eq!(message.ticket_hint, None);
+        assert_eq!(
+            CommonMessage::try_from(email("anna", "support@company.com")).unwrap_err(),
+            ValidationError::InvalidAddress("anna".to_string())
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! The few IMAP4rev1 commands (RFC 3501) the poller needs, with MOVE (RFC 6851) and UIDPLUS
+//! (RFC 4315) when offered.
+
+use anyhow::{Context, Result, bail};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Largest literal accepted from the server, i.e. the largest message fetched
+const MAX_LITERAL: usize = 50 * 1024 * 1024;
+const MAX_LINE: u64 = 64 * 1024;
+
+/// An untagged response: its text, with the content of literals in order
+#[derive(Debug, Default)]
+struct Untagged {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+pub struct ImapClient<S> {
+    stream: BufReader<S>,
+    next_tag: u32,
+    capabilities: Vec<String>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ImapClient<S> {
+    /// Read the server greeting and its capabilities
+    pub async fn connect(stream: S) -> Result<Self> {
+        let mut client = ImapClient {
+            stream: BufReader::new(stream),
+            next_tag: 1,
+            capabilities: vec![],
+        };
+        let greeting = client.read_response_line().await?;
+        if !greeting.text.starts_with("* OK") && !greeting.text.starts_with("* PREAUTH") {
+            bail!("Unexpected IMAP greeting: {}", greeting.text);
+        }
+        Ok(client)
+    }
+
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<()> {
+        self.command(&format!("LOGIN {} {}", quote(username), quote(password)))
+            .await
+            .context("IMAP login failed")?;
+        // Servers may advertise more once authenticated
+        let capabilities = self.command("CAPABILITY").await?;
+        self.capabilities = capabilities
+            .iter()
+            .filter_map(|response| response.text.strip_prefix("* CAPABILITY "))
+            .flat_map(|list| list.split_whitespace().map(str::to_ascii_uppercase))
+            .collect();
+        Ok(())
+    }
+
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(capability))
+    }
+
+    /// Open a folder read-write and return its UIDVALIDITY
+    pub async fn select(&mut self, folder: &str) -> Result<u32> {
+        let responses = self.command(&format!("SELECT {}", quote(folder))).await?;
+        responses
+            .iter()
+            .find_map(|response| {
+                let rest = response.text.split("[UIDVALIDITY ").nth(1)?;
+                rest.split(']').next()?.trim().parse().ok()
+            })
+            .with_context(|| format!("No UIDVALIDITY selecting {}", folder))
+    }
+
+    pub async fn uid_search(&mut self, criteria: &str) -> Result<Vec<u32>> {
+        let responses = self.command(&format!("UID SEARCH {}", criteria)).await?;
+        Ok(responses
+            .iter()
+            .filter_map(|response| response.text.strip_prefix("* SEARCH"))
+            .flat_map(|uids| uids.split_whitespace().filter_map(|uid| uid.parse().ok()))
+            .collect())
+    }
+
+    /// Whole RFC 5322 message, without setting `\Seen`. `None` when the UID does not exist.
+    pub async fn uid_fetch(&mut self, uid: u32) -> Result<Option<Vec<u8>>> {
+        let responses = self
+            .command(&format!("UID FETCH {} BODY.PEEK[]", uid))
+            .await?;
+        Ok(responses
+            .into_iter()
+            .find(|response| response.text.contains(" FETCH ("))
+            .and_then(|response| response.literals.into_iter().next()))
+    }
+
+    pub async fn uid_store_flag(&mut self, uid: u32, flag: &str) -> Result<()> {
+        self.command(&format!("UID STORE {} +FLAGS.SILENT ({})", uid, flag))
+            .await?;
+        Ok(())
+    }
+
+    /// Move a message to another folder, by copy and delete on servers without MOVE. Without
+    /// UIDPLUS either, the copied message is only flagged `\Deleted`: a bare EXPUNGE would also
+    /// remove what others flagged in a shared mailbox, expunging is left to its owner.
+    pub async fn uid_move(&mut self, uid: u32, folder: &str) -> Result<()> {
+        if self.supports("MOVE") {
+            self.command(&format!("UID MOVE {} {}", uid, quote(folder)))
+                .await?;
+        } else {
+            self.command(&format!("UID COPY {} {}", uid, quote(folder)))
+                .await?;
+            self.uid_store_flag(uid, "\\Deleted").await?;
+            if self.supports("UIDPLUS") {
+                self.command(&format!("UID EXPUNGE {}", uid)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn logout(&mut self) -> Result<()> {
+        self.command("LOGOUT").await?;
+        Ok(())
+    }
+
+    /// Send a command and collect the untagged responses until its completion, which must be OK
+    async fn command(&mut self, command: &str) -> Result<Vec<Untagged>> {
+        let tag = format!("A{:04}", self.next_tag);
+        self.next_tag += 1;
+        self.stream
+            .write_all(format!("{} {}\r\n", tag, command).as_bytes())
+            .await?;
+        self.stream.flush().await?;
+
+        let mut responses = Vec::new();
+        loop {
+            let response = self.read_response_line().await?;
+            if let Some(status) = response.text.strip_prefix(&format!("{} ", tag)) {
+                if status.starts_with("OK") {
+                    return Ok(responses);
+                }
+                // Keep credentials out of the error
+                let verb = command.split(' ').next().unwrap_or_default();
+                bail!("IMAP {} failed: {}", verb, status);
+            }
+            responses.push(response);
+        }
+    }
+
+    /// One response, following the literals (`{<size>}` at the end of a line) it contains
+    async fn read_response_line(&mut self) -> Result<Untagged> {
+        let mut response = Untagged::default();
+        loop {
+            let mut line = Vec::new();
+            let read = (&mut self.stream)
+                .take(MAX_LINE)
+                .read_until(b'\n', &mut line)
+                .await?;
+            if read == 0 {
+                bail!("IMAP server closed the connection");
+            }
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            response.text.push_str(line);
+
+            let Some(size) = literal_size(line) else {
+                return Ok(response);
+            };
+            if size > MAX_LITERAL {
+                bail!("IMAP literal of {} bytes is too large", size);
+            }
+            let mut literal = vec![0; size];
+            self.stream.read_exact(&mut literal).await?;
+            response.literals.push(literal);
+        }
+    }
+}
+
+/// Size announced by a line ending with `{123}`
+fn literal_size(line: &str) -> Option<usize> {
+    let open = line.strip_suffix('}')?.rfind('{')?;
+    line[open + 1..line.len() - 1].parse().ok()
+}
+
+/// IMAP quoted string
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
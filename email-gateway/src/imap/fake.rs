@@ -0,0 +1,196 @@
+//! In-process IMAP server with just enough of the protocol for the poller: LOGIN, CAPABILITY,
+//! SELECT, UID SEARCH/FETCH/STORE/MOVE/COPY/EXPUNGE, EXPUNGE and LOGOUT.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+pub struct FakeMessage {
+    pub uid: u32,
+    pub flags: Vec<String>,
+    pub data: Vec<u8>,
+}
+
+pub struct FakeFolder {
+    pub uid_validity: u32,
+    pub next_uid: u32,
+    pub messages: Vec<FakeMessage>,
+}
+
+impl FakeFolder {
+    pub fn new(uid_validity: u32) -> Self {
+        FakeFolder {
+            uid_validity,
+            next_uid: 1,
+            messages: vec![],
+        }
+    }
+
+    pub fn append(&mut self, data: &[u8]) {
+        self.messages.push(FakeMessage {
+            uid: self.next_uid,
+            flags: vec![],
+            data: data.to_vec(),
+        });
+        self.next_uid += 1;
+    }
+}
+
+#[derive(Default)]
+pub struct FakeServer {
+    pub folders: HashMap<String, FakeFolder>,
+    pub supports_move: bool,
+    pub supports_uidplus: bool,
+}
+
+pub type Shared = Arc<Mutex<FakeServer>>;
+
+/// Open a connection to the server
+pub fn connect(server: Shared) -> DuplexStream {
+    let (client, server_side) = tokio::io::duplex(1 << 20);
+    tokio::spawn(serve(server, server_side));
+    client
+}
+
+fn unquote(value: &str) -> String {
+    value.trim_matches('"').to_string()
+}
+
+async fn serve(server: Shared, stream: DuplexStream) -> anyhow::Result<()> {
+    let mut stream = BufReader::new(stream);
+    stream.write_all(b"* OK fake IMAP ready\r\n").await?;
+    let mut selected: Option<String> = None;
+
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        let line = line.trim_end();
+        let (tag, command) = line.split_once(' ').unwrap_or((line, ""));
+        let words: Vec<&str> = command.split(' ').collect();
+        let mut out: Vec<u8> = Vec::new();
+        let mut status = "OK done".to_string();
+        {
+            let mut server = server.lock().unwrap();
+            let supports_move = server.supports_move;
+            let supports_uidplus = server.supports_uidplus;
+            match words[..] {
+                ["LOGIN", _, "\"wrong\""] => status = "NO invalid credentials".to_string(),
+                ["LOGIN", ..] => {}
+                ["CAPABILITY"] => {
+                    let mut capabilities = "IMAP4rev1".to_string();
+                    if supports_move {
+                        capabilities.push_str(" MOVE");
+                    }
+                    if supports_uidplus {
+                        capabilities.push_str(" UIDPLUS");
+                    }
+                    out.extend(format!("* CAPABILITY {}\r\n", capabilities).bytes());
+                }
+                ["SELECT", folder] => match server.folders.get(&unquote(folder)) {
+                    Some(f) => {
+                        out.extend(format!("* {} EXISTS\r\n", f.messages.len()).bytes());
+                        out.extend(format!("* OK [UIDVALIDITY {}]\r\n", f.uid_validity).bytes());
+                        selected = Some(unquote(folder));
+                    }
+                    None => status = "NO no such folder".to_string(),
+                },
+                ["UID", "SEARCH", "UID", range, ref criteria @ ..] => {
+                    let folder = &server.folders[selected.as_ref().unwrap()];
+                    let from: u32 = range.trim_end_matches(":*").parse().unwrap();
+                    let mut uids: Vec<u32> = folder
+                        .messages
+                        .iter()
+                        .filter(|m| m.uid >= from)
+                        .filter(|m| match criteria {
+                            ["UNSEEN"] => !m.flags.iter().any(|f| f == "\\Seen"),
+                            ["UNKEYWORD", keyword] => !m.flags.iter().any(|f| f == keyword),
+                            _ => true,
+                        })
+                        .map(|m| m.uid)
+                        .collect();
+                    // Like real servers, `n:*` matches the last message even below n
+                    if uids.is_empty() && criteria.is_empty() {
+                        uids.extend(folder.messages.last().map(|m| m.uid));
+                    }
+                    let uids: Vec<String> = uids.iter().map(u32::to_string).collect();
+                    out.extend(
+                        format!("* SEARCH {}\r\n", uids.join(" "))
+                            .trim_end()
+                            .bytes(),
+                    );
+                    out.extend(b"\r\n");
+                }
+                ["UID", "FETCH", uid, "BODY.PEEK[]"] => {
+                    let uid: u32 = uid.parse().unwrap();
+                    let folder = &server.folders[selected.as_ref().unwrap()];
+                    if let Some((seq, m)) = folder
+                        .messages
+                        .iter()
+                        .enumerate()
+                        .find(|(_, m)| m.uid == uid)
+                    {
+                        out.extend(
+                            format!(
+                                "* {} FETCH (UID {} BODY[] {{{}}}\r\n",
+                                seq + 1,
+                                uid,
+                                m.data.len()
+                            )
+                            .bytes(),
+                        );
+                        out.extend(&m.data);
+                        out.extend(b")\r\n");
+                    }
+                }
+                ["UID", "STORE", uid, "+FLAGS.SILENT", flag] => {
+                    let uid: u32 = uid.parse().unwrap();
+                    let folder = server.folders.get_mut(selected.as_ref().unwrap()).unwrap();
+                    let flag = flag.trim_matches(['(', ')']).to_string();
+                    for m in folder.messages.iter_mut().filter(|m| m.uid == uid) {
+                        m.flags.push(flag.clone());
+                    }
+                }
+                ["UID", verb @ ("MOVE" | "COPY"), uid, target]
+                    if verb == "COPY" || supports_move =>
+                {
+                    let uid: u32 = uid.parse().unwrap();
+                    let source = server.folders.get_mut(selected.as_ref().unwrap()).unwrap();
+                    let position = source.messages.iter().position(|m| m.uid == uid).unwrap();
+                    let data = if verb == "MOVE" {
+                        source.messages.remove(position).data
+                    } else {
+                        source.messages[position].data.clone()
+                    };
+                    match server.folders.get_mut(&unquote(target)) {
+                        Some(target) => target.append(&data),
+                        None => status = "NO no such folder".to_string(),
+                    }
+                }
+                ["UID", "EXPUNGE", uid] if supports_uidplus => {
+                    let uid: u32 = uid.parse().unwrap();
+                    let folder = server.folders.get_mut(selected.as_ref().unwrap()).unwrap();
+                    folder
+                        .messages
+                        .retain(|m| m.uid != uid || !m.flags.iter().any(|f| f == "\\Deleted"));
+                }
+                ["EXPUNGE"] => {
+                    let folder = server.folders.get_mut(selected.as_ref().unwrap()).unwrap();
+                    folder
+                        .messages
+                        .retain(|m| !m.flags.iter().any(|f| f == "\\Deleted"));
+                }
+                ["LOGOUT"] => out.extend(b"* BYE\r\n"),
+                _ => status = "BAD unknown command".to_string(),
+            }
+        }
+        out.extend(format!("{} {}\r\n", tag, status).bytes());
+        stream.write_all(&out).await?;
+        stream.flush().await?;
+        if command == "LOGOUT" {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! Ingestion from existing mailboxes: IMAP folders are polled for new mail, which is published
+//! like mail received over SMTP. A UID watermark per folder, reset when UIDVALIDITY changes,
+//! keeps messages from being ingested twice; processed messages are moved or flagged on the server.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{ClientConfig, RootCertStore, crypto::aws_lc_rs, pki_types::ServerName},
+};
+
+use client::ImapClient;
+
+pub mod client;
+#[cfg(test)]
+mod fake;
+
+/// What happens to a message on the server once published
+#[derive(Debug, Clone, PartialEq)]
+pub enum Processed {
+    MoveTo(String),
+    /// `\Seen`, `\Flagged` or a keyword
+    Flag(String),
+}
+
+impl Processed {
+    /// Search criterion leaving out messages already flagged, in case the watermark was lost
+    fn not_flagged(&self) -> Option<String> {
+        match self {
+            Processed::MoveTo(_) => None,
+            Processed::Flag(flag) => Some(match flag.to_ascii_lowercase().as_str() {
+                "\\seen" => "UNSEEN".to_string(),
+                "\\flagged" => "UNFLAGGED".to_string(),
+                "\\answered" => "UNANSWERED".to_string(),
+                _ => format!("UNKEYWORD {}", flag),
+            }),
+        }
+    }
+}
+
+pub struct ImapConfig {
+    pub host: String,
+    pub port: u16,
+    /// Implicit TLS, as on port 993
+    pub tls: bool,
+    pub username: String,
+    pub password: String,
+    pub folders: Vec<String>,
+    pub processed: Processed,
+    pub poll_interval: Duration,
+    /// JSON file keeping the watermarks between runs
+    pub state_path: PathBuf,
+}
+
+impl ImapConfig {
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| env::var(name).with_context(|| format!("{} must be set", name));
+        let tls = env::var("IMAP_TLS").map_or(true, |v| v != "false");
+        let port = match env::var("IMAP_PORT") {
+            Ok(port) => port.parse().context("IMAP_PORT must be a port number")?,
+            Err(_) if tls => 993,
+            Err(_) => 143,
+        };
+        let processed = match env::var("IMAP_PROCESSED_FOLDER") {
+            Ok(folder) => Processed::MoveTo(folder),
+            Err(_) => Processed::Flag(
+                env::var("IMAP_PROCESSED_FLAG").unwrap_or_else(|_| "\\Seen".to_string()),
+            ),
+        };
+        let poll_interval = match env::var("IMAP_POLL_INTERVAL_SECS") {
+            Ok(secs) => secs
+                .parse()
+                .context("IMAP_POLL_INTERVAL_SECS must be a number")?,
+            Err(_) => 60,
+        };
+        Ok(ImapConfig {
+            host: var("IMAP_HOST")?,
+            port,
+            tls,
+            username: var("IMAP_USERNAME")?,
+            password: var("IMAP_PASSWORD")?,
+            folders: env::var("IMAP_FOLDERS")
+                .unwrap_or_else(|_| "INBOX".to_string())
+                .split(',')
+                .map(|folder| folder.trim().to_string())
+                .filter(|folder| !folder.is_empty())
+                .collect(),
+            processed,
+            poll_interval: Duration::from_secs(poll_interval),
+            state_path: env::var("IMAP_STATE_PATH")
+                .unwrap_or_else(|_| "./data/imap_state.json".to_string())
+                .into(),
+        })
+    }
+
+    fn folder_key(&self, folder: &str) -> String {
+        format!("{}@{}/{}", self.username, self.host, folder)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Watermark {
+    pub uid_validity: u32,
+    /// Highest UID published
+    pub last_uid: u32,
+}
+
+/// Watermark of each folder, by `user@host/folder`
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Watermarks(HashMap<String, Watermark>);
+
+impl Watermarks {
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(content) => Ok(serde_json::from_slice(&content)
+                .with_context(|| format!("Invalid IMAP state in {}", path.display()))?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Written aside then renamed, a crash never leaves a truncated file
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Last UID published from the folder, 0 when its UIDs were renumbered since
+    fn last_uid(&self, key: &str, uid_validity: u32) -> u32 {
+        match self.0.get(key) {
+            Some(watermark) if watermark.uid_validity == uid_validity => watermark.last_uid,
+            _ => 0,
+        }
+    }
+}
+
+/// Publish the new mail of every folder, returns the number of messages published
+pub async fn poll_once<S>(
+    client: &mut ImapClient<S>,
+    config: &ImapConfig,
+    watermarks: &mut Watermarks,
+    publish: &impl AsyncFn(Vec<u8>) -> Result<()>,
+) -> Result<usize>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut published = 0;
+    for folder in &config.folders {
+        let uid_validity = client.select(folder).await?;
+        let key = config.folder_key(folder);
+        let last_uid = watermarks.last_uid(&key, uid_validity);
+
+        let mut criteria = format!("UID {}:*", last_uid + 1);
+        if let Some(not_flagged) = config.processed.not_flagged() {
+            criteria = format!("{} {}", criteria, not_flagged);
+        }
+        // `n:*` always matches the last message, even below n
+        let mut uids: Vec<u32> = client
+            .uid_search(&criteria)
+            .await?
+            .into_iter()
+            .filter(|uid| *uid > last_uid)
+            .collect();
+        uids.sort_unstable();
+
+        for uid in uids {
+            if let Some(raw) = client.uid_fetch(uid).await? {
+                publish(raw).await?;
+                published += 1;
+            }
+            match &config.processed {
+                Processed::MoveTo(target) => client.uid_move(uid, target).await?,
+                Processed::Flag(flag) => client.uid_store_flag(uid, flag).await?,
+            }
+            watermarks.0.insert(
+                key.clone(),
+                Watermark {
+                    uid_validity,
+                    last_uid: uid,
+                },
+            );
+            watermarks.save(&config.state_path)?;
+        }
+    }
+    Ok(published)
+}
+
+/// Poll forever, a failed round is logged and retried at the next interval
+pub async fn run(config: ImapConfig, publish: &impl AsyncFn(Vec<u8>) -> Result<()>) -> Result<()> {
+    let mut watermarks = Watermarks::load(&config.state_path)?;
+    let connector = config.tls.then(tls_connector).transpose()?;
+    println!(
+        "Polling {:?} on {}:{} every {}s",
+        config.folders,
+        config.host,
+        config.port,
+        config.poll_interval.as_secs()
+    );
+
+    loop {
+        let round = async {
+            let tcp = TcpStream::connect((config.host.as_str(), config.port)).await?;
+            match &connector {
+                Some(connector) => {
+                    let server_name = ServerName::try_from(config.host.clone())?;
+                    let stream = connector.connect(server_name, tcp).await?;
+                    poll_session(
+                        ImapClient::connect(stream).await?,
+                        &config,
+                        &mut watermarks,
+                        publish,
+                    )
+                    .await
+                }
+                None => {
+                    poll_session(
+                        ImapClient::connect(tcp).await?,
+                        &config,
+                        &mut watermarks,
+                        publish,
+                    )
+                    .await
+                }
+            }
+        };
+        match round.await {
+            Ok(0) => {}
+            Ok(published) => println!("Published {} messages from IMAP", published),
+            Err(e) => println!("IMAP polling failed: {:?}", e),
+        }
+        tokio::time::sleep(config.poll_interval).await;
+    }
+}
+
+async fn poll_session<S>(
+    mut client: ImapClient<S>,
+    config: &ImapConfig,
+    watermarks: &mut Watermarks,
+    publish: &impl AsyncFn(Vec<u8>) -> Result<()>,
+) -> Result<usize>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    client.login(&config.username, &config.password).await?;
+    let published = poll_once(&mut client, config, watermarks, publish).await?;
+    client.logout().await?;
+    Ok(published)
+}
+
+fn tls_connector() -> Result<TlsConnector> {
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+    let config = ClientConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{FakeFolder, FakeServer};
+    use std::sync::Mutex;
+
+    fn config(name: &str, processed: Processed) -> ImapConfig {
+        let state_path = std::env::temp_dir().join(format!(
+            "email-gateway-{}-{}/imap_state.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&state_path);
+        ImapConfig {
+            host: "imap.test".to_string(),
+            port: 143,
+            tls: false,
+            username: "support".to_string(),
+            password: "secret".to_string(),
+            folders: vec!["INBOX".to_string()],
+            processed,
+            poll_interval: Duration::from_secs(60),
+            state_path,
+        }
+    }
+
+    fn server(supports_move: bool, supports_uidplus: bool) -> fake::Shared {
+        let mut server = FakeServer {
+            supports_move,
+            supports_uidplus,
+            ..Default::default()
+        };
+        let mut inbox = FakeFolder::new(1);
+        inbox.append(b"Subject: first\r\n\r\nHello\r\n");
+        inbox.append(b"Subject: second\r\n\r\n{42}\r\n");
+        server.folders.insert("INBOX".to_string(), inbox);
+        server
+            .folders
+            .insert("Processed".to_string(), FakeFolder::new(7));
+        Arc::new(Mutex::new(server))
+    }
+
+    /// One polling session, returns the messages published
+    async fn poll(
+        server: &fake::Shared,
+        config: &ImapConfig,
+        watermarks: &mut Watermarks,
+    ) -> Result<Vec<Vec<u8>>> {
+        let published = Mutex::new(vec![]);
+        let publish = async |raw: Vec<u8>| {
+            published.lock().unwrap().push(raw);
+            Ok(())
+        };
+        let client = ImapClient::connect(fake::connect(server.clone())).await?;
+        poll_session(client, config, watermarks, &publish).await?;
+        Ok(published.into_inner().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_flags_and_resumes_from_watermark() -> Result<()> {
+        let server = server(true, true);
+        let config = config("flag", Processed::Flag("$Ingested".to_string()));
+        let mut watermarks = Watermarks::default();
+
+        let published = poll(&server, &config, &mut watermarks).await?;
+        assert_eq!(published.len(), 2);
+        // Literals inside the message are not mistaken for protocol
+        assert_eq!(published[1], b"Subject: second\r\n\r\n{42}\r\n");
+        assert!(poll(&server, &config, &mut watermarks).await?.is_empty());
+
+        server
+            .lock()
+            .unwrap()
+            .folders
+            .get_mut("INBOX")
+            .unwrap()
+            .append(b"Subject: third\r\n\r\nAgain\r\n");
+        // The watermark survives a restart
+        let mut reloaded = Watermarks::load(&config.state_path)?;
+        assert_eq!(reloaded.last_uid("support@imap.test/INBOX", 1), 2);
+        let published = poll(&server, &config, &mut reloaded).await?;
+        assert_eq!(published, [b"Subject: third\r\n\r\nAgain\r\n".to_vec()]);
+
+        let server = server.lock().unwrap();
+        assert!(
+            server.folders["INBOX"]
+                .messages
+                .iter()
+                .all(|m| m.flags == ["$Ingested"])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_moves_and_restarts_on_uid_validity_change() -> Result<()> {
+        for (supports_move, supports_uidplus) in [(true, false), (false, true), (false, false)] {
+            let server = server(supports_move, supports_uidplus);
+            let config = config(
+                &format!("move-{}-{}", supports_move, supports_uidplus),
+                Processed::MoveTo("Processed".to_string()),
+            );
+            let mut watermarks = Watermarks::default();
+
+            assert_eq!(poll(&server, &config, &mut watermarks).await?.len(), 2);
+            {
+                let mut server = server.lock().unwrap();
+                let inbox = &server.folders["INBOX"].messages;
+                if supports_move || supports_uidplus {
+                    assert!(inbox.is_empty());
+                } else {
+                    // Flagged, never expunged
+                    assert_eq!(inbox.len(), 2);
+                    assert!(inbox.iter().all(|m| m.flags == ["\\Deleted"]));
+                }
+                assert_eq!(server.folders["Processed"].messages.len(), 2);
+
+                // Mailbox recreated: UIDs start over
+                let mut inbox = FakeFolder::new(2);
+                inbox.append(b"Subject: after reset\r\n\r\nHi\r\n");
+                server.folders.insert("INBOX".to_string(), inbox);
+            }
+            let published = poll(&server, &config, &mut watermarks).await?;
+            assert_eq!(published, [b"Subject: after reset\r\n\r\nHi\r\n".to_vec()]);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login_failure() -> Result<()> {
+        let mut config = config("login", Processed::Flag("\\Seen".to_string()));
+        config.password = "wrong".to_string();
+        let err = poll(&server(true, true), &config, &mut Watermarks::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("IMAP login failed"));
+        assert!(!format!("{:?}", err).contains("wrong"));
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+mod imap;
+mod smtp;
+mod tls;
+
+use std::env;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use clap::{Parser, Subcommand};
+use common::{
+    EMAIL_MSG_QUEUE,
+    dto::RawEmailMessage,
+    queue::{QueueManager, kafka::KafkaQueueManager},
+};
+use smtp::{Envelope, Mailbox, SmtpConfig};
+use tokio::net::TcpListener;
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Receive mail over SMTP (default)
+    Smtp,
+    /// Poll existing mailboxes, configured through the `IMAP_*` variables
+    Imap,
+}
+
+const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:2525";
+/// Largest mail that fits one Kafka record: the broker accepts about 1 MB by default, and the mail
+/// is published base64-encoded (4/3 of its size) inside a JSON document
+const MAX_MESSAGE_SIZE: usize = 700 * 1024;
+
+/// Publishes received mail on the email topic, where email-trt parses it
+struct KafkaMailbox {
+    queue_mgr: KafkaQueueManager,
+}
+
+/// Publish a raw RFC 5322 message on the email topic, with its envelope recipients when known
+async fn publish(
+    queue_mgr: &KafkaQueueManager,
+    raw: &[u8],
+    recipients: Vec<String>,
+) -> Result<i64> {
+    let received_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    queue_mgr
+        .send(
+            EMAIL_MSG_QUEUE,
+            &RawEmailMessage::new(raw, recipients, received_at),
+        )
+        .await
+}
+
+impl Mailbox for KafkaMailbox {
+    async fn deliver(&self, envelope: Envelope) -> Result<()> {
+        let msg_id = publish(&self.queue_mgr, &envelope.data, envelope.rcpt_to.clone()).await?;
+        println!(
+            "Mail from <{}> to {:?} published (id={}, {} bytes)",
+            envelope.mail_from,
+            envelope.rcpt_to,
+            msg_id,
+            envelope.data.len()
+        );
+        Ok(())
+    }
+}
+
+fn config_from_env() -> Result<SmtpConfig> {
+    let max_message_size = match env::var("SMTP_MAX_MESSAGE_SIZE") {
+        Ok(size) => size
+            .parse()
+            .context("SMTP_MAX_MESSAGE_SIZE must be a number of bytes")?,
+        Err(_) => MAX_MESSAGE_SIZE,
+    };
+    if max_message_size > MAX_MESSAGE_SIZE {
+        bail!(
+            "SMTP_MAX_MESSAGE_SIZE must be at most {} bytes, larger mail does not fit a Kafka record",
+            MAX_MESSAGE_SIZE
+        );
+    }
+    // STARTTLS is offered only when both the certificate and the key are given
+    let tls = match (env::var("SMTP_TLS_CERT"), env::var("SMTP_TLS_KEY")) {
+        (Ok(cert), Ok(key)) => Some(tls::acceptor(&cert, &key)?),
+        _ => None,
+    };
+    Ok(SmtpConfig {
+        hostname: env::var("SMTP_HOSTNAME").unwrap_or_else(|_| "localhost".to_string()),
+        max_message_size,
+        tls,
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let queue_mgr = KafkaQueueManager::new()
+        .await
+        .context("Failed to connect to Kafka")?;
+    queue_mgr.create(EMAIL_MSG_QUEUE).await?;
+
+    match args.command.unwrap_or(Command::Smtp) {
+        Command::Smtp => serve_smtp(queue_mgr).await,
+        Command::Imap => {
+            let config = imap::ImapConfig::from_env()?;
+            imap::run(config, &async |raw: Vec<u8>| {
+                // Retrying would not make it fit, the mail stays in the mailbox for a human
+                if raw.len() > MAX_MESSAGE_SIZE {
+                    eprintln!(
+                        "Mail from IMAP skipped, {} bytes is over the {} bytes limit",
+                        raw.len(),
+                        MAX_MESSAGE_SIZE
+                    );
+                    return Ok(());
+                }
+                let msg_id = publish(&queue_mgr, &raw, vec![]).await?;
+                println!(
+                    "Mail from IMAP published (id={}, {} bytes)",
+                    msg_id,
+                    raw.len()
+                );
+                Ok(())
+            })
+            .await
+        }
+    }
+}
+
+async fn serve_smtp(queue_mgr: KafkaQueueManager) -> Result<()> {
+    let config = Arc::new(config_from_env()?);
+    let mailbox = Arc::new(KafkaMailbox { queue_mgr });
+
+    let addr = env::var("SMTP_LISTEN_ADDR").unwrap_or_else(|_| DEFAULT_LISTEN_ADDR.to_string());
+    let listener = TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    println!(
+        "SMTP gateway listening on {} (STARTTLS {})",
+        addr,
+        if config.tls.is_some() { "on" } else { "off" }
+    );
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let config = config.clone();
+        let mailbox = mailbox.clone();
+        tokio::spawn(async move {
+            if let Err(e) = smtp::handle(stream, config, mailbox).await {
+                println!("SMTP session with {} failed: {:?}", peer, e);
+            }
+        });
+    }
+}
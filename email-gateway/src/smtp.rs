@@ -0,0 +1,398 @@
+//! Minimal SMTP server side (RFC 5321): EHLO/HELO, MAIL, RCPT, DATA, RSET, NOOP, QUIT, with the
+//! SIZE extension and optional STARTTLS (RFC 3207). Mail is accepted for any recipient and handed
+//! to a `Mailbox`, nothing is relayed.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio_rustls::TlsAcceptor;
+
+/// Longest command line accepted, RFC 5321 allows 512 octets
+const MAX_COMMAND_LINE: u64 = 4096;
+/// Lines of message content longer than this are read in several chunks
+const MAX_DATA_CHUNK: u64 = 64 * 1024;
+const MAX_RECIPIENTS: usize = 100;
+
+pub struct SmtpConfig {
+    /// Name announced in the greeting and the EHLO reply
+    pub hostname: String,
+    pub max_message_size: usize,
+    /// STARTTLS is offered when set
+    pub tls: Option<TlsAcceptor>,
+}
+
+/// A message as received, with its SMTP envelope
+#[derive(Debug)]
+pub struct Envelope {
+    pub mail_from: String,
+    pub rcpt_to: Vec<String>,
+    /// The RFC 5322 message, dot-unstuffed
+    pub data: Vec<u8>,
+}
+
+pub trait Mailbox: Send + Sync + 'static {
+    /// Take responsibility for the message, the client is told to retry later on error
+    fn deliver(&self, envelope: Envelope) -> impl Future<Output = Result<()>> + Send;
+}
+
+enum Outcome<S> {
+    Closed,
+    /// The client asked for TLS, the session restarts over it
+    StartTls(S),
+}
+
+/// Serve one SMTP connection until the client quits
+pub async fn handle<S, M>(stream: S, config: Arc<SmtpConfig>, mailbox: Arc<M>) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    M: Mailbox,
+{
+    match session(stream, &config, &*mailbox, false).await? {
+        Outcome::Closed => Ok(()),
+        Outcome::StartTls(stream) => {
+            let Some(acceptor) = &config.tls else {
+                bail!("STARTTLS accepted without TLS configuration");
+            };
+            let stream = acceptor.accept(stream).await?;
+            session(stream, &config, &*mailbox, true).await?;
+            Ok(())
+        }
+    }
+}
+
+struct Transaction {
+    mail_from: String,
+    rcpt_to: Vec<String>,
+}
+
+async fn session<S, M>(
+    stream: S,
+    config: &SmtpConfig,
+    mailbox: &M,
+    secure: bool,
+) -> Result<Outcome<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    M: Mailbox,
+{
+    let mut stream = BufReader::new(stream);
+    // After STARTTLS the client starts over with EHLO, without a new greeting
+    if !secure {
+        reply(
+            &mut stream,
+            220,
+            &format!("{} ESMTP ready", config.hostname),
+        )
+        .await?;
+    }
+
+    let mut greeted = false;
+    let mut transaction: Option<Transaction> = None;
+    loop {
+        let mut line = Vec::new();
+        let read = (&mut stream)
+            .take(MAX_COMMAND_LINE)
+            .read_until(b'\n', &mut line)
+            .await?;
+        if read == 0 {
+            return Ok(Outcome::Closed);
+        }
+        if !line.ends_with(b"\n") {
+            reply(&mut stream, 500, "Line too long").await?;
+            return Ok(Outcome::Closed);
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end();
+        let (verb, argument) = line.split_once(' ').unwrap_or((line, ""));
+
+        match verb.to_ascii_uppercase().as_str() {
+            "EHLO" => {
+                greeted = true;
+                transaction = None;
+                let mut lines = vec![
+                    config.hostname.clone(),
+                    format!("SIZE {}", config.max_message_size),
+                    "8BITMIME".to_string(),
+                ];
+                if config.tls.is_some() && !secure {
+                    lines.push("STARTTLS".to_string());
+                }
+                reply_lines(&mut stream, 250, &lines).await?;
+            }
+            "HELO" => {
+                greeted = true;
+                transaction = None;
+                reply(&mut stream, 250, &config.hostname).await?;
+            }
+            "STARTTLS" if config.tls.is_none() || secure => {
+                reply(&mut stream, 502, "STARTTLS not available").await?;
+            }
+            "STARTTLS" => {
+                reply(&mut stream, 220, "Ready to start TLS").await?;
+                return Ok(Outcome::StartTls(stream.into_inner()));
+            }
+            "MAIL" if !greeted => reply(&mut stream, 503, "Send EHLO first").await?,
+            "MAIL" if transaction.is_some() => {
+                reply(&mut stream, 503, "Nested MAIL command").await?
+            }
+            "MAIL" => match parse_path(argument, "FROM:") {
+                None => reply(&mut stream, 501, "Syntax: MAIL FROM:<address>").await?,
+                Some((_, parameters))
+                    if declared_size(&parameters)
+                        .is_some_and(|size| size > config.max_message_size) =>
+                {
+                    reply(&mut stream, 552, "Message size exceeds fixed limit").await?
+                }
+                Some((mail_from, _)) => {
+                    transaction = Some(Transaction {
+                        mail_from,
+                        rcpt_to: vec![],
+                    });
+                    reply(&mut stream, 250, "OK").await?;
+                }
+            },
+            "RCPT" => {
+                let Some(t) = transaction.as_mut() else {
+                    reply(&mut stream, 503, "Send MAIL first").await?;
+                    continue;
+                };
+                match parse_path(argument, "TO:") {
+                    Some((rcpt_to, _)) if !rcpt_to.is_empty() => {
+                        if t.rcpt_to.len() >= MAX_RECIPIENTS {
+                            reply(&mut stream, 452, "Too many recipients").await?;
+                        } else {
+                            t.rcpt_to.push(rcpt_to);
+                            reply(&mut stream, 250, "OK").await?;
+                        }
+                    }
+                    _ => reply(&mut stream, 501, "Syntax: RCPT TO:<address>").await?,
+                }
+            }
+            "DATA" => match transaction.take() {
+                Some(t) if !t.rcpt_to.is_empty() => {
+                    reply(&mut stream, 354, "End data with <CR><LF>.<CR><LF>").await?;
+                    match read_data(&mut stream, config.max_message_size).await? {
+                        None => reply(&mut stream, 552, "Message size exceeds fixed limit").await?,
+                        Some(data) => {
+                            let envelope = Envelope {
+                                mail_from: t.mail_from,
+                                rcpt_to: t.rcpt_to,
+                                data,
+                            };
+                            match mailbox.deliver(envelope).await {
+                                Ok(()) => reply(&mut stream, 250, "OK queued").await?,
+                                Err(e) => {
+                                    println!("Failed to deliver message: {:?}", e);
+                                    reply(&mut stream, 451, "Try again later").await?
+                                }
+                            }
+                        }
+                    }
+                }
+                other => {
+                    transaction = other;
+                    reply(&mut stream, 503, "Send MAIL and RCPT first").await?
+                }
+            },
+            "RSET" => {
+                transaction = None;
+                reply(&mut stream, 250, "OK").await?;
+            }
+            "NOOP" => reply(&mut stream, 250, "OK").await?,
+            "VRFY" => reply(&mut stream, 252, "Cannot verify user").await?,
+            "QUIT" => {
+                reply(&mut stream, 221, "Bye").await?;
+                return Ok(Outcome::Closed);
+            }
+            _ => reply(&mut stream, 500, "Command not recognized").await?,
+        }
+    }
+}
+
+/// Read message content up to the lone dot. `None` when it is larger than `max_size`, the content
+/// is still consumed so that the session can go on.
+async fn read_data<S: AsyncRead + Unpin>(
+    stream: &mut BufReader<S>,
+    max_size: usize,
+) -> Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    let mut too_large = false;
+    let mut line_start = true;
+    loop {
+        let mut chunk = Vec::new();
+        let read = (&mut *stream)
+            .take(MAX_DATA_CHUNK)
+            .read_until(b'\n', &mut chunk)
+            .await?;
+        if read == 0 {
+            bail!("Connection closed during DATA");
+        }
+        if line_start && (chunk == b".\r\n" || chunk == b".\n") {
+            break;
+        }
+        // Dot-stuffing: a leading dot was doubled by the client
+        let content = match chunk.strip_prefix(b".") {
+            Some(rest) if line_start => rest,
+            _ => &chunk[..],
+        };
+        line_start = chunk.ends_with(b"\n");
+        if too_large || data.len() + content.len() > max_size {
+            too_large = true;
+            data.clear();
+        } else {
+            data.extend_from_slice(content);
+        }
+    }
+    Ok(if too_large { None } else { Some(data) })
+}
+
+/// Address and ESMTP parameters of `FROM:<address> SIZE=123`. The null sender `<>` is allowed.
+fn parse_path(argument: &str, prefix: &str) -> Option<(String, String)> {
+    let head = argument.get(..prefix.len())?;
+    if !head.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    let rest = argument[prefix.len()..].trim_start();
+    let rest = rest.strip_prefix('<')?;
+    let (address, parameters) = rest.split_once('>')?;
+    Some((address.to_string(), parameters.trim().to_string()))
+}
+
+fn declared_size(parameters: &str) -> Option<usize> {
+    parameters.split_whitespace().find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        if key.eq_ignore_ascii_case("SIZE") {
+            value.parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+async fn reply<S: AsyncWrite + Unpin>(stream: &mut S, code: u16, text: &str) -> Result<()> {
+    stream
+        .write_all(format!("{} {}\r\n", code, text).as_bytes())
+        .await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn reply_lines<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    code: u16,
+    lines: &[String],
+) -> Result<()> {
+    let mut response = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let separator = if i + 1 == lines.len() { ' ' } else { '-' };
+        response.push_str(&format!("{}{}{}\r\n", code, separator, line));
+    }
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Inbox(Mutex<Vec<Envelope>>);
+
+    impl Mailbox for Inbox {
+        async fn deliver(&self, envelope: Envelope) -> Result<()> {
+            self.0.lock().unwrap().push(envelope);
+            Ok(())
+        }
+    }
+
+    /// Send the whole client side at once and return the server replies, one per line
+    async fn converse(max_message_size: usize, client: &str) -> Result<(Vec<String>, Arc<Inbox>)> {
+        let (mut client_side, server_side) = tokio::io::duplex(1 << 20);
+        let config = Arc::new(SmtpConfig {
+            hostname: "mx.test".to_string(),
+            max_message_size,
+            tls: None,
+        });
+        let inbox = Arc::new(Inbox::default());
+        let server = tokio::spawn(handle(server_side, config, inbox.clone()));
+
+        client_side.write_all(client.as_bytes()).await?;
+        let mut replies = String::new();
+        client_side.read_to_string(&mut replies).await?;
+        server.await??;
+        Ok((replies.lines().map(str::to_string).collect(), inbox))
+    }
+
+    #[tokio::test]
+    async fn test_delivers_mail() -> Result<()> {
+        let (replies, inbox) = converse(
+            1024,
+            "EHLO client.test\r\n\
+             MAIL FROM:<jane@example.com> SIZE=120\r\n\
+             RCPT TO:<support+42@company.com>\r\n\
+             DATA\r\n\
+             Subject: Hi\r\n\
+             \r\n\
+             ..leading dot\r\n\
+             .\r\n\
+             QUIT\r\n",
+        )
+        .await?;
+
+        assert_eq!(
+            replies,
+            [
+                "220 mx.test ESMTP ready",
+                "250-mx.test",
+                "250-SIZE 1024",
+                "250 8BITMIME",
+                "250 OK",
+                "250 OK",
+                "354 End data with <CR><LF>.<CR><LF>",
+                "250 OK queued",
+                "221 Bye",
+            ]
+        );
+        let inbox = inbox.0.lock().unwrap();
+        assert_eq!(inbox[0].mail_from, "jane@example.com");
+        assert_eq!(inbox[0].rcpt_to, ["support+42@company.com"]);
+        assert_eq!(inbox[0].data, b"Subject: Hi\r\n\r\n.leading dot\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rejects_large_messages_and_bad_sequences() -> Result<()> {
+        let (replies, inbox) = converse(
+            16,
+            "RCPT TO:<support@company.com>\r\n\
+             MAIL FROM:<jane@example.com>\r\n\
+             HELO client.test\r\n\
+             MAIL FROM:<jane@example.com> SIZE=100\r\n\
+             MAIL FROM:<>\r\n\
+             DATA\r\n\
+             RCPT TO:<support@company.com>\r\n\
+             DATA\r\n\
+             This line alone is over the limit\r\n\
+             .\r\n\
+             STARTTLS\r\n\
+             NOOP\r\n\
+             QUIT\r\n",
+        )
+        .await?;
+
+        let codes: Vec<&str> = replies.iter().map(|reply| &reply[..3]).collect();
+        assert_eq!(
+            codes,
+            [
+                "220", "503", "503", "250", "552", "250", "503", "250", "354", "552", "502", "250",
+                "221"
+            ]
+        );
+        assert!(inbox.0.lock().unwrap().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+use std::{fs::File, io::BufReader, sync::Arc};
+
+use anyhow::{Context, Result};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{ServerConfig, crypto::aws_lc_rs},
+};
+
+/// TLS acceptor for STARTTLS, from PEM certificate chain and private key files
+pub fn acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor> {
+    let open = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .with_context(|| format!("Failed to open {}", path))
+    };
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate in {}", cert_path))?;
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)?
+        .with_context(|| format!("No private key in {}", key_path))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
@@ -0,0 +1,112 @@
+//! Reply emails, threaded under the customer's message
+
+use anyhow::{Context, Result};
+use common::dto::OutboundMessage;
+use lettre::{
+    Address, Message,
+    message::{Mailbox, header::ContentType},
+};
+
+/// `support+<ticket>@company.com`: email-trt reads the ticket back from the `+tag`
+pub fn plus_address(address: &Address, ticket_id: &str) -> Result<Address> {
+    let user = address.user().split('+').next().unwrap_or_default();
+    Address::new(format!("{}+{}", user, ticket_id), address.domain())
+        .with_context(|| format!("Invalid reply-to address for ticket {}", ticket_id))
+}
+
+/// `Re: <subject>`, without piling up prefixes
+pub fn reply_subject(subject: Option<&str>) -> String {
+    match subject.map(str::trim) {
+        Some(subject) if subject.to_lowercase().starts_with("re:") => subject.to_string(),
+        Some(subject) if !subject.is_empty() => format!("Re: {}", subject),
+        _ => "Re: Your support request".to_string(),
+    }
+}
+
+/// Message-ID of a reply, without the angle brackets. Its local part is the outbound id, which
+/// delivery reports quote back.
+pub fn message_id(reply: &OutboundMessage, from: &Mailbox) -> String {
+    format!("{}@{}", reply.id, from.email.domain())
+}
+
+pub fn compose(reply: &OutboundMessage, from: &Mailbox, message_id: &str) -> Result<Message> {
+    let to: Mailbox = reply
+        .contact
+        .parse()
+        .with_context(|| format!("Invalid recipient {}", reply.contact))?;
+    let reply_to = Mailbox::new(
+        from.name.clone(),
+        plus_address(&from.email, &reply.ticket_id)?,
+    );
+
+    let mut builder = Message::builder()
+        .from(from.clone())
+        .reply_to(reply_to)
+        .to(to)
+        .subject(reply_subject(reply.subject.as_deref()))
+        .message_id(Some(format!("<{}>", message_id)));
+    if let Some(in_reply_to) = &reply.in_reply_to {
+        // The thread only goes back to the message replied to, which is enough for clients
+        builder = builder
+            .in_reply_to(format!("<{}>", in_reply_to))
+            .references(format!("<{}>", in_reply_to));
+    }
+    builder
+        .header(ContentType::TEXT_PLAIN)
+        .body(reply.body.clone())
+        .context("Failed to build the reply email")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::dto::Origin;
+
+    fn reply() -> OutboundMessage {
+        OutboundMessage {
+            id: "r1".to_string(),
+            ticket_id: "42".to_string(),
+            contact: "anna@shop.example".to_string(),
+            origin: Origin::Email,
+            body: "Your refund was issued today.".to_string(),
+            subject: Some("Charged twice".to_string()),
+            in_reply_to: Some("CAF=abc@mail.shop.example".to_string()),
+            suggestion_id: None,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_compose_threads_the_reply() {
+        let from: Mailbox = "Support <support@company.com>".parse().unwrap();
+        let reply = reply();
+        let message_id = message_id(&reply, &from);
+        assert_eq!(message_id, "r1@company.com");
+        let message = compose(&reply, &from, &message_id).unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+
+        assert!(formatted.contains("Reply-To: Support <support+42@company.com>\r\n"));
+        assert!(formatted.contains("To: anna@shop.example\r\n"));
+        assert!(formatted.contains("Subject: Re: Charged twice\r\n"));
+        assert!(formatted.contains("Message-ID: <r1@company.com>\r\n"));
+        assert!(formatted.contains("In-Reply-To: <CAF=abc@mail.shop.example>\r\n"));
+        assert!(formatted.contains("References: <CAF=abc@mail.shop.example>\r\n"));
+        assert!(formatted.ends_with("Your refund was issued today."));
+    }
+
+    #[test]
+    fn test_subject_and_plus_address() {
+        assert_eq!(
+            reply_subject(Some("RE: Charged twice")),
+            "RE: Charged twice"
+        );
+        assert_eq!(reply_subject(Some("  ")), "Re: Your support request");
+        assert_eq!(reply_subject(None), "Re: Your support request");
+
+        let address: Address = "support+old@company.com".parse().unwrap();
+        assert_eq!(
+            plus_address(&address, "42").unwrap().to_string(),
+            "support+42@company.com"
+        );
+    }
+}
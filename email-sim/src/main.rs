@@ -1,8 +1,9 @@
 use clap::Parser;
 use common::{
     EMAIL_MSG_QUEUE,
-    dto::EmailMessage,
+    dto::{Attachment, EmailMessage},
     queue::{QueueManager, kafka::KafkaQueueManager},
+    storage::checksum,
 };
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -18,6 +19,22 @@ struct Args {
     loop_send: bool,
 }
 
+/// Every third customer sends a screenshot along; content is not uploaded, only described
+fn fake_attachments(i: u32) -> Vec<Attachment> {
+    if !i.is_multiple_of(3) {
+        return vec![];
+    }
+    let content = format!("fake PNG screenshot #{}", i).into_bytes();
+    vec![Attachment {
+        filename: format!("screenshot-{}.png", i),
+        content_type: "image/png".to_string(),
+        size: content.len() as u64,
+        checksum: checksum(&content),
+        storage_key: None,
+        source_id: None,
+    }]
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
@@ -31,7 +48,7 @@ async fn main() -> anyhow::Result<()> {
     queue_mgr
         .create(EMAIL_MSG_QUEUE)
         .await
-        .expect(&format!("Failed to create topic '{}'", EMAIL_MSG_QUEUE));
+        .unwrap_or_else(|_| panic!("Failed to create topic '{}'", EMAIL_MSG_QUEUE));
     println!("Topic '{}' ready", EMAIL_MSG_QUEUE);
 
     if args.loop_send {
@@ -45,12 +62,19 @@ async fn main() -> anyhow::Result<()> {
                     .duration_since(UNIX_EPOCH)
                     .expect("Failed to get current timestamp")
                     .as_secs(),
+                attachments: fake_attachments(i),
+                subject: Some(format!("Help request #{}", i)),
+                cc: vec![],
+                message_id: None,
+                in_reply_to: None,
+                references: vec![],
+                headers: Default::default(),
             };
 
             let msg_id = queue_mgr
                 .send(EMAIL_MSG_QUEUE, &msg)
                 .await
-                .expect(&format!("Failed to send message {} to Kafka", i));
+                .unwrap_or_else(|_| panic!("Failed to send message {} to Kafka", i));
             
             println!("Sent message {} (id={}): from={}, content={}", 
                      i, msg_id, msg.from, msg.content);
@@ -68,12 +92,19 @@ async fn main() -> anyhow::Result<()> {
                     .duration_since(UNIX_EPOCH)
                     .expect("Failed to get current timestamp")
                     .as_secs(),
+                attachments: fake_attachments(i),
+                subject: Some(format!("Help request #{}", i)),
+                cc: vec![],
+                message_id: None,
+                in_reply_to: None,
+                references: vec![],
+                headers: Default::default(),
             };
 
             let msg_id = queue_mgr
                 .send(EMAIL_MSG_QUEUE, &msg)
                 .await
-                .expect(&format!("Failed to send message {} to Kafka", i));
+                .unwrap_or_else(|_| panic!("Failed to send message {} to Kafka", i));
             
             println!("Sent message {}/{} (id={}): from={}, content={}", 
                      i, count, msg_id, msg.from, msg.content);
@@ -1,59 +1,149 @@
-use clap::Parser;
 use common::{
-    COMMON_MSG_QUEUE, EMAIL_MSG_QUEUE,
-    dto::{CommonMessage, EmailMessage},
-    queue::{Message, QueueManager, kafka::KafkaQueueManager},
+    DELIVERY_STATUS_QUEUE, EMAIL_MSG_QUEUE,
+    channel::{self, ChannelAdapter},
+    dto::{CommonMessage, DeliveryStatus, InboundEmail, Origin},
+    queue::{QueueManager, kafka::KafkaQueueManager},
+    storage::{ObjectStore, archive_raw, archive_raw_bytes, store_attachment},
+    store::{ThreadIndex, postgres::PostgresTicketRepository, postgres_url_from_env, thread},
 };
 
-#[derive(Parser)]
-#[command()]
-struct Args {
-    /// Message content
-    #[arg(long)]
-    message: String,
+mod mime;
+mod reply;
+mod report;
 
-    /// Number of messages to send
-    #[arg(long)]
-    count: u32,
+use mime::MimeAttachment;
+
+/// Emails, parsed when they come raw. Delivery reports about our replies become delivery
+/// statuses instead of messages.
+struct Email {
+    threads: PostgresTicketRepository,
+    queue_mgr: KafkaQueueManager,
+}
+
+impl ChannelAdapter for Email {
+    type Input = InboundEmail;
+    /// MIME attachments of raw emails, only described in the message until stored
+    type Parts = Vec<MimeAttachment>;
+    const NAME: &'static str = "email";
+    const SOURCE_QUEUE: &'static str = EMAIL_MSG_QUEUE;
+
+    async fn normalize(
+        &self,
+        input: InboundEmail,
+    ) -> anyhow::Result<Option<(CommonMessage, Vec<MimeAttachment>)>> {
+        let mut attachments = vec![];
+        let mut envelope_hint = None;
+        let email = match input {
+            InboundEmail::Raw(raw) => {
+                envelope_hint = raw.ticket_hint();
+                let bytes = raw.bytes()?;
+                if let Some(reports) = report::parse_report(&bytes) {
+                    for report in reports {
+                        publish_report(&self.queue_mgr, &self.threads, report, raw.received_at)
+                            .await?;
+                    }
+                    return Ok(None);
+                }
+                let parsed = mime::parse_email(&bytes, raw.received_at)?;
+                attachments = parsed.attachments;
+                parsed.email
+            }
+            InboundEmail::Parsed(email) => *email,
+        };
+        let thread_ticket = self
+            .threads
+            .find_thread(email.in_reply_to.as_deref(), &email.references)
+            .await?;
+        let mut common_msg = CommonMessage::try_from(email)?;
+        // A `+tag` in the recipient wins, the envelope's first as the `To` header may not be
+        // where the mail was sent. The headers cover replies sent to another address, and tags
+        // naming no ticket such as `support+billing@`.
+        let tagged = envelope_hint.or(common_msg.ticket_hint.take());
+        common_msg.ticket_hint = channel::known_ticket(&self.threads, tagged)
+            .await?
+            .or(thread_ticket);
+        let ticket_id = common_msg.assign_ticket_id();
+        if let Some(message_id) = &common_msg.message_id {
+            self.threads
+                .record(thread::normalize(message_id), &ticket_id)
+                .await?;
+        }
+        Ok(Some((common_msg, attachments)))
+    }
+
+    async fn archive(
+        &self,
+        store: &impl ObjectStore,
+        input: &InboundEmail,
+        common_msg: &mut CommonMessage,
+        parts: Vec<MimeAttachment>,
+    ) -> anyhow::Result<()> {
+        match input {
+            InboundEmail::Raw(raw) => {
+                let ticket_id = common_msg.assign_ticket_id();
+                for (described, part) in common_msg.attachments.iter_mut().zip(parts) {
+                    *described = store_attachment(
+                        store,
+                        &ticket_id,
+                        &part.filename,
+                        &part.content_type,
+                        part.content,
+                    )
+                    .await?;
+                }
+                archive_raw_bytes(store, "message/rfc822", "eml", raw.bytes()?, common_msg).await?;
+            }
+            InboundEmail::Parsed(email) => {
+                archive_raw(store, email.as_ref(), common_msg).await?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let queue_mgr = KafkaQueueManager::new().await.unwrap();
-
-    // Create queues
-    queue_mgr.create(EMAIL_MSG_QUEUE).await.expect(&format!(
-        "Failed to create email topic '{}'",
-        EMAIL_MSG_QUEUE
-    ));
-    queue_mgr.create(COMMON_MSG_QUEUE).await.expect(&format!(
-        "Failed to create common topic '{}'",
-        COMMON_MSG_QUEUE
-    ));
+    let queue_mgr = KafkaQueueManager::new().await?;
+    queue_mgr.create(DELIVERY_STATUS_QUEUE).await?;
+    let threads = PostgresTicketRepository::connect(&postgres_url_from_env()).await?;
+
+    channel::run(Email { threads, queue_mgr }).await
+}
 
+/// Delivery status of the reply a report is about, when it is one of ours
+async fn publish_report(
+    queue_mgr: &KafkaQueueManager,
+    threads: &PostgresTicketRepository,
+    report: report::Report,
+    received_at: u64,
+) -> anyhow::Result<()> {
+    let Some(ticket_id) = threads.lookup(&report.original_message_id).await? else {
+        println!(
+            "Ignoring report about unknown message {}",
+            report.original_message_id
+        );
+        return Ok(());
+    };
+    // email-sender uses the outbound id as the local part of the Message-ID
+    let outbound_id = report
+        .original_message_id
+        .split('@')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let status = DeliveryStatus {
+        outbound_id,
+        ticket_id,
+        contact: report.recipient,
+        origin: Origin::Email,
+        state: report.state,
+        at: received_at,
+        reason: report.reason,
+    };
+    queue_mgr.send(DELIVERY_STATUS_QUEUE, &status).await?;
     println!(
-        "Listening for email messages on topic '{}'...",
-        EMAIL_MSG_QUEUE
+        "Reply {} on ticket {} to {} is {:?}",
+        status.outbound_id, status.ticket_id, status.contact, status.state
     );
-
-    queue_mgr
-        .register_read(EMAIL_MSG_QUEUE, &async |msg: Message<EmailMessage>| {
-            // Transform to common message
-            let common_msg: CommonMessage = msg.message.into();
-            println!("Transformed to: {:?}", common_msg);
-
-            // Send to common queue
-            let forwarded_id = queue_mgr
-                .send(COMMON_MSG_QUEUE, &common_msg)
-                .await
-                .expect(&format!(
-                    "Failed to forward message {} to common queue",
-                    msg.msg_id
-                ));
-            println!("Forwarded to common queue (id={})", forwarded_id);
-
-            Ok(())
-        })
-        .await?;
     Ok(())
 }
@@ -0,0 +1,261 @@
+//! Parsing of raw RFC 5322 emails into `EmailMessage`. mail-parser takes care of charsets,
+//! transfer encodings and the MIME tree.
+
+use anyhow::{Context, Result};
+use common::{
+    dto::{Attachment, EmailMessage},
+    kb::ingest::html_to_text,
+    storage::checksum,
+};
+use mail_parser::{Address, HeaderValue, Message, MessageParser, MimeHeaders};
+
+use crate::reply::strip_reply;
+
+/// Headers kept on the message for message-filter, which spots auto-replies, bounces and bulk
+/// mail with them
+const FILTER_HEADERS: [&str; 10] = [
+    "Auto-Submitted",
+    "X-Autoreply",
+    "X-Autorespond",
+    "X-Auto-Response-Suppress",
+    "Precedence",
+    "List-Id",
+    "List-Unsubscribe",
+    "Return-Path",
+    "X-Spam-Flag",
+    "X-Spam-Score",
+];
+
+/// A MIME attachment, kept in memory until it can be stored under its ticket
+pub struct MimeAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+impl MimeAttachment {
+    pub fn describe(&self) -> Attachment {
+        Attachment {
+            filename: self.filename.clone(),
+            content_type: self.content_type.clone(),
+            size: self.content.len() as u64,
+            checksum: checksum(&self.content),
+            storage_key: None,
+            source_id: None,
+        }
+    }
+}
+
+pub struct ParsedEmail {
+    pub email: EmailMessage,
+    pub attachments: Vec<MimeAttachment>,
+}
+
+pub fn parse_email(raw: &[u8], received_at: u64) -> Result<ParsedEmail> {
+    let message = MessageParser::default()
+        .parse(raw)
+        .context("Not an RFC 5322 message")?;
+    let from = addresses(message.from())
+        .into_iter()
+        .next()
+        .context("Missing From address")?;
+
+    let attachments: Vec<MimeAttachment> = message
+        .attachments()
+        .enumerate()
+        .map(|(i, part)| MimeAttachment {
+            filename: part
+                .attachment_name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("attachment-{}", i + 1)),
+            content_type: part
+                .content_type()
+                .map(|ct| match ct.subtype() {
+                    Some(subtype) => format!("{}/{}", ct.ctype(), subtype),
+                    None => ct.ctype().to_string(),
+                })
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            content: part.contents().to_vec(),
+        })
+        .collect();
+
+    let email = EmailMessage {
+        from,
+        to: addresses(message.to())
+            .into_iter()
+            .next()
+            .unwrap_or_default(),
+        content: strip_reply(&body(&message)),
+        timestamp: received_at,
+        attachments: attachments.iter().map(MimeAttachment::describe).collect(),
+        subject: message.subject().map(str::to_string),
+        cc: addresses(message.cc()),
+        message_id: message.message_id().map(str::to_string),
+        in_reply_to: ids(message.in_reply_to()).into_iter().next(),
+        references: ids(message.references()),
+        headers: message
+            .headers_raw()
+            .filter(|(name, _)| FILTER_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)))
+            .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+            .collect(),
+    };
+    Ok(ParsedEmail { email, attachments })
+}
+
+/// The text/plain body, or the HTML one converted to text when there is none
+fn body(message: &Message) -> String {
+    let text: Vec<&str> = message
+        .text_bodies()
+        // mail-parser lists the HTML part here when there is no text alternative
+        .filter(|part| !part.is_text_html())
+        .filter_map(|part| part.text_contents())
+        .collect();
+    if !text.is_empty() {
+        return text.join("\n");
+    }
+    message
+        .body_html(0)
+        .map(|html| html_to_text(&html))
+        .unwrap_or_default()
+}
+
+fn addresses(address: Option<&Address>) -> Vec<String> {
+    address
+        .map(|address| {
+            address
+                .iter()
+                .filter_map(|addr| addr.address())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn ids(header: &HeaderValue) -> Vec<String> {
+    match header {
+        HeaderValue::Text(id) => vec![id.to_string()],
+        HeaderValue::TextList(ids) => ids.iter().map(|id| id.to_string()).collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quoted_printable_reply() -> Result<()> {
+        let raw = b"From: =?ISO-8859-1?Q?Ren=E9e?= <renee@example.fr>\r\n\
+            To: support+42@company.com\r\n\
+            Cc: Paul <paul@example.fr>, anne@example.fr\r\n\
+            Subject: =?UTF-8?B?Q29tbWFuZGUgcmV0YXJkw6ll?=\r\n\
+            Message-ID: <reply-2@example.fr>\r\n\
+            In-Reply-To: <answer-1@company.com>\r\n\
+            References: <question-0@example.fr> <answer-1@company.com>\r\n\
+            Content-Type: text/plain; charset=ISO-8859-1\r\n\
+            Content-Transfer-Encoding: quoted-printable\r\n\
+            \r\n\
+            Toujours rien re=E7u.\r\n\
+            \r\n\
+            Le lun. 2 mars 2026 =E0 10:00, Support a =E9crit :\r\n\
+            > Votre colis est parti.\r\n";
+        let email = parse_email(raw, 1772000000)?.email;
+
+        assert_eq!(email.from, "renee@example.fr");
+        assert_eq!(email.to, "support+42@company.com");
+        assert_eq!(email.cc, ["paul@example.fr", "anne@example.fr"]);
+        assert_eq!(email.subject.as_deref(), Some("Commande retardée"));
+        assert_eq!(email.content, "Toujours rien reçu.");
+        assert_eq!(email.message_id.as_deref(), Some("reply-2@example.fr"));
+        assert_eq!(email.in_reply_to.as_deref(), Some("answer-1@company.com"));
+        assert_eq!(
+            email.references,
+            ["question-0@example.fr", "answer-1@company.com"]
+        );
+        assert_eq!(email.timestamp, 1772000000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_multipart_with_attachment() -> Result<()> {
+        let raw = b"From: jane@example.com\r\n\
+            To: support@company.com\r\n\
+            Subject: Broken screen\r\n\
+            Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+            \r\n\
+            --outer\r\n\
+            Content-Type: multipart/alternative; boundary=\"inner\"\r\n\
+            \r\n\
+            --inner\r\n\
+            Content-Type: text/plain; charset=utf-8\r\n\
+            \r\n\
+            The screen is cracked, photo attached.\r\n\
+            \r\n\
+            -- \r\n\
+            Jane\r\n\
+            --inner\r\n\
+            Content-Type: text/html; charset=utf-8\r\n\
+            \r\n\
+            <p>The screen is <b>cracked</b>, photo attached.</p>\r\n\
+            --inner--\r\n\
+            --outer\r\n\
+            Content-Type: image/png; name=\"screen.png\"\r\n\
+            Content-Disposition: attachment; filename=\"screen.png\"\r\n\
+            Content-Transfer-Encoding: base64\r\n\
+            \r\n\
+            iVBORw0KGgo=\r\n\
+            --outer--\r\n";
+        let parsed = parse_email(raw, 0)?;
+
+        assert_eq!(
+            parsed.email.content,
+            "The screen is cracked, photo attached."
+        );
+        assert_eq!(parsed.attachments.len(), 1);
+        let attachment = &parsed.attachments[0];
+        assert_eq!(attachment.filename, "screen.png");
+        assert_eq!(attachment.content_type, "image/png");
+        assert_eq!(attachment.content, b"\x89PNG\r\n\x1a\n");
+        assert_eq!(parsed.email.attachments[0].size, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_html_only() -> Result<()> {
+        let raw = b"From: Jane <jane@example.com>\r\n\
+            To: support@company.com\r\n\
+            Content-Type: text/html; charset=utf-8\r\n\
+            \r\n\
+            <html><body><p>Where is my order?</p><p>Thanks</p></body></html>\r\n";
+        let email = parse_email(raw, 0)?.email;
+
+        assert!(email.content.contains("Where is my order?"));
+        assert!(email.content.contains("Thanks"));
+        assert!(!email.content.contains('<'));
+        assert!(email.subject.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_filter_headers() -> Result<()> {
+        let raw = b"From: anna@shop.example\r\n\
+            To: support@company.com\r\n\
+            Subject: Automatic reply: Your order\r\n\
+            Auto-Submitted: auto-replied\r\n\
+            X-Auto-Response-Suppress: All\r\n\
+            Received: from mx.shop.example\r\n\
+            \r\n\
+            I am out of the office until Monday.\r\n";
+        let email = parse_email(raw, 1772000000)?.email;
+
+        assert_eq!(email.headers.len(), 2);
+        assert_eq!(email.headers["auto-submitted"], "auto-replied");
+        assert_eq!(email.headers["x-auto-response-suppress"], "All");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_without_sender() {
+        assert!(parse_email(b"Subject: hi\r\n\r\nhello\r\n", 0).is_err());
+    }
+}
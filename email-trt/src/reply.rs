@@ -0,0 +1,72 @@
+//! Removal of what the customer did not write in a reply: quoted messages and signatures.
+
+/// Line introducing the quoted previous message, e.g. "On Mon, 2 Mar 2026, Jane wrote:"
+fn is_attribution(line: &str) -> bool {
+    let line = line.trim();
+    (line.starts_with("On ") && line.ends_with("wrote:"))
+        || (line.starts_with("Le ") && (line.ends_with("a écrit :") || line.ends_with("a écrit:")))
+        || line.starts_with("-----Original Message-----")
+        || line.starts_with("-------- Message d'origine --------")
+        // Outlook separator above the quoted headers
+        || line == "________________________________"
+}
+
+fn is_signature(line: &str) -> bool {
+    // "-- " is the RFC 3676 delimiter, often trimmed by clients
+    line.trim_end() == "--"
+        || line.starts_with("Sent from my ")
+        || line.starts_with("Envoyé de mon ")
+}
+
+/// Keep the new text of a reply: drop `>` lines and everything from the attribution line or the
+/// signature on. A body made only of quotes is kept as is.
+pub fn strip_reply(body: &str) -> String {
+    let lines: Vec<&str> = body.lines().collect();
+    let mut kept = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        // Clients wrap long attribution lines
+        let wrapped = lines
+            .get(i + 1)
+            .is_some_and(|next| is_attribution(&format!("{} {}", line.trim(), next.trim())));
+        if is_attribution(line) || wrapped || is_signature(line) {
+            break;
+        }
+        if line.trim_start().starts_with('>') {
+            continue;
+        }
+        kept.push(line.trim_end());
+    }
+
+    let text = kept.join("\n").trim().to_string();
+    if text.is_empty() {
+        body.trim().to_string()
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_reply() {
+        let body = "Still nothing, can you check?\n\n\
+            On Mon, 2 Mar 2026 at 10:00, Support <support@company.com>\n\
+            wrote:\n\
+            > Your parcel left our warehouse.\n\
+            >\n\
+            > -- \n\
+            > Support";
+        assert_eq!(strip_reply(body), "Still nothing, can you check?");
+
+        let body =
+            "Merci, c'est reçu.\n\nLe lun. 2 mars 2026 à 10:00, Support a écrit :\n> Bonjour";
+        assert_eq!(strip_reply(body), "Merci, c'est reçu.");
+
+        let body = "See inline\n> Which size?\nM please\n\n-- \nJane Doe\n+33 6 12 34 56 78";
+        assert_eq!(strip_reply(body), "See inline\nM please");
+
+        assert_eq!(strip_reply("> only a quote\n"), "> only a quote");
+    }
+}
@@ -0,0 +1,240 @@
+//! Delivery reports about the replies sent by email-sender: DSNs from mail servers (RFC 3464,
+//! bounces and delivery confirmations) and read receipts from mail clients (MDNs, RFC 8098).
+//! They are turned into delivery statuses instead of being treated as customer messages.
+
+use common::{store::thread, ticket::DeliveryState};
+use mail_parser::{Message, MessageParser, MimeHeaders, PartType};
+
+pub struct Report {
+    /// Message-ID of the reply the report is about, without angle brackets
+    pub original_message_id: String,
+    pub recipient: String,
+    pub state: DeliveryState,
+    pub reason: Option<String>,
+}
+
+/// `None` when the email is not a delivery report. Reports telling nothing new, e.g. a delay,
+/// give an empty list.
+pub fn parse_report(raw: &[u8]) -> Option<Vec<Report>> {
+    let message = MessageParser::default().parse(raw)?;
+    let content_type = message.root_part().content_type()?;
+    if !content_type.ctype().eq_ignore_ascii_case("multipart")
+        || !content_type
+            .subtype()
+            .is_some_and(|subtype| subtype.eq_ignore_ascii_case("report"))
+    {
+        return None;
+    }
+
+    let (kind, fields) = message.parts.iter().find_map(|part| {
+        let content_type = part.content_type()?;
+        let subtype = content_type.subtype()?.to_lowercase();
+        match subtype.as_str() {
+            "delivery-status"
+            | "global-delivery-status"
+            | "disposition-notification"
+            | "global-disposition-notification" => Some((
+                subtype,
+                String::from_utf8_lossy(part.contents()).into_owned(),
+            )),
+            _ => None,
+        }
+    })?;
+    let blocks = field_blocks(&fields);
+    let per_message = blocks.first()?;
+    let original_message_id = field(per_message, "original-message-id")
+        .map(str::to_string)
+        .or_else(|| original_message_id(&message))?;
+    let original_message_id = thread::normalize(&original_message_id).to_string();
+
+    let reports = if kind.ends_with("disposition-notification") {
+        mdn_report(per_message, &original_message_id)
+            .into_iter()
+            .collect()
+    } else {
+        blocks
+            .iter()
+            .skip(1)
+            .filter_map(|recipient| dsn_report(recipient, &original_message_id))
+            .collect()
+    };
+    Some(reports)
+}
+
+fn dsn_report(fields: &[(String, String)], original_message_id: &str) -> Option<Report> {
+    let status = field(fields, "status").unwrap_or_default();
+    let state = match field(fields, "action")?.to_lowercase().as_str() {
+        "delivered" => DeliveryState::Delivered,
+        // Bad destination address, or disabled mailbox
+        "failed" if status.starts_with("5.1.") || status == "5.2.1" => DeliveryState::Bounced,
+        "failed" => DeliveryState::Failed,
+        // Delayed, or relayed to a system that does not report
+        _ => return None,
+    };
+    let reason = field(fields, "diagnostic-code")
+        .map(|code| after_type(code).to_string())
+        .or_else(|| (!status.is_empty()).then(|| status.to_string()));
+    Some(Report {
+        original_message_id: original_message_id.to_string(),
+        recipient: recipient(fields)?,
+        state,
+        reason: reason.filter(|_| state.is_failure()),
+    })
+}
+
+fn mdn_report(fields: &[(String, String)], original_message_id: &str) -> Option<Report> {
+    // e.g. `manual-action/MDN-sent-manually; displayed`
+    let disposition = after_type(field(fields, "disposition")?);
+    if !disposition.to_lowercase().starts_with("displayed") {
+        return None;
+    }
+    Some(Report {
+        original_message_id: original_message_id.to_string(),
+        recipient: recipient(fields)?,
+        state: DeliveryState::Read,
+        reason: None,
+    })
+}
+
+/// `rfc822; anna@shop.example` → `anna@shop.example`
+fn recipient(fields: &[(String, String)]) -> Option<String> {
+    field(fields, "final-recipient")
+        .or_else(|| field(fields, "original-recipient"))
+        .map(|value| after_type(value).to_lowercase())
+}
+
+/// Value of a typed field, without its type: `smtp; 550 User unknown` → `550 User unknown`
+fn after_type(value: &str) -> &str {
+    value.split_once(';').map_or(value, |(_, rest)| rest).trim()
+}
+
+fn field<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(field, _)| field == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Groups of `Name: value` fields separated by blank lines, names lowercased and folded lines
+/// joined
+fn field_blocks(text: &str) -> Vec<Vec<(String, String)>> {
+    let mut blocks = vec![];
+    let mut block: Vec<(String, String)> = vec![];
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !block.is_empty() {
+                blocks.push(std::mem::take(&mut block));
+            }
+        } else if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = block.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            block.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Message-ID of the returned message or of its headers, attached to the report
+fn original_message_id(message: &Message) -> Option<String> {
+    message.parts.iter().find_map(|part| match &part.body {
+        PartType::Message(original) => original.message_id().map(str::to_string),
+        PartType::Text(headers)
+            if part
+                .content_type()
+                .and_then(|ct| ct.subtype())
+                .is_some_and(|subtype| subtype.eq_ignore_ascii_case("rfc822-headers")) =>
+        {
+            field(field_blocks(headers).first()?, "message-id").map(str::to_string)
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounce() {
+        let raw = b"From: Mail Delivery System <MAILER-DAEMON@mx.company.com>\r\n\
+            To: support@company.com\r\n\
+            Subject: Undelivered Mail Returned to Sender\r\n\
+            Content-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n\
+            \r\n\
+            --b\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            Your message could not be delivered.\r\n\
+            --b\r\n\
+            Content-Type: message/delivery-status\r\n\
+            \r\n\
+            Reporting-MTA: dns; mx.company.com\r\n\
+            \r\n\
+            Final-Recipient: rfc822; Anna@shop.example\r\n\
+            Action: failed\r\n\
+            Status: 5.1.1\r\n\
+            Diagnostic-Code: smtp; 550 5.1.1 <anna@shop.example>:\r\n\
+            \x20 Recipient address rejected: User unknown\r\n\
+            \r\n\
+            Final-Recipient: rfc822; paul@shop.example\r\n\
+            Action: delayed\r\n\
+            Status: 4.4.1\r\n\
+            --b\r\n\
+            Content-Type: text/rfc822-headers\r\n\
+            \r\n\
+            From: Support <support@company.com>\r\n\
+            To: anna@shop.example\r\n\
+            Message-ID: <r1@company.com>\r\n\
+            --b--\r\n";
+        let reports = parse_report(raw).unwrap();
+
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].original_message_id, "r1@company.com");
+        assert_eq!(reports[0].recipient, "anna@shop.example");
+        assert_eq!(reports[0].state, DeliveryState::Bounced);
+        assert_eq!(
+            reports[0].reason.as_deref(),
+            Some("550 5.1.1 <anna@shop.example>: Recipient address rejected: User unknown")
+        );
+    }
+
+    #[test]
+    fn test_read_receipt() {
+        let raw = b"From: anna@shop.example\r\n\
+            To: support@company.com\r\n\
+            Content-Type: multipart/report; report-type=disposition-notification; boundary=\"b\"\r\n\
+            \r\n\
+            --b\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            The message was displayed.\r\n\
+            --b\r\n\
+            Content-Type: message/disposition-notification\r\n\
+            \r\n\
+            Reporting-UA: shop.example; Thunderbird\r\n\
+            Final-Recipient: rfc822; anna@shop.example\r\n\
+            Original-Message-ID: <r2@company.com>\r\n\
+            Disposition: manual-action/MDN-sent-manually; displayed\r\n\
+            --b--\r\n";
+        let reports = parse_report(raw).unwrap();
+
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].original_message_id, "r2@company.com");
+        assert_eq!(reports[0].state, DeliveryState::Read);
+    }
+
+    #[test]
+    fn test_regular_email_is_not_a_report() {
+        let raw = b"From: anna@shop.example\r\n\
+            To: support@company.com\r\n\
+            \r\n\
+            Hello\r\n";
+        assert!(parse_report(raw).is_none());
+    }
+}
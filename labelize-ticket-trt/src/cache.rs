@@ -0,0 +1,91 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const CACHE_DIR: &str = "./data/label_cache";
+
+/// Parsed LLM answer kept for a labeling request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedLabel {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub description: String,
+}
+
+/// On-disk cache of labeling results, keyed by a hash of everything sent to the model
+pub struct LabelCache {
+    dir: PathBuf,
+}
+
+impl LabelCache {
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(LabelCache {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Compute the cache key for a request. Every part that can change the answer must be included.
+    pub fn key(parts: &[&str]) -> String {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part.as_bytes());
+            // Separator so that ["ab", "c"] and ["a", "bc"] don't collide
+            hasher.update([0u8]);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedLabel> {
+        let content = fs::read_to_string(self.path(key)).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(label) => Some(label),
+            Err(e) => {
+                // A corrupted entry is treated as a miss, it will be overwritten
+                eprintln!("Ignoring unreadable cache entry {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, key: &str, label: &CachedLabel) -> Result<()> {
+        // Write then rename, so that a concurrent reader never sees a partial file
+        let tmp_path = self.dir.join(format!("{}.json.tmp", key));
+        fs::write(&tmp_path, serde_json::to_string(label)?)?;
+        fs::rename(tmp_path, self.path(key))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_roundtrip() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("label_cache_test_{}", std::process::id()));
+        let cache = LabelCache::new(&dir)?;
+
+        let key = LabelCache::key(&["model", "prompt"]);
+        assert_ne!(key, LabelCache::key(&["modelp", "rompt"]));
+        assert!(cache.get(&key).is_none());
+
+        let label = CachedLabel {
+            title: "Database outage".to_string(),
+            tags: vec!["database".to_string()],
+            description: "Production database is down".to_string(),
+        };
+        cache.put(&key, &label)?;
+        assert_eq!(cache.get(&key), Some(label));
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use common::dto::{CommonMessage, NewTicket};
+use serde::Deserialize;
+
+use crate::labeler::Labeler;
+
+/// A message of the golden dataset, with the labeling a human expects for it
+#[derive(Deserialize, Debug)]
+pub struct GoldenExample {
+    pub message: CommonMessage,
+    pub expected_title: String,
+    pub expected_tags: Vec<String>,
+}
+
+/// Load a JSONL golden dataset, one `GoldenExample` per line
+pub fn load_dataset(path: impl AsRef<Path>) -> Result<Vec<GoldenExample>> {
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read dataset {}", path.as_ref().display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).with_context(|| format!("Invalid example on line {}", i + 1))
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct EvalReport {
+    pub examples: usize,
+    /// The model answered, but not with JSON following the schema
+    pub schema_failures: usize,
+    /// Any other failure (transport, empty answer...)
+    pub errors: usize,
+    pub tag_precision: f64,
+    pub tag_recall: f64,
+    /// Mean word-level Jaccard similarity between produced and expected titles
+    pub title_similarity: f64,
+}
+
+impl EvalReport {
+    pub fn schema_failure_rate(&self) -> f64 {
+        ratio(self.schema_failures, self.examples)
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "examples:            {}", self.examples)?;
+        writeln!(f, "tag precision:       {:.3}", self.tag_precision)?;
+        writeln!(f, "tag recall:          {:.3}", self.tag_recall)?;
+        writeln!(f, "title similarity:    {:.3}", self.title_similarity)?;
+        writeln!(
+            f,
+            "schema failure rate: {:.3} ({} failures)",
+            self.schema_failure_rate(),
+            self.schema_failures
+        )?;
+        write!(f, "other errors:        {}", self.errors)
+    }
+}
+
+fn ratio(num: usize, den: usize) -> f64 {
+    if den == 0 {
+        0.0
+    } else {
+        num as f64 / den as f64
+    }
+}
+
+fn normalize_tags(tags: &[String]) -> HashSet<String> {
+    tags.iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+fn words(s: &str) -> HashSet<String> {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+pub fn title_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (words(a), words(b));
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    ratio(a.intersection(&b).count(), a.union(&b).count())
+}
+
+/// Run every example through the labeler. Tags are micro-averaged; a failed example counts
+/// its expected tags as missed and its title similarity as zero.
+pub async fn evaluate(labeler: &impl Labeler, dataset: Vec<GoldenExample>) -> EvalReport {
+    let mut report = EvalReport {
+        examples: dataset.len(),
+        ..Default::default()
+    };
+    let (mut true_positives, mut predicted, mut expected) = (0, 0, 0);
+    let mut title_similarity_sum = 0.0;
+
+    for (i, example) in dataset.into_iter().enumerate() {
+        let expected_tags = normalize_tags(&example.expected_tags);
+        expected += expected_tags.len();
+
+        let ticket = NewTicket {
+            id: format!("eval-{}", i),
+            init_message: example.message,
+        };
+        match labeler.label(&ticket).await {
+            Ok(labeled) => {
+                let tags = normalize_tags(&labeled.tags);
+                true_positives += tags.intersection(&expected_tags).count();
+                predicted += tags.len();
+                title_similarity_sum += title_similarity(&labeled.title, &example.expected_title);
+            }
+            Err(e) if e.downcast_ref::<serde_json::Error>().is_some() => {
+                eprintln!("Example {}: answer does not follow the schema: {}", i, e);
+                report.schema_failures += 1;
+            }
+            Err(e) => {
+                eprintln!("Example {}: labeling failed: {}", i, e);
+                report.errors += 1;
+            }
+        }
+    }
+
+    report.tag_precision = ratio(true_positives, predicted);
+    report.tag_recall = ratio(true_positives, expected);
+    report.title_similarity = if report.examples == 0 {
+        0.0
+    } else {
+        title_similarity_sum / report.examples as f64
+    };
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labeler::OpenRouterLabeler;
+    use crate::prompt::{DEFAULT_PROMPT_VERSION, PromptTemplate};
+    use crate::stub;
+    use openrouter_rs::OpenRouterClient;
+
+    #[test]
+    fn test_title_similarity() {
+        assert_eq!(title_similarity("Database outage", "database OUTAGE"), 1.0);
+        assert_eq!(title_similarity("Database outage", "Login issue"), 0.0);
+        assert_eq!(
+            title_similarity("Database outage", "Production database down"),
+            0.25
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_golden_dataset_against_stub() -> Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+        tokio::spawn(stub::serve(listener));
+
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let client = OpenRouterClient::builder()
+            .api_key("stub")
+            .base_url(base_url)
+            .build()?;
+        let template = PromptTemplate::load(manifest_dir.join("prompts"), DEFAULT_PROMPT_VERSION)?;
+        let labeler = OpenRouterLabeler::new(client, "stub", template, None);
+
+        let dataset = load_dataset(manifest_dir.join("eval/golden.jsonl"))?;
+        let examples = dataset.len();
+        let report = evaluate(&labeler, dataset).await;
+        println!("{}", report);
+
+        assert_eq!(report.examples, examples);
+        assert_eq!(report.errors, 0);
+        assert_eq!(report.schema_failures, 0);
+        assert!(report.tag_recall > 0.5);
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use std::time::Instant;
+
+use common::dto::{LlmUsage, NewTicket};
+use common::kb::{self, Retriever};
+use common::llm;
+use openrouter_rs::{OpenRouterClient, types::ResponseFormat};
+
+use crate::cache::{CachedLabel, LabelCache};
+use crate::prompt::PromptTemplate;
+
+#[derive(Debug)]
+pub struct FormattedTicket {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub description: String,
+    pub usage: LlmUsage,
+    /// Knowledge-base articles given to the model
+    pub citations: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LLMResponse {
+    title: String,
+    tags: Vec<String>,
+    description: String,
+}
+
+/// Produces the metadata of a ticket
+#[allow(async_fn_in_trait)]
+pub trait Labeler {
+    /// Version of the prompt in use, recorded on the labeled ticket
+    fn prompt_version(&self) -> &str;
+
+    /// Label a ticket. A model answer that doesn't match the schema is returned as a `serde_json::Error`
+    async fn label(&self, ticket: &NewTicket) -> anyhow::Result<FormattedTicket>;
+}
+
+/// Labeler backed by OpenRouter, or any OpenAI-compatible server through `OPENROUTER_BASE_URL`
+pub struct OpenRouterLabeler {
+    client: OpenRouterClient,
+    model: String,
+    template: PromptTemplate,
+    cache: Option<LabelCache>,
+    retriever: Option<Retriever>,
+}
+
+impl OpenRouterLabeler {
+    pub fn new(
+        client: OpenRouterClient,
+        model: &str,
+        template: PromptTemplate,
+        cache: Option<LabelCache>,
+    ) -> Self {
+        OpenRouterLabeler {
+            client,
+            model: model.to_string(),
+            template,
+            cache,
+            retriever: None,
+        }
+    }
+
+    /// Ground the labeling on the knowledge-base chunks closest to the message
+    pub fn with_retriever(mut self, retriever: Retriever) -> Self {
+        self.retriever = Some(retriever);
+        self
+    }
+}
+
+impl Labeler for OpenRouterLabeler {
+    fn prompt_version(&self) -> &str {
+        &self.template.version
+    }
+
+    async fn label(&self, msg: &NewTicket) -> anyhow::Result<FormattedTicket> {
+        println!("Processing message: {:?}", msg);
+
+        let snippets = match &self.retriever {
+            Some(retriever) => retriever.retrieve(&msg.init_message.body).await?,
+            None => vec![],
+        };
+        let citations = kb::cited_articles(&snippets);
+
+        // send to llm, expect a title, tags and description
+        let user_prompt = self.template.render(msg, &snippets)?;
+
+        // Redeliveries of the same message must not cost another LLM call.
+        // The ticket id is left out of the key on purpose: it does not change the labeling.
+        let started = Instant::now();
+        let cache_key = LabelCache::key(&[
+            &self.model,
+            &self.template.version,
+            self.template.source(),
+            &self.template.schema.to_string(),
+            &serde_json::to_string(&msg.init_message)?,
+            &serde_json::to_string(&snippets)?,
+        ]);
+        if let Some(cached) = self.cache.as_ref().and_then(|c| c.get(&cache_key)) {
+            println!("Cache hit for message (key={})", cache_key);
+            return Ok(FormattedTicket {
+                title: cached.title,
+                tags: cached.tags,
+                description: cached.description,
+                usage: llm::cached_usage(&self.model, started.elapsed().as_millis() as u64),
+                citations,
+            });
+        }
+
+        let format =
+            ResponseFormat::json_schema("labelled_ticket", true, self.template.schema.clone());
+
+        println!("Sending request to LLM...");
+        let completion =
+            llm::complete(&self.client, &self.model, user_prompt, Some(format)).await?;
+        let content = completion.content;
+        println!("Response: {:#?}", content);
+
+        let parsed = serde_json::from_str::<LLMResponse>(&content)?;
+        println!("Parsed response: {:?}", parsed);
+
+        // The labels are paid for already, a cache that cannot be written only costs a new call
+        if let Some(cache) = &self.cache
+            && let Err(e) = cache.put(
+                &cache_key,
+                &CachedLabel {
+                    title: parsed.title.clone(),
+                    tags: parsed.tags.clone(),
+                    description: parsed.description.clone(),
+                },
+            )
+        {
+            eprintln!("Failed to cache labels (key={}): {:#}", cache_key, e);
+        }
+
+        // Deserialize into struct
+        let formatted_ticket = FormattedTicket {
+            title: parsed.title,
+            tags: parsed.tags,
+            description: parsed.description,
+            usage: completion.usage,
+            citations,
+        };
+        eprintln!("Formatted ticket: {:#?}", formatted_ticket);
+        Ok(formatted_ticket)
+    }
+}
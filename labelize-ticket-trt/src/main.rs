@@ -1,48 +1,142 @@
-use common::{COMMON_MSG_QUEUE, LABELED_TICKETS_QUEUE};
-use common::dto::{CommonMessage, NewTicket, LabeledTicket};
+mod cache;
+mod eval;
+mod labeler;
+mod prompt;
+mod stub;
+
+use cache::{LabelCache, CACHE_DIR};
+use clap::{Parser, Subcommand};
+use common::{TRANSLATED_MSG_QUEUE, UNROUTED_TICKETS_QUEUE};
+use common::dto::{CommonMessage, LabeledTicket, NewTicket};
 use common::queue::QueueManager;
+use common::kb::{self, KbIndex, Retriever, embed::HashEmbedder};
+use common::llm;
 use common::queue::kafka::KafkaQueueManager;
-use openrouter_rs::{
-    OpenRouterClient,
-    api::chat::*,
-    types::{ResponseFormat, Role},
-};
-use pgmq::PgmqError;
-use std::env;
-
-const MODEL: &str = "openrouter/free";
-
-fn get_api_key() -> String {
-    env::var("OPENROUTER_API_KEY").expect("OPENROUTER_API_KEY must be set")
+use labeler::{Labeler, OpenRouterLabeler};
+use prompt::PromptTemplate;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render the prompt for a NewTicket JSON file, without calling the model
+    Render {
+        /// Path to a JSON-serialized NewTicket
+        ticket: PathBuf,
+    },
+
+    /// Run a golden dataset through the labeler and report labeling quality
+    Eval {
+        /// JSONL file of golden examples
+        #[arg(long)]
+        dataset: PathBuf,
+
+        /// Model to evaluate, defaults to `LLM_MODEL`
+        #[arg(long)]
+        model: Option<String>,
+    },
+
+    /// (Re)build the knowledge-base index from `KB_DIR` into `KB_INDEX_PATH`
+    IndexKb,
+
+    /// Serve a local OpenAI-compatible stub, to point `OPENROUTER_BASE_URL` at
+    ServeStub {
+        #[arg(long, default_value_t = 8089)]
+        port: u16,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<(), PgmqError> {
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    match args.command {
+        Some(Command::Render { ticket }) => {
+            let template = PromptTemplate::from_env()?;
+            let ticket: NewTicket = serde_json::from_str(&std::fs::read_to_string(ticket)?)?;
+            println!("{}", template.render(&ticket, &[])?);
+            println!("{}", serde_json::to_string_pretty(&template.schema)?);
+            return Ok(());
+        }
+        Some(Command::Eval { dataset, model }) => {
+            let model = model.unwrap_or_else(llm::model_from_env);
+            let template = PromptTemplate::from_env()?;
+            println!("Evaluating model {} with prompt version {}", model, template.version);
+            // No cache: we want to measure the model, not replay previous answers
+            let mut labeler = OpenRouterLabeler::new(llm::client_from_env()?, &model, template, None);
+            // Same grounding as in production, when a knowledge base is available
+            match Retriever::from_env().await {
+                Ok(retriever) => labeler = labeler.with_retriever(retriever),
+                Err(e) => eprintln!("Evaluating without knowledge base: {:#}", e),
+            }
+            let report = eval::evaluate(&labeler, eval::load_dataset(dataset)?).await;
+            println!("{}", report);
+            return Ok(());
+        }
+        Some(Command::IndexKb) => {
+            let (dir, index_path) = kb::paths_from_env();
+            let index = KbIndex::build(&dir, &HashEmbedder::default()).await?;
+            index.save(&index_path)?;
+            println!("Indexed {} chunks from {} into {}", index.chunks.len(), dir, index_path);
+            return Ok(());
+        }
+        Some(Command::ServeStub { port }) => {
+            let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+            println!("Stub LLM listening on {}", listener.local_addr()?);
+            return stub::serve(listener).await;
+        }
+        None => {}
+    }
+
+    let template = PromptTemplate::from_env().expect("Failed to load prompt template");
+    println!("Using prompt version {}", template.version);
+
     let queue_mgr = KafkaQueueManager::new()
         .await
         .expect("Failed to connect to postgres");
 
     // Create a queue
     queue_mgr
-        .create(COMMON_MSG_QUEUE)
+        .create(TRANSLATED_MSG_QUEUE)
         .await
         .expect("Failed to create queue");
 
     // Init LLM
-    let client = OpenRouterClient::builder()
-        .api_key(get_api_key())
-        .build()
-        .expect("Failed to create OpenRouter client");
+    let client = llm::client_from_env().expect("Failed to create OpenRouter client");
+    let cache = LabelCache::new(CACHE_DIR).expect("Failed to create label cache");
+    let mut labeler = OpenRouterLabeler::new(
+        client,
+        &llm::model_from_env(),
+        template,
+        Some(cache),
+    );
+    // Labeling still works ungrounded, e.g. before the first `index-kb`
+    match Retriever::from_env().await {
+        Ok(retriever) => labeler = labeler.with_retriever(retriever),
+        Err(e) => eprintln!("Labeling without knowledge base: {:#}", e),
+    }
 
     queue_mgr
-        .register_read(COMMON_MSG_QUEUE, &async |msg: common::queue::Message<
+        .register_read(TRANSLATED_MSG_QUEUE, &async |msg: common::queue::Message<
             CommonMessage,
         >| {
+            // The channel normalizers already picked the ticket: the hinted one or a new one
             let new_ticket = NewTicket {
-                id: "99".to_string(),
+                id: msg
+                    .message
+                    .ticket_id
+                    .clone()
+                    .or_else(|| msg.message.ticket_hint.clone())
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
                 init_message: msg.message,
             };
-            on_message(&client, new_ticket).await
+            on_message(&labeler, new_ticket).await
         })
         .await
         .expect("Failed to register read handler");
@@ -50,26 +144,11 @@ async fn main() -> Result<(), PgmqError> {
     Ok(())
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct FormattedTicket {
-    id: String,
-    title: String,
-    tags: Vec<String>,
-    description: String,
-}
-
-#[derive(Debug, serde::Deserialize)]
-struct LLMResponse {
-    title: String,
-    tags: Vec<String>,
-    description: String,
-}
-
-async fn on_message(client: &OpenRouterClient, msg: NewTicket) -> anyhow::Result<()> {
+async fn on_message(labeler: &impl Labeler, msg: NewTicket) -> anyhow::Result<()> {
     println!("Received a message: {:?}", msg);
 
-    let formatted_ticket = labelize_message(client, &msg).await?;
-    
+    let formatted_ticket = labeler.label(&msg).await?;
+
     // Create a complete labeled ticket
     let labeled_ticket = LabeledTicket {
         id: msg.id.clone(),
@@ -81,90 +160,22 @@ async fn on_message(client: &OpenRouterClient, msg: NewTicket) -> anyhow::Result
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs(),
+        llm_usage: Some(formatted_ticket.usage),
+        prompt_version: Some(labeler.prompt_version().to_string()),
+        kb_citations: formatted_ticket.citations,
+        routing: None,
     };
 
-    // Send to labeled tickets queue for storage
+    // Send to ticket-router, which forwards it to storage
     let queue_mgr = KafkaQueueManager::new().await?;
-    queue_mgr.create(LABELED_TICKETS_QUEUE).await?;
+    queue_mgr.create(UNROUTED_TICKETS_QUEUE).await?;
     
-    let stored_id = queue_mgr.send(LABELED_TICKETS_QUEUE, &labeled_ticket).await?;
-    println!("Labeled ticket sent to storage queue (id={})", stored_id);
+    let stored_id = queue_mgr.send(UNROUTED_TICKETS_QUEUE, &labeled_ticket).await?;
+    println!("Labeled ticket sent to routing queue (id={})", stored_id);
 
     Ok(())
 }
 
-async fn labelize_message(
-    client: &OpenRouterClient,
-    msg: &NewTicket,
-) -> anyhow::Result<FormattedTicket> {
-    println!("Processing message: {:?}", msg);
-
-    // send to llm, expect a title, tags and description
-    let input_json = serde_json::to_string(&msg)?;
-
-    let user_prompt = format!(
-        "Given this ticket, :\n{}\nGenerate its metadata. Output ONLY the JSON, without any additional text.",
-        input_json
-    );
-
-    let format = ResponseFormat::json_schema(
-        "labelled_ticket",
-        true,
-        serde_json::json!({
-          "type": "object",
-          "properties": {
-            "title": {
-              "type": "string",
-              "description": "A concise title for the ticket, summarizing the main issue or request.",
-            },
-            "tags": {
-              "type": "array",
-              "items": {
-                "type": "string"
-              },
-              "description": "Tags for the ticket: a list of relevant keywords or categories that apply to the ticket, such as 'database', 'outage', 'production', etc.",
-            },
-            "description": {
-              "type": "string",
-              "description": "A TL;DR of the ticket, summarizing the key details and context in a few sentences.",
-            },
-          },
-          "additionalProperties": false,
-          "required": ["title", "tags", "description"]
-        }),
-    );
-
-    // Send request
-    let request = ChatCompletionRequest::builder()
-        .model(MODEL)
-        .messages(vec![Message::new(Role::User, user_prompt)])
-        .response_format(format)
-        .build()?;
-
-    println!("Sending request to LLM...");
-
-    // Extract raw text
-    let response = client.send_chat_completion(&request).await?;
-    let content = response.choices[0]
-        .content()
-        .expect("LLM Content should be present")
-        .to_string();
-    println!("Response: {:#?}", content);
-
-    let parsed = serde_json::from_str::<LLMResponse>(&content)?;
-    println!("Parsed response: {:?}", parsed);
-
-    // Deserialize into struct
-    let formatted_ticket = FormattedTicket {
-        id: msg.id.clone(),
-        title: parsed.title,
-        tags: parsed.tags,
-        description: parsed.description,
-    };
-    eprintln!("Formatted ticket: {:#?}", formatted_ticket);
-    Ok(formatted_ticket)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,25 +186,31 @@ mod tests {
         NewTicket {
             id: "384594739".to_string(),
             init_message: CommonMessage {
-                contact: "jack.hammer@mycom.com".to_string(),
                 origin: common::dto::Origin::WhatsApp,
-                body: "Hello, \n Our production database is down across all regions. Can you help us solve this issue ASAP?".to_string(),
                 timestamp: 0,
-                ticket_hint: None,
+                ..CommonMessage::test(
+                    "jack.hammer@mycom.com",
+                    "Hello, \n Our production database is down across all regions. Can you help us solve this issue ASAP?",
+                )
             },
         }
     }
 
     #[tokio::test]
     async fn test_labelize_message_with_fake_ticket() {
-        let client = OpenRouterClient::builder()
-            .api_key(get_api_key())
-            .build()
-            .expect("Failed to create OpenRouter client");
-
+        let client = llm::client_from_env().expect("Failed to create OpenRouter client");
+
+        let cache = LabelCache::new(std::env::temp_dir().join("label_cache_llm_test"))
+            .expect("Failed to create label cache");
+        let template = PromptTemplate::load(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("prompts"),
+            prompt::DEFAULT_PROMPT_VERSION,
+        )
+        .expect("Failed to load prompt template");
+        let labeler = OpenRouterLabeler::new(client, llm::DEFAULT_MODEL, template, Some(cache));
         let ticket = fake_new_ticket();
 
-        let result = labelize_message(&client, &ticket).await;
+        let result = labeler.label(&ticket).await;
         assert!(
             result.is_ok(),
             "LLM processing should succeed with a fake ticket: \n {:?}",
@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use common::dto::NewTicket;
+use common::kb::KbSnippet;
+use minijinja::{Environment, context};
+
+pub const DEFAULT_PROMPTS_DIR: &str = "./prompts";
+pub const DEFAULT_PROMPT_VERSION: &str = "v4";
+
+const TEMPLATE_FILE: &str = "prompt.j2";
+const SCHEMA_FILE: &str = "schema.json";
+
+/// A versioned prompt: `<prompts_dir>/<version>/prompt.j2` and the JSON schema the answer must follow
+pub struct PromptTemplate {
+    pub version: String,
+    source: String,
+    pub schema: serde_json::Value,
+}
+
+impl PromptTemplate {
+    pub fn load(prompts_dir: impl AsRef<Path>, version: &str) -> Result<Self> {
+        let dir = prompts_dir.as_ref().join(version);
+        let source = fs::read_to_string(dir.join(TEMPLATE_FILE))
+            .with_context(|| format!("Failed to read prompt template in {}", dir.display()))?;
+        let schema = fs::read_to_string(dir.join(SCHEMA_FILE))
+            .with_context(|| format!("Failed to read prompt schema in {}", dir.display()))?;
+
+        let template = PromptTemplate {
+            version: version.to_string(),
+            source,
+            schema: serde_json::from_str(&schema).context("Invalid prompt schema")?,
+        };
+        // Fail at startup rather than on the first message if the template is broken
+        Environment::new().template_from_str(&template.source)?;
+        Ok(template)
+    }
+
+    /// Load the template selected through `PROMPTS_DIR` and `PROMPT_VERSION`
+    pub fn from_env() -> Result<Self> {
+        let dir = std::env::var("PROMPTS_DIR").unwrap_or_else(|_| DEFAULT_PROMPTS_DIR.to_string());
+        let version =
+            std::env::var("PROMPT_VERSION").unwrap_or_else(|_| DEFAULT_PROMPT_VERSION.to_string());
+        Self::load(dir, &version)
+    }
+
+    /// Template source, used to invalidate cached labels when the prompt changes
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Templates that don't use `snippets` simply ignore the knowledge-base excerpts
+    pub fn render(&self, ticket: &NewTicket, snippets: &[KbSnippet]) -> Result<String> {
+        let env = Environment::new();
+        let template = env.template_from_str(&self.source)?;
+        Ok(template.render(context! {
+            ticket => ticket,
+            ticket_json => serde_json::to_string(ticket)?,
+            snippets => snippets,
+        })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::dto::{Attachment, CommonMessage};
+
+    #[test]
+    fn test_render_shipped_prompt() -> Result<()> {
+        let prompts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("prompts");
+        let template = PromptTemplate::load(prompts_dir, DEFAULT_PROMPT_VERSION)?;
+        let ticket = NewTicket {
+            id: "42".to_string(),
+            init_message: CommonMessage {
+                timestamp: 0,
+                ..CommonMessage::test("jane@example.com", "The export button does nothing")
+            },
+        };
+
+        let snippets = vec![KbSnippet {
+            article: "export.md".to_string(),
+            text: "Exports are disabled on trial accounts.".to_string(),
+            score: 0.5,
+        }];
+        let prompt = template.render(&ticket, &snippets)?;
+        assert!(prompt.contains("The export button does nothing"));
+        assert!(prompt.contains("--- export.md ---\nExports are disabled on trial accounts."));
+        assert!(prompt.ends_with("without any additional text."));
+
+        let prompt = template.render(&ticket, &[])?;
+        assert!(!prompt.contains("knowledge base"));
+        assert!(!prompt.contains("attached"));
+        assert_eq!(template.schema["required"][0], "title");
+
+        let mut ticket = ticket;
+        ticket.init_message.attachments.push(Attachment {
+            filename: "error.png".to_string(),
+            content_type: "image/png".to_string(),
+            size: 1024,
+            checksum: String::new(),
+            storage_key: None,
+            source_id: None,
+        });
+        let prompt = template.render(&ticket, &[])?;
+        assert!(prompt.contains("The customer attached error.png (image/png, 1024 bytes)."));
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use axum::{Json, Router, routing::post};
+use common::dto::NewTicket;
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+
+/// Keywords the stub recognizes as tags
+const KNOWN_TAGS: &[&str] = &[
+    "database",
+    "outage",
+    "production",
+    "login",
+    "password",
+    "billing",
+    "invoice",
+    "refund",
+    "delivery",
+    "export",
+    "email",
+    "mobile",
+    "payment",
+    "account",
+];
+
+/// Serve a minimal OpenAI-compatible `/chat/completions` endpoint with deterministic answers,
+/// so that the pipeline and the evaluation can run without network access
+pub async fn serve(listener: TcpListener) -> anyhow::Result<()> {
+    let app = Router::new().route("/chat/completions", post(chat_completions));
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn chat_completions(Json(request): Json<Value>) -> Json<Value> {
+    let prompt = request["messages"]
+        .as_array()
+        .and_then(|messages| messages.last())
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default();
+    let answer = stub_label(prompt);
+
+    Json(json!({
+        "id": "stub-completion",
+        "created": 0,
+        "model": request["model"].as_str().unwrap_or("stub"),
+        "object": "chat.completion",
+        "choices": [{
+            "finish_reason": "stop",
+            "message": { "role": "assistant", "content": answer.to_string() },
+        }],
+        "usage": {
+            "prompt_tokens": prompt.split_whitespace().count(),
+            "completion_tokens": answer.to_string().split_whitespace().count(),
+            "total_tokens": 0,
+        },
+    }))
+}
+
+/// Label from the ticket embedded in the prompt: the first sentence as title, known keywords as tags
+fn stub_label(prompt: &str) -> Value {
+    let body = prompt
+        .lines()
+        .find_map(|line| serde_json::from_str::<NewTicket>(line.trim()).ok())
+        .map(|ticket| ticket.init_message.body)
+        .unwrap_or_else(|| prompt.to_string());
+
+    let title: String = body
+        .split(['.', '?', '!', '\n'])
+        .map(str::trim)
+        .find(|s| !s.is_empty())
+        .unwrap_or("Support request")
+        .chars()
+        .take(80)
+        .collect();
+    let lower = body.to_lowercase();
+    let tags: Vec<&str> = KNOWN_TAGS
+        .iter()
+        .copied()
+        .filter(|tag| lower.contains(tag))
+        .collect();
+
+    json!({
+        "title": title,
+        "tags": tags,
+        "description": body.chars().take(200).collect::<String>(),
+    })
+}
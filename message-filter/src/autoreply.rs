@@ -0,0 +1,169 @@
+//! Messages written by a machine: out-of-office and vacation replies, bounces, mailing lists.
+//! Labeling them is wasted, answering them can start a loop with the other auto-responder.
+
+use common::dto::CommonMessage;
+
+/// Local parts of the addresses of mail systems and of mailboxes nobody reads
+const MACHINE_SENDERS: [&str; 6] = [
+    "mailer-daemon",
+    "postmaster",
+    "noreply",
+    "no-reply",
+    "do-not-reply",
+    "donotreply",
+];
+
+/// Beginnings of the subjects auto-responders use, lowercased
+const AUTO_SUBJECTS: [&str; 10] = [
+    "out of office",
+    "out of the office",
+    "automatic reply",
+    "auto-reply",
+    "autoreply",
+    "réponse automatique",
+    "absence du bureau",
+    "abwesenheitsnotiz",
+    "undeliverable",
+    "delivery status notification",
+];
+
+/// What gives the message away as an auto-reply, if it is one
+pub fn detect(message: &CommonMessage) -> Option<String> {
+    header_reason(message).or_else(|| heuristic_reason(message))
+}
+
+/// Headers of RFC 3834 and of the common auto-responders. Header names are lowercased.
+fn header_reason(message: &CommonMessage) -> Option<String> {
+    let header = |name: &str| message.headers.get(name).map(|value| value.trim());
+    if let Some(value) = header("auto-submitted")
+        && !value.eq_ignore_ascii_case("no")
+    {
+        return Some(format!("Auto-Submitted: {}", value));
+    }
+    for name in ["x-autoreply", "x-autorespond"] {
+        if header(name).is_some() {
+            return Some(format!("{} header", name));
+        }
+    }
+    // Exchange sets it on its own out-of-office replies
+    if header("x-auto-response-suppress").is_some_and(|value| {
+        value.eq_ignore_ascii_case("all") || value.to_lowercase().contains("oof")
+    }) {
+        return Some("X-Auto-Response-Suppress header".to_string());
+    }
+    if let Some(value) = header("precedence")
+        && ["bulk", "junk", "list", "auto_reply"]
+            .iter()
+            .any(|precedence| value.eq_ignore_ascii_case(precedence))
+    {
+        return Some(format!("Precedence: {}", value));
+    }
+    if header("list-id").is_some() {
+        return Some("List-Id header".to_string());
+    }
+    // `<>`, the null sender of bounces
+    if header("return-path").is_some_and(|value| value.trim_matches(['<', '>', ' ']).is_empty()) {
+        return Some("Null Return-Path".to_string());
+    }
+    None
+}
+
+/// For the channels without headers, and the auto-responders forgetting them
+fn heuristic_reason(message: &CommonMessage) -> Option<String> {
+    let local = message
+        .contact
+        .rsplit_once('@')
+        .map(|(local, _)| local.to_lowercase())?;
+    if MACHINE_SENDERS
+        .iter()
+        .any(|sender| local == *sender || local.starts_with(&format!("{}+", sender)))
+    {
+        return Some(format!("Sent by {}", message.contact));
+    }
+    let subject = message.subject.as_deref()?.trim().to_lowercase();
+    AUTO_SUBJECTS
+        .iter()
+        .find(|prefix| subject.starts_with(*prefix))
+        .map(|_| {
+            format!(
+                "Subject: {}",
+                message.subject.as_deref().unwrap_or_default()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(from: &str, subject: &str, headers: &[(&str, &str)]) -> CommonMessage {
+        CommonMessage {
+            subject: Some(subject.to_string()),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..CommonMessage::test(from, "I am out of the office until Monday.")
+        }
+    }
+
+    #[test]
+    fn test_headers() {
+        assert_eq!(
+            detect(&email(
+                "anna@shop.example",
+                "Re: Order 42",
+                &[("auto-submitted", "auto-replied")]
+            ))
+            .as_deref(),
+            Some("Auto-Submitted: auto-replied")
+        );
+        assert!(
+            detect(&email(
+                "anna@shop.example",
+                "Re: Order 42",
+                &[("precedence", "bulk")]
+            ))
+            .is_some()
+        );
+        assert!(
+            detect(&email(
+                "anna@shop.example",
+                "Re: Order 42",
+                &[("return-path", "<>")]
+            ))
+            .is_some()
+        );
+        // Explicitly written by a person
+        assert_eq!(
+            detect(&email(
+                "anna@shop.example",
+                "Re: Order 42",
+                &[
+                    ("auto-submitted", "no"),
+                    ("return-path", "<anna@shop.example>")
+                ]
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn test_heuristics() {
+        assert!(detect(&email("MAILER-DAEMON@mx.shop.example", "Failure", &[])).is_some());
+        assert!(
+            detect(&email(
+                "anna@shop.example",
+                "Réponse automatique : Commande 42",
+                &[]
+            ))
+            .is_some()
+        );
+        assert!(detect(&email("anna@shop.example", "Out of Office: Order 42", &[])).is_some());
+        assert_eq!(
+            detect(&email("anna@shop.example", "Where is my order?", &[])),
+            None
+        );
+        assert_eq!(detect(&email("+33612345678", "Out of office", &[])), None);
+    }
+}
@@ -0,0 +1,187 @@
+mod autoreply;
+mod rules;
+mod spam;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use common::{
+    ACCEPTED_MSG_QUEUE, COMMON_MSG_QUEUE,
+    dto::CommonMessage,
+    queue::{Message, QueueManager, kafka::KafkaQueueManager},
+    store::{
+        FilterReason, FilteredMessage, FilteredMessages, postgres::PostgresTicketRepository,
+        postgres_url_from_env,
+    },
+};
+use rules::{RateLimit, SenderList};
+use spam::{HeuristicScorer, SpamScorer};
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the messages of a contact that were filtered instead of labeled, most recent first
+    Filtered {
+        contact: String,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+/// Checks deciding which messages are worth labeling
+struct Filter<S: SpamScorer> {
+    blocklist: SenderList,
+    /// Contacts never blocked, rate limited nor taken for spam, e.g. partners sending a lot
+    allowlist: SenderList,
+    rate_limit: Option<RateLimit>,
+    scorer: S,
+    spam_threshold: f32,
+}
+
+impl Filter<HeuristicScorer> {
+    /// `FILTER_BLOCKLIST` and `FILTER_ALLOWLIST`, `FILTER_RATE_LIMIT` (e.g. `20/3600`, none when
+    /// unset) and `FILTER_SPAM_THRESHOLD`
+    fn from_env() -> Result<Self> {
+        let rate_limit = match std::env::var("FILTER_RATE_LIMIT") {
+            Ok(limit) if !limit.trim().is_empty() => Some(RateLimit::parse(&limit)?),
+            _ => None,
+        };
+        let spam_threshold = match std::env::var("FILTER_SPAM_THRESHOLD") {
+            Ok(threshold) => threshold
+                .trim()
+                .parse()
+                .context("Invalid FILTER_SPAM_THRESHOLD")?,
+            Err(_) => spam::DEFAULT_THRESHOLD,
+        };
+        Ok(Filter {
+            blocklist: SenderList::from_env("FILTER_BLOCKLIST"),
+            allowlist: SenderList::from_env("FILTER_ALLOWLIST"),
+            rate_limit,
+            scorer: HeuristicScorer,
+            spam_threshold,
+        })
+    }
+}
+
+impl<S: SpamScorer> Filter<S> {
+    /// Why the message should not be labeled, `None` when it should
+    fn check(&self, message: &CommonMessage) -> Option<FilterReason> {
+        // Even allowed contacts have auto-responders
+        if let Some(reason) = autoreply::detect(message) {
+            return Some(FilterReason::AutoReply(reason));
+        }
+        if self.allowlist.contains(&message.contact) {
+            return None;
+        }
+        if self.blocklist.contains(&message.contact) {
+            return Some(FilterReason::Blocked);
+        }
+        if let Some(rate_limit) = &self.rate_limit
+            && !rate_limit.allow(&message.contact, message.timestamp)
+        {
+            return Some(FilterReason::RateLimited);
+        }
+        let score = self.scorer.score(message);
+        (score >= self.spam_threshold).then_some(FilterReason::Spam(score))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if let Some(Command::Filtered { contact, limit }) = args.command {
+        let store = PostgresTicketRepository::connect(&postgres_url_from_env()).await?;
+        let filtered = store.filtered(&contact, limit).await?;
+        println!("{}", serde_json::to_string_pretty(&filtered)?);
+        return Ok(());
+    }
+
+    println!("Starting message filter...");
+    let queue_mgr = KafkaQueueManager::with_group_id("message-filter")
+        .await
+        .context("Failed to connect to Kafka")?;
+    queue_mgr.create(COMMON_MSG_QUEUE).await?;
+    queue_mgr.create(ACCEPTED_MSG_QUEUE).await?;
+
+    let filter = Filter::from_env()?;
+    let store = PostgresTicketRepository::connect(&postgres_url_from_env()).await?;
+
+    println!("Listening for messages on '{}'...", COMMON_MSG_QUEUE);
+    queue_mgr
+        .register_read(COMMON_MSG_QUEUE, &async |msg: Message<CommonMessage>| {
+            let message = msg.message;
+            match filter.check(&message) {
+                Some(reason) => {
+                    println!("Message from {} filtered: {:?}", message.contact, reason);
+                    store
+                        .record_filtered(&FilteredMessage {
+                            filtered_at: std::time::SystemTime::now()
+                                .duration_since(std::time::UNIX_EPOCH)?
+                                .as_secs(),
+                            message,
+                            reason,
+                        })
+                        .await
+                        .context("Failed to record filtered message")?;
+                }
+                None => {
+                    let sent_id = queue_mgr.send(ACCEPTED_MSG_QUEUE, &message).await?;
+                    println!("Message from {} accepted (id={})", message.contact, sent_id);
+                }
+            }
+            Ok(())
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::dto::Origin;
+
+    fn message(contact: &str, body: &str, timestamp: u64) -> CommonMessage {
+        CommonMessage {
+            origin: Origin::WhatsApp,
+            timestamp,
+            ..CommonMessage::test(contact, body)
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let filter = Filter {
+            blocklist: SenderList::parse("+33600000000"),
+            allowlist: SenderList::parse("+33611111111"),
+            rate_limit: Some(RateLimit::new(1, 60)),
+            scorer: HeuristicScorer,
+            spam_threshold: spam::DEFAULT_THRESHOLD,
+        };
+        let spam = "YOU HAVE WON THE CRYPTO LOTTERY, CLICK HERE";
+
+        assert_eq!(
+            filter.check(&message("+33612345678", "Where is my order?", 100)),
+            None
+        );
+        assert_eq!(
+            filter.check(&message("+33612345678", "Hello?", 110)),
+            Some(FilterReason::RateLimited)
+        );
+        assert_eq!(
+            filter.check(&message("+33600000000", "Hello", 100)),
+            Some(FilterReason::Blocked)
+        );
+        assert!(matches!(
+            filter.check(&message("+33622222222", spam, 100)),
+            Some(FilterReason::Spam(_))
+        ));
+        // Allowed contacts are not rate limited nor scored
+        assert_eq!(filter.check(&message("+33611111111", spam, 100)), None);
+        assert_eq!(filter.check(&message("+33611111111", spam, 101)), None);
+    }
+}
@@ -0,0 +1,138 @@
+//! Rules on the senders: block and allow lists, and a limit of messages per contact
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result, bail};
+use common::store::contact::Identity;
+
+/// Contacts and domains, e.g. `+33612345678, spam.example, @spam.example`. Entries are matched
+/// against the normalized contact, so `0033 6 12 34 56 78` blocks WhatsApp and Signal alike.
+#[derive(Default)]
+pub struct SenderList {
+    contacts: Vec<String>,
+    domains: Vec<String>,
+}
+
+impl SenderList {
+    /// Comma separated entries, a domain starts with `@`
+    pub fn parse(list: &str) -> SenderList {
+        let mut sender_list = SenderList::default();
+        for entry in list
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            match entry.strip_prefix('@') {
+                Some(domain) => sender_list.domains.push(domain.to_lowercase()),
+                None => sender_list.contacts.push(Identity::parse(entry).value),
+            }
+        }
+        sender_list
+    }
+
+    pub fn from_env(name: &str) -> SenderList {
+        SenderList::parse(&std::env::var(name).unwrap_or_default())
+    }
+
+    pub fn contains(&self, contact: &str) -> bool {
+        let identity = Identity::parse(contact);
+        if self.contacts.contains(&identity.value) {
+            return true;
+        }
+        let Some((_, domain)) = identity.value.rsplit_once('@') else {
+            return false;
+        };
+        // Subdomains too
+        self.domains.iter().any(|blocked| {
+            domain == blocked
+                || domain
+                    .strip_suffix(blocked.as_str())
+                    .is_some_and(|sub| sub.ends_with('.'))
+        })
+    }
+}
+
+/// At most `max` messages per contact in a sliding window of `window_secs`, by message timestamps.
+/// Counts are kept in memory: a restart forgets them, which only lets a flood through longer.
+/// Only the contacts that wrote within the window are kept.
+pub struct RateLimit {
+    max: usize,
+    window_secs: u64,
+    seen: Mutex<HashMap<String, VecDeque<u64>>>,
+}
+
+impl RateLimit {
+    pub fn new(max: usize, window_secs: u64) -> RateLimit {
+        RateLimit {
+            max,
+            window_secs,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// `<max>/<window in seconds>`, e.g. `20/3600`
+    pub fn parse(limit: &str) -> Result<RateLimit> {
+        let Some((max, window)) = limit.split_once('/') else {
+            bail!("Invalid rate limit '{}', expected <max>/<seconds>", limit);
+        };
+        let max = max
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid maximum in rate limit '{}'", limit))?;
+        let window_secs = window
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid window in rate limit '{}'", limit))?;
+        Ok(RateLimit::new(max, window_secs))
+    }
+
+    /// Count the message, returns false when the contact is over the limit. Messages over the
+    /// limit count too: a contact has to slow down to get through again.
+    pub fn allow(&self, contact: &str, timestamp: u64) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        let in_window = |time: &u64| *time + self.window_secs > timestamp;
+        seen.retain(|_, times| {
+            while times.front().is_some_and(|time| !in_window(time)) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = seen.entry(Identity::parse(contact).value).or_default();
+        times.push_back(timestamp);
+        times.len() <= self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sender_list() {
+        let list = SenderList::parse(" 0033 6 12 34 56 78, @spam.example, Bob@Shop.example ,");
+        assert!(list.contains("33612345678"));
+        assert!(list.contains("promo@spam.example"));
+        assert!(list.contains("promo@mail.spam.example"));
+        assert!(!list.contains("promo@notspam.example"));
+        assert!(list.contains("bob+orders@shop.example"));
+        assert!(!list.contains("anna@shop.example"));
+        assert!(!SenderList::default().contains("anna@shop.example"));
+    }
+
+    #[test]
+    fn test_rate_limit() -> Result<()> {
+        let limit = RateLimit::parse("2/60")?;
+        assert!(limit.allow("+33612345678", 100));
+        assert!(limit.allow("33612345678", 110));
+        assert!(!limit.allow("+33612345678", 120));
+        assert!(limit.allow("anna@shop.example", 120));
+        // 100 and 110 left the window
+        assert!(limit.allow("+33612345678", 175));
+        // Once anna's message left it too, she is forgotten
+        assert!(limit.allow("+33612345678", 185));
+        assert_eq!(limit.seen.lock().unwrap().len(), 1);
+        assert!(RateLimit::parse("20").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+//! Spam scoring. The heuristic scorer is cheap and good enough against the usual promotions,
+//! a scorer backed by e.g. rspamd can replace it through `SpamScorer`.
+
+use common::dto::CommonMessage;
+
+pub trait SpamScorer: Send + Sync {
+    /// Higher is spammier, messages from `FILTER_SPAM_THRESHOLD` on are filtered
+    fn score(&self, message: &CommonMessage) -> f32;
+}
+
+pub const DEFAULT_THRESHOLD: f32 = 5.0;
+
+/// Lowercased phrases of promotions and scams
+const SPAM_PHRASES: [&str; 12] = [
+    "viagra",
+    "casino",
+    "crypto",
+    "bitcoin",
+    "lottery",
+    "you have won",
+    "click here",
+    "limited time offer",
+    "100% free",
+    "work from home",
+    "seo services",
+    "unsubscribe",
+];
+
+#[derive(Default)]
+pub struct HeuristicScorer;
+
+impl SpamScorer for HeuristicScorer {
+    fn score(&self, message: &CommonMessage) -> f32 {
+        let mut score = 0.0;
+        // Verdict of the mail server, when it ran a filter
+        if message
+            .headers
+            .get("x-spam-flag")
+            .is_some_and(|flag| flag.trim().eq_ignore_ascii_case("yes"))
+        {
+            score += 5.0;
+        }
+        if let Some(server_score) = message
+            .headers
+            .get("x-spam-score")
+            .and_then(|value| value.trim().parse::<f32>().ok())
+        {
+            score += server_score.max(0.0);
+        }
+
+        let text = match &message.subject {
+            Some(subject) => format!("{}\n{}", subject, message.body),
+            None => message.body.clone(),
+        };
+        let lowercase = text.to_lowercase();
+        score += SPAM_PHRASES
+            .iter()
+            .filter(|phrase| lowercase.contains(*phrase))
+            .count() as f32
+            * 1.5;
+
+        let links = lowercase.matches("http://").count() + lowercase.matches("https://").count();
+        if links > 3 {
+            score += (links - 3) as f32 * 0.5;
+        }
+
+        let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+        if letters.len() >= 20 {
+            let uppercase = letters.iter().filter(|c| c.is_uppercase()).count();
+            if uppercase * 10 > letters.len() * 6 {
+                score += 2.0;
+            }
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(body: &str, headers: &[(&str, &str)]) -> CommonMessage {
+        CommonMessage {
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..CommonMessage::test("promo@spam.example", body)
+        }
+    }
+
+    #[test]
+    fn test_heuristic_score() {
+        let scorer = HeuristicScorer;
+        assert!(
+            scorer.score(&message(
+                "Where is my order 42? It was due yesterday.",
+                &[("x-spam-score", "-1.2")]
+            )) < DEFAULT_THRESHOLD
+        );
+        assert!(
+            scorer.score(&message(
+                "YOU HAVE WON THE CRYPTO LOTTERY!!! CLICK HERE http://a.example http://b.example \
+                 http://c.example http://d.example http://e.example",
+                &[]
+            )) >= DEFAULT_THRESHOLD
+        );
+        assert!(scorer.score(&message("Hello", &[("x-spam-flag", "YES")])) >= DEFAULT_THRESHOLD);
+    }
+}
@@ -0,0 +1,354 @@
+use anyhow::{Context, bail};
+use clap::{Parser, Subcommand};
+use common::{
+    LABELED_TICKETS_QUEUE, OUTBOUND_MSG_QUEUE, SUGGESTED_REPLIES_QUEUE,
+    dto::{CommonMessage, LabeledTicket, OutboundMessage, SuggestedReply, SuggestionStatus},
+    kb::{self, KbSnippet, Retriever},
+    llm,
+    queue::{Message, QueueManager, kafka::KafkaQueueManager},
+    store::{
+        Suggestions, TicketRepository, postgres::PostgresTicketRepository, postgres_url_from_env,
+    },
+    ticket::Ticket,
+};
+use openrouter_rs::OpenRouterClient;
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Send a suggested reply as drafted
+    Approve { suggestion_id: String },
+    /// Send a suggested reply with another body
+    Edit { suggestion_id: String, body: String },
+    /// Drop a suggested reply, nothing is sent
+    Reject { suggestion_id: String },
+    /// Send a reply written from scratch to the customer of a ticket
+    Send { ticket_id: String, body: String },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    if let Some(command) = args.command {
+        let repository = PostgresTicketRepository::connect(&postgres_url_from_env()).await?;
+        return match command {
+            Command::Approve { suggestion_id } => {
+                review(
+                    &repository,
+                    &suggestion_id,
+                    SuggestionStatus::Approved,
+                    None,
+                )
+                .await
+            }
+            Command::Edit {
+                suggestion_id,
+                body,
+            } => {
+                review(
+                    &repository,
+                    &suggestion_id,
+                    SuggestionStatus::Edited,
+                    Some(body),
+                )
+                .await
+            }
+            Command::Reject { suggestion_id } => {
+                review(
+                    &repository,
+                    &suggestion_id,
+                    SuggestionStatus::Rejected,
+                    None,
+                )
+                .await
+            }
+            Command::Send { ticket_id, body } => {
+                let reply = reply_on(&repository, &ticket_id, body).await?;
+                queue_reply(&reply).await
+            }
+        };
+    }
+
+    println!("Starting reply suggestion service...");
+    // Own consumer group: ticket-storage reads the same topic
+    let queue_mgr = KafkaQueueManager::with_group_id("reply-suggest-trt")
+        .await
+        .context("Failed to connect to Kafka")?;
+    queue_mgr.create(LABELED_TICKETS_QUEUE).await?;
+    queue_mgr.create(SUGGESTED_REPLIES_QUEUE).await?;
+
+    let retriever = Retriever::from_env().await?;
+    println!(
+        "Loaded {} knowledge-base chunks",
+        retriever.index.chunks.len()
+    );
+
+    let client = llm::client_from_env()?;
+    let model = llm::model_from_env();
+    // Earlier messages and replies of the conversation, as stored by ticket-storage
+    let repository = PostgresTicketRepository::connect(&postgres_url_from_env()).await?;
+
+    println!(
+        "Listening for labeled tickets on '{}'...",
+        LABELED_TICKETS_QUEUE
+    );
+    queue_mgr
+        .register_read(LABELED_TICKETS_QUEUE, &async |msg: Message<
+            LabeledTicket,
+        >| {
+            let stored = repository.resolve(&msg.message.id).await?;
+            let history = conversation(stored.as_ref(), &msg.message.original_message);
+            let suggestion =
+                draft_reply(&client, &model, &retriever, &msg.message, &history).await?;
+            // Kept for the agents to review, the topic only tells about it
+            repository.save_suggestion(&suggestion).await?;
+            let sent_id = queue_mgr.send(SUGGESTED_REPLIES_QUEUE, &suggestion).await?;
+            println!(
+                "Suggested reply {} for ticket {} sent (id={})",
+                suggestion.id, suggestion.ticket_id, sent_id
+            );
+            Ok(())
+        })
+        .await
+}
+
+/// Settle a pending suggestion. Approved or edited, it is sent to the customer.
+async fn review(
+    repository: &PostgresTicketRepository,
+    suggestion_id: &str,
+    status: SuggestionStatus,
+    edited: Option<String>,
+) -> anyhow::Result<()> {
+    let suggestion = repository
+        .suggestion(suggestion_id)
+        .await?
+        .with_context(|| format!("Unknown suggestion {}", suggestion_id))?;
+    let body = edited.unwrap_or_else(|| suggestion.body.clone());
+    let reply = match status {
+        SuggestionStatus::Rejected => None,
+        _ => Some(OutboundMessage {
+            suggestion_id: Some(suggestion.id.clone()),
+            ..reply_on(repository, &suggestion.ticket_id, body.clone()).await?
+        }),
+    };
+    // Claimed before sending, so that a suggestion reviewed twice is not sent twice
+    if !repository
+        .review_suggestion(&suggestion.id, status.clone(), &body)
+        .await?
+    {
+        bail!("Suggestion {} was reviewed already", suggestion.id);
+    }
+    match reply {
+        Some(reply) => queue_reply(&reply).await.with_context(|| {
+            format!(
+                "Suggestion {} is {:?} but its reply was not queued, send it with `send`",
+                suggestion.id, status
+            )
+        }),
+        None => {
+            println!("Suggestion {} rejected", suggestion.id);
+            Ok(())
+        }
+    }
+}
+
+/// Reply to the customer of a ticket, on the channel of their last message
+async fn reply_on(
+    repository: &PostgresTicketRepository,
+    ticket_id: &str,
+    body: String,
+) -> anyhow::Result<OutboundMessage> {
+    let ticket = repository
+        .resolve(ticket_id)
+        .await?
+        .with_context(|| format!("Unknown ticket {}", ticket_id))?;
+    let last_message = ticket
+        .messages
+        .last()
+        .with_context(|| format!("Ticket {} has no message", ticket.id))?;
+    if !last_message.origin.has_sender() {
+        bail!(
+            "Replies cannot be sent on {:?} yet, answer ticket {} from the channel itself",
+            last_message.origin,
+            ticket.id
+        );
+    }
+    Ok(OutboundMessage::reply(&ticket.id, last_message, body))
+}
+
+/// Queue the reply for the sender of the channel
+async fn queue_reply(reply: &OutboundMessage) -> anyhow::Result<()> {
+    let queue_mgr = KafkaQueueManager::new().await?;
+    queue_mgr.create(OUTBOUND_MSG_QUEUE).await?;
+    let sent_id = queue_mgr.send(OUTBOUND_MSG_QUEUE, reply).await?;
+    println!(
+        "Reply {} to {} on ticket {} queued (id={})",
+        reply.id, reply.contact, reply.ticket_id, sent_id
+    );
+    Ok(())
+}
+
+/// Earlier messages of the customer and replies sent to them, oldest first. The message being
+/// answered is left out, ticket-storage may have stored it already.
+fn conversation(stored: Option<&Ticket>, current: &CommonMessage) -> Vec<String> {
+    let Some(ticket) = stored else {
+        return vec![];
+    };
+    let mut entries: Vec<(u64, String)> = ticket
+        .messages
+        .iter()
+        .filter(|message| !is_same(message, current))
+        .map(|message| (message.timestamp, format!("Customer: {}", message.body)))
+        .collect();
+    entries.extend(
+        ticket
+            .replies
+            .iter()
+            // Only known from its delivery statuses so far
+            .filter(|reply| !reply.body.is_empty())
+            .map(|reply| (reply.created_at, format!("Support: {}", reply.body))),
+    );
+    entries.retain(|(at, _)| *at <= current.timestamp);
+    entries.sort_by_key(|(at, _)| *at);
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+fn is_same(message: &CommonMessage, other: &CommonMessage) -> bool {
+    match (&message.message_id, &other.message_id) {
+        (Some(id), Some(other_id)) => id == other_id,
+        _ => message.timestamp == other.timestamp && message.body == other.body,
+    }
+}
+
+fn build_prompt(ticket: &LabeledTicket, history: &[String], snippets: &[KbSnippet]) -> String {
+    let knowledge = snippets
+        .iter()
+        .map(|s| format!("--- {} ---\n{}", s.article, s.text))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "You are a customer support agent. Draft a reply to the customer below, in the language they wrote in.\n\
+         Rely on the knowledge-base excerpts when they are relevant, never invent procedures.\n\
+         Output ONLY the reply body, without any additional text.\n\n\
+         Knowledge base:\n{}\n\n\
+         Ticket: {}\nSummary: {}\nTags: {}\n\
+         Conversation so far:\n{}\n\n\
+         Customer message to answer ({}):\n{}",
+        knowledge,
+        ticket.title,
+        ticket.description,
+        ticket.tags.join(", "),
+        if history.is_empty() {
+            "(none)".to_string()
+        } else {
+            history.join("\n")
+        },
+        ticket.original_message.contact,
+        ticket.original_message.body,
+    )
+}
+
+async fn draft_reply(
+    client: &OpenRouterClient,
+    model: &str,
+    retriever: &Retriever,
+    ticket: &LabeledTicket,
+    history: &[String],
+) -> anyhow::Result<SuggestedReply> {
+    let query = format!(
+        "{} {} {}",
+        ticket.title,
+        ticket.tags.join(" "),
+        ticket.original_message.body
+    );
+    let snippets = retriever.retrieve(&query).await?;
+
+    let completion = llm::complete(
+        client,
+        model,
+        build_prompt(ticket, history, &snippets),
+        None,
+    )
+    .await?;
+
+    Ok(SuggestedReply {
+        id: uuid::Uuid::new_v4().to_string(),
+        ticket_id: ticket.id.clone(),
+        contact: ticket.original_message.contact.clone(),
+        origin: ticket.original_message.origin,
+        body: completion.content.trim().to_string(),
+        sources: kb::cited_articles(&snippets),
+        status: SuggestionStatus::Pending,
+        created_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+        llm_usage: Some(completion.usage),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_prompt_includes_thread_and_snippets() {
+        let message = CommonMessage::test(
+            "anna@shop.example",
+            "I was charged twice on my last invoice.",
+        );
+        let ticket = LabeledTicket {
+            title: "Double charge".to_string(),
+            tags: vec!["billing".to_string(), "refund".to_string()],
+            description: "Customer charged twice".to_string(),
+            ..LabeledTicket::test("12", message)
+        };
+        let snippets = vec![KbSnippet {
+            article: "refunds.md".to_string(),
+            text: "Refunds are issued within 10 days.".to_string(),
+            score: 0.7,
+        }];
+
+        // Stored with an earlier message and a reply, and already the one being answered
+        let mut stored = Ticket::from(LabeledTicket::test(
+            "12",
+            CommonMessage {
+                timestamp: 50,
+                ..CommonMessage::test("anna@shop.example", "Where is my invoice?")
+            },
+        ));
+        let mut reply = OutboundMessage::reply(
+            "12",
+            &stored.messages[0],
+            "It is attached to your order.".to_string(),
+        );
+        reply.created_at = 60;
+        stored.add_reply(&reply);
+        stored.add_message(ticket.original_message.clone());
+        let history = conversation(Some(&stored), &ticket.original_message);
+        assert_eq!(
+            history,
+            vec![
+                "Customer: Where is my invoice?",
+                "Support: It is attached to your order.",
+            ]
+        );
+
+        let prompt = build_prompt(&ticket, &history, &snippets);
+        assert!(prompt.contains("--- refunds.md ---\nRefunds are issued within 10 days."));
+        assert!(
+            prompt.contains(
+                "Customer: Where is my invoice?\nSupport: It is attached to your order.\n"
+            )
+        );
+        assert!(prompt.contains("I was charged twice on my last invoice."));
+        assert!(prompt.contains("Tags: billing, refund"));
+        assert!(build_prompt(&ticket, &[], &snippets).contains("Conversation so far:\n(none)"));
+    }
+}
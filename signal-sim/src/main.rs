@@ -0,0 +1,116 @@
+//! Stand-in for the signal-cli REST API `receive` and `attachments` endpoints, handing out fake
+//! customer messages to signal-trt without a registered Signal number
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{Json, Router, extract::Path, extract::State, routing::get};
+use clap::Parser;
+use serde_json::{Value, json};
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    /// Number of messages to hand out, one per poll, instead of one per poll forever
+    #[arg(long)]
+    count: Option<u32>,
+}
+
+struct Sim {
+    count: Option<u32>,
+    sent: AtomicU32,
+}
+
+/// Every third customer sends a photo along, and every other envelope comes with the delivery
+/// receipt of a reply, which signal-trt has to skip
+fn fake_envelopes(i: u32, account: &str) -> Vec<Value> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Failed to get current timestamp")
+        .as_millis() as u64;
+    let number = format!("+336{:02}654321", i % 100);
+    let attachments: Vec<Value> = if i.is_multiple_of(3) {
+        vec![json!({
+            "contentType": "image/jpeg",
+            "filename": format!("photo-{}.jpg", i),
+            "id": format!("sim-attachment-{}.jpg", i),
+            "size": 48213,
+        })]
+    } else {
+        vec![]
+    };
+    let mut envelopes = vec![json!({
+        "envelope": {
+            "source": number,
+            "sourceNumber": number,
+            "sourceName": format!("Customer {}", i),
+            "sourceDevice": 1,
+            "timestamp": now,
+            "dataMessage": {
+                "timestamp": now,
+                "message": format!("Hello, this is Signal message #{} - I need help!", i),
+                "expiresInSeconds": 0,
+                "viewOnce": false,
+                "attachments": attachments,
+            },
+        },
+        "account": account,
+    })];
+    if i.is_multiple_of(2) {
+        envelopes.push(json!({
+            "envelope": {
+                "source": number,
+                "sourceNumber": number,
+                "sourceDevice": 1,
+                "timestamp": now,
+                "receiptMessage": {
+                    "when": now,
+                    "isDelivery": true,
+                    "isRead": false,
+                    "timestamps": [now - 60_000],
+                },
+            },
+            "account": account,
+        }));
+    }
+    envelopes
+}
+
+async fn receive(State(sim): State<Arc<Sim>>, Path(number): Path<String>) -> Json<Vec<Value>> {
+    let i = sim.sent.fetch_add(1, Ordering::SeqCst) + 1;
+    if sim.count.is_some_and(|count| i > count) {
+        return Json(vec![]);
+    }
+    println!("Handing out message {} to {}", i, number);
+    Json(fake_envelopes(i, &number))
+}
+
+/// Content of the photos handed out, whatever their id
+async fn attachment(Path(id): Path<String>) -> Vec<u8> {
+    format!("fake JPEG photo {}", id).into_bytes()
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let sim = Arc::new(Sim {
+        count: args.count,
+        sent: AtomicU32::new(0),
+    });
+    let app = Router::new()
+        .route("/v1/receive/{number}", get(receive))
+        .route("/v1/attachments/{id}", get(attachment))
+        .with_state(sim);
+
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", args.port)).await?;
+    println!(
+        "Fake signal-cli REST API listening on {}, point SIGNAL_CLI_URL at it",
+        listener.local_addr()?
+    );
+    axum::serve(listener, app).await?;
+    Ok(())
+}
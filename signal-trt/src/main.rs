@@ -0,0 +1,135 @@
+mod rest;
+
+use std::collections::VecDeque;
+use std::env;
+use std::time::Duration;
+
+use anyhow::Context;
+use common::{
+    SIGNAL_MSG_QUEUE,
+    channel::{self, ChannelAdapter},
+    dto::{CommonMessage, SignalMessage},
+    queue::{QueueManager, kafka::KafkaQueueManager},
+    storage::{ObjectStore, archive_raw, store_channel_attachments},
+};
+use rest::SignalCliClient;
+
+const DEFAULT_SIGNAL_CLI_URL: &str = "http://localhost:8080";
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 5;
+
+/// Move the messages received by the signal-cli REST API to the Signal topic. signal-cli forgets
+/// the messages it returned: those not published yet are kept until Kafka takes them, and no more
+/// are received meanwhile, signal-cli keeps them.
+async fn poll(
+    client: SignalCliClient,
+    queue_mgr: KafkaQueueManager,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let mut pending = VecDeque::new();
+    loop {
+        if pending.is_empty() {
+            match client.receive().await {
+                Ok(received) => pending.extend(
+                    received
+                        .into_iter()
+                        .filter_map(|item| item.envelope.to_signal_message()),
+                ),
+                // signal-cli may be restarting, try again at the next interval
+                Err(e) => println!("Failed to receive Signal messages: {:?}", e),
+            }
+        }
+        while let Some(message) = pending.front() {
+            match queue_mgr.send(SIGNAL_MSG_QUEUE, message).await {
+                Ok(msg_id) => println!(
+                    "Message from {} published (id={}, {} attachments)",
+                    message.source,
+                    msg_id,
+                    message.attachments.len()
+                ),
+                Err(e) => {
+                    eprintln!(
+                        "Failed to publish Signal message, {} kept for the next interval: {:#}",
+                        pending.len(),
+                        e
+                    );
+                    break;
+                }
+            }
+            pending.pop_front();
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+struct Signal {
+    /// `None` when not polling signal-cli, attachments then stay described only
+    cli: Option<SignalCliClient>,
+}
+
+impl ChannelAdapter for Signal {
+    type Input = SignalMessage;
+    type Parts = ();
+    const NAME: &'static str = "Signal";
+    const SOURCE_QUEUE: &'static str = SIGNAL_MSG_QUEUE;
+
+    async fn normalize(&self, input: SignalMessage) -> anyhow::Result<Option<(CommonMessage, ())>> {
+        Ok(Some((input.try_into()?, ())))
+    }
+
+    /// Signal has no conversation id, the sender is the thread
+    fn thread_key(&self, _input: &SignalMessage, message: &CommonMessage) -> Option<String> {
+        Some(message.contact.clone())
+    }
+
+    /// The attachments are downloaded from signal-cli along
+    async fn archive(
+        &self,
+        store: &impl ObjectStore,
+        input: &SignalMessage,
+        message: &mut CommonMessage,
+        _parts: (),
+    ) -> anyhow::Result<()> {
+        if let Some(cli) = &self.cli {
+            store_channel_attachments(store, message, &async |id: &str| cli.attachment(id).await)
+                .await?;
+        }
+        archive_raw(store, input, message).await?;
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let url = env::var("SIGNAL_CLI_URL").unwrap_or_else(|_| DEFAULT_SIGNAL_CLI_URL.to_string());
+    let number = env::var("SIGNAL_NUMBER").ok();
+    // Receiving is optional, messages can also be published on the topic by signal-sim
+    let polling = async {
+        let Some(number) = &number else {
+            println!("SIGNAL_NUMBER is not set, not polling signal-cli");
+            return Ok(());
+        };
+        let interval = env::var("SIGNAL_POLL_INTERVAL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+        println!("Polling {} for {} every {} seconds", url, number, interval);
+        let queue_mgr = KafkaQueueManager::new()
+            .await
+            .context("Failed to connect to Kafka")?;
+        queue_mgr.create(SIGNAL_MSG_QUEUE).await?;
+        poll(
+            SignalCliClient::new(&url, number),
+            queue_mgr,
+            Duration::from_secs(interval),
+        )
+        .await
+    };
+    let signal = Signal {
+        cli: number
+            .as_ref()
+            .map(|number| SignalCliClient::new(&url, number)),
+    };
+
+    tokio::try_join!(polling, channel::run(signal))?;
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! Client of the signal-cli REST API (bbernhard/signal-cli-rest-api) `receive` endpoint, which
+//! hands out the envelopes received by the registered number since the previous call
+
+use anyhow::{Context, Result, bail};
+use common::dto::{Attachment, SignalMessage};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Received {
+    pub envelope: Envelope,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub source: Option<String>,
+    pub source_number: Option<String>,
+    pub source_name: Option<String>,
+    /// Absent from receipts, typing indicators and sync messages
+    pub data_message: Option<DataMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DataMessage {
+    pub timestamp: u64,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub attachments: Vec<RestAttachment>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RestAttachment {
+    pub content_type: String,
+    pub filename: Option<String>,
+    /// Served by `/v1/attachments/{id}`
+    pub id: String,
+    pub size: Option<u64>,
+}
+
+impl Envelope {
+    /// `None` for anything but a message from a customer with a text or an attachment
+    pub fn to_signal_message(&self) -> Option<SignalMessage> {
+        let data = self.data_message.as_ref()?;
+        let text = data.message.clone().unwrap_or_default();
+        if text.trim().is_empty() && data.attachments.is_empty() {
+            return None;
+        }
+        Some(SignalMessage {
+            // Senders hiding their number are only known by their UUID
+            source: self.source_number.clone().or_else(|| self.source.clone())?,
+            source_name: self.source_name.clone(),
+            message: text,
+            timestamp: data.timestamp,
+            attachments: data
+                .attachments
+                .iter()
+                .map(|attachment| Attachment {
+                    filename: attachment
+                        .filename
+                        .clone()
+                        .unwrap_or_else(|| attachment.id.clone()),
+                    content_type: attachment.content_type.clone(),
+                    size: attachment.size.unwrap_or_default(),
+                    // Not given until the content is downloaded
+                    checksum: String::new(),
+                    storage_key: None,
+                    source_id: Some(attachment.id.clone()),
+                })
+                .collect(),
+        })
+    }
+}
+
+pub struct SignalCliClient {
+    http: reqwest::Client,
+    base_url: String,
+    number: String,
+}
+
+impl SignalCliClient {
+    pub fn new(base_url: &str, number: &str) -> Self {
+        SignalCliClient {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            number: number.to_string(),
+        }
+    }
+
+    /// Envelopes received since the previous call, signal-cli forgets them once returned
+    pub async fn receive(&self) -> Result<Vec<Received>> {
+        let url = format!("{}/v1/receive/{}", self.base_url, self.number);
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", url))?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!(
+                "signal-cli answered {}: {}",
+                status,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        response
+            .json()
+            .await
+            .context("Invalid answer from signal-cli")
+    }
+
+    /// Content of a received attachment, signal-cli keeps it until deleted
+    pub async fn attachment(&self, id: &str) -> Result<Vec<u8>> {
+        let url = format!("{}/v1/attachments/{}", self.base_url, id);
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", url))?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!(
+                "signal-cli answered {} for attachment {}: {}",
+                status,
+                id,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelopes() -> Result<()> {
+        let received: Vec<Received> = serde_json::from_str(
+            r#"[
+                {"envelope": {"source": "+33612345678", "sourceNumber": "+33612345678",
+                    "sourceUuid": "3f1c7a2e-5b1d-4d4e-9a55-0c1f2b3d4e5f", "sourceName": "Anna",
+                    "sourceDevice": 1, "timestamp": 1760781600123,
+                    "dataMessage": {"timestamp": 1760781600123, "message": "Mon colis ?",
+                        "expiresInSeconds": 0, "viewOnce": false,
+                        "attachments": [{"contentType": "image/jpeg", "filename": null,
+                            "id": "hBvz3-Sx9QwAT2bY.jpg", "size": 48213}]}},
+                 "account": "+33100000000"},
+                {"envelope": {"source": "+33612345678", "sourceNumber": "+33612345678",
+                    "timestamp": 1760781601000,
+                    "receiptMessage": {"when": 1760781601000, "isDelivery": true,
+                        "isRead": false, "timestamps": [1760781590000]}},
+                 "account": "+33100000000"}
+            ]"#,
+        )?;
+        assert_eq!(received.len(), 2);
+
+        let message = received[0].envelope.to_signal_message().unwrap();
+        assert_eq!(message.source, "+33612345678");
+        assert_eq!(message.source_name.as_deref(), Some("Anna"));
+        assert_eq!(message.message, "Mon colis ?");
+        assert_eq!(message.timestamp, 1760781600123);
+        assert_eq!(message.attachments[0].filename, "hBvz3-Sx9QwAT2bY.jpg");
+        assert_eq!(message.attachments[0].size, 48213);
+        assert_eq!(
+            message.attachments[0].source_id.as_deref(),
+            Some("hBvz3-Sx9QwAT2bY.jpg")
+        );
+
+        // A receipt of a reply
+        assert!(received[1].envelope.to_signal_message().is_none());
+        Ok(())
+    }
+}
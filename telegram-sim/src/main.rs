@@ -0,0 +1,95 @@
+use clap::Parser;
+use common::{
+    TELEGRAM_MSG_QUEUE,
+    dto::{Attachment, TelegramMessage},
+    queue::{QueueManager, kafka::KafkaQueueManager},
+    storage::checksum,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    /// Number of messages to send
+    #[arg(long)]
+    count: Option<u32>,
+
+    /// Run in loop, sending one message every second
+    #[arg(long)]
+    loop_send: bool,
+}
+
+/// Every third customer sends a photo along; content is not uploaded, only described
+fn fake_attachments(i: u32) -> Vec<Attachment> {
+    if !i.is_multiple_of(3) {
+        return vec![];
+    }
+    let content = format!("fake JPEG photo #{}", i).into_bytes();
+    vec![Attachment {
+        filename: format!("photo-{}.jpg", i),
+        content_type: "image/jpeg".to_string(),
+        size: content.len() as u64,
+        checksum: checksum(&content),
+        storage_key: None,
+        source_id: Some(format!("AgACAgQAAxkBAAI{}", i)),
+    }]
+}
+
+fn fake_message(i: u32) -> TelegramMessage {
+    TelegramMessage {
+        update_id: 900_000_000 + i as i64,
+        message_id: i as i64,
+        chat_id: 5_000_000_000 + i as i64,
+        username: Some(format!("customer{}", i)),
+        first_name: Some(format!("Customer {}", i)),
+        text: format!("Hello, this is Telegram message #{} - I need help!", i),
+        date: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to get current timestamp")
+            .as_secs(),
+        attachments: fake_attachments(i),
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    println!("Connecting to Kafka for sending Telegram messages...");
+    let queue_mgr = KafkaQueueManager::new()
+        .await
+        .expect("Failed to connect to Kafka - ensure Kafka is running on localhost:9092");
+
+    // Create topic if it doesn't exist
+    queue_mgr
+        .create(TELEGRAM_MSG_QUEUE)
+        .await
+        .unwrap_or_else(|_| panic!("Failed to create topic '{}'", TELEGRAM_MSG_QUEUE));
+    println!("Topic '{}' ready", TELEGRAM_MSG_QUEUE);
+
+    let count = match (args.loop_send, args.count) {
+        (true, _) => u32::MAX,
+        (false, Some(count)) => count,
+        (false, None) => {
+            println!("Error: Please specify --count or --loop-send");
+            return Ok(());
+        }
+    };
+    for i in 1..=count {
+        let msg = fake_message(i);
+        let msg_id = queue_mgr
+            .send(TELEGRAM_MSG_QUEUE, &msg)
+            .await
+            .unwrap_or_else(|_| panic!("Failed to send Telegram message {} to Kafka", i));
+        println!(
+            "Sent message {} (id={}): chat={}, text={}",
+            i, msg_id, msg.chat_id, msg.text
+        );
+        if args.loop_send {
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    println!("All {} Telegram messages sent successfully!", count);
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use common::{
+    TELEGRAM_MSG_QUEUE,
+    channel::{self, ChannelAdapter},
+    dto::{CommonMessage, TelegramMessage},
+};
+
+struct Telegram;
+
+impl ChannelAdapter for Telegram {
+    type Input = TelegramMessage;
+    type Parts = ();
+    const NAME: &'static str = "Telegram";
+    const SOURCE_QUEUE: &'static str = TELEGRAM_MSG_QUEUE;
+
+    async fn normalize(
+        &self,
+        input: TelegramMessage,
+    ) -> anyhow::Result<Option<(CommonMessage, ())>> {
+        Ok(Some((input.try_into()?, ())))
+    }
+
+    /// The private chat with the bot
+    fn thread_key(&self, input: &TelegramMessage, _message: &CommonMessage) -> Option<String> {
+        Some(input.chat_id.to_string())
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    channel::run(Telegram).await
+}
@@ -0,0 +1,145 @@
+mod reload;
+mod rules;
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use common::{
+    LABELED_TICKETS_QUEUE, TICKET_ALERTS_QUEUE, UNROUTED_TICKETS_QUEUE,
+    dto::LabeledTicket,
+    queue::{Message, QueueManager, kafka::KafkaQueueManager},
+};
+use reload::HotRules;
+use rules::RuleSet;
+
+const DEFAULT_RULES_PATH: &str = "./routing.toml";
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    /// Rules file, `ROUTING_RULES_PATH` by default
+    #[arg(long, global = true)]
+    rules: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Route sample tickets without sending anything: labeled tickets as a JSON array or as JSON
+    /// lines, e.g. from the ticket-storage archive
+    DryRun { tickets: PathBuf },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let rules_path = args.rules.unwrap_or_else(|| {
+        PathBuf::from(
+            std::env::var("ROUTING_RULES_PATH").unwrap_or_else(|_| DEFAULT_RULES_PATH.to_string()),
+        )
+    });
+    match args.command {
+        Some(Command::DryRun { tickets }) => dry_run(&rules_path, &tickets),
+        None => serve(rules_path).await,
+    }
+}
+
+async fn serve(rules_path: PathBuf) -> Result<()> {
+    println!("Starting ticket router...");
+    let rules = HotRules::load(rules_path.clone())?;
+    println!(
+        "Loaded {} routing rule(s) from {}",
+        rules.current().rules.len(),
+        rules_path.display()
+    );
+    let reload_interval = std::env::var("ROUTING_RELOAD_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(5);
+
+    let queue_mgr = KafkaQueueManager::with_group_id("ticket-router")
+        .await
+        .context("Failed to connect to Kafka")?;
+    queue_mgr.create(UNROUTED_TICKETS_QUEUE).await?;
+    queue_mgr.create(LABELED_TICKETS_QUEUE).await?;
+    queue_mgr.create(TICKET_ALERTS_QUEUE).await?;
+
+    println!(
+        "Listening for labeled tickets on '{}'...",
+        UNROUTED_TICKETS_QUEUE
+    );
+    let route = async |msg: Message<LabeledTicket>| {
+        let mut ticket = msg.message;
+        let alerts = rules.current().route(&mut ticket, now());
+        if let Some(routing) = &ticket.routing {
+            println!(
+                "Ticket {} routed to {} with {:?} priority by {:?}",
+                ticket.id,
+                routing.team.as_deref().unwrap_or("no team"),
+                routing.priority.unwrap_or_default(),
+                routing.rules
+            );
+        }
+        queue_mgr.send(LABELED_TICKETS_QUEUE, &ticket).await?;
+        // The ticket is on its way: a failed alert must not route it a second time
+        for alert in &alerts {
+            if let Err(e) = queue_mgr.send(TICKET_ALERTS_QUEUE, alert).await {
+                eprintln!("Failed to send alert for ticket {}: {:#}", ticket.id, e);
+            }
+        }
+        Ok(())
+    };
+    tokio::select! {
+        result = queue_mgr.register_read(UNROUTED_TICKETS_QUEUE, &route) => result,
+        _ = rules.watch(Duration::from_secs(reload_interval)) => Ok(()),
+    }
+}
+
+fn dry_run(rules_path: &Path, tickets_path: &Path) -> Result<()> {
+    let rules = RuleSet::load(rules_path)?;
+    let text = std::fs::read_to_string(tickets_path)
+        .with_context(|| format!("Failed to read {}", tickets_path.display()))?;
+    let tickets: Vec<LabeledTicket> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(&text)?
+    } else {
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?
+    };
+
+    for mut ticket in tickets {
+        let alerts = rules.route(&mut ticket, now());
+        let routing = ticket.routing.unwrap_or_default();
+        println!("Ticket {} ({})", ticket.id, ticket.title);
+        println!("  rules:    {}", display_list(&routing.rules));
+        println!("  team:     {}", routing.team.as_deref().unwrap_or("-"));
+        println!("  priority: {:?}", routing.priority.unwrap_or_default());
+        println!("  tags:     {}", display_list(&ticket.tags));
+        if let Some(reason) = &routing.close {
+            println!("  closed:   {}", reason);
+        }
+        for alert in alerts {
+            println!("  alert:    {} ({})", alert.message, alert.rule);
+        }
+    }
+    Ok(())
+}
+
+fn display_list(items: &[String]) -> String {
+    if items.is_empty() {
+        "-".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
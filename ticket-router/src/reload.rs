@@ -0,0 +1,110 @@
+//! Rules reloaded when their file changes, so that they can be edited without restarting the
+//! router. A file that does not parse is reported and the previous rules are kept.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+use crate::rules::RuleSet;
+
+pub struct HotRules {
+    path: PathBuf,
+    current: RwLock<Arc<RuleSet>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl HotRules {
+    /// Fails when the rules cannot be loaded at startup
+    pub fn load(path: PathBuf) -> Result<HotRules> {
+        let modified = modified_at(&path);
+        let rules = RuleSet::load(&path)?;
+        Ok(HotRules {
+            path,
+            current: RwLock::new(Arc::new(rules)),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    pub fn current(&self) -> Arc<RuleSet> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Reload the rules if the file was modified since they were loaded. Returns whether they
+    /// were replaced.
+    pub fn reload_if_changed(&self) -> Result<bool> {
+        let modified = modified_at(&self.path);
+        {
+            let mut last = self.modified.lock().unwrap();
+            if *last == modified {
+                return Ok(false);
+            }
+            // Not tried again until the next change
+            *last = modified;
+        }
+        let rules = RuleSet::load(&self.path)?;
+        *self.current.write().unwrap() = Arc::new(rules);
+        Ok(true)
+    }
+
+    /// Check the file every `interval`, forever
+    pub async fn watch(&self, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            match self.reload_if_changed() {
+                Ok(true) => println!(
+                    "Reloaded {} routing rule(s) from {}",
+                    self.current().rules.len(),
+                    self.path.display()
+                ),
+                Ok(false) => {}
+                Err(e) => eprintln!("Keeping the previous routing rules: {:#}", e),
+            }
+        }
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_if_changed() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("routing-{}.toml", std::process::id()));
+        let rule = |name: &str| {
+            format!(
+                "[[rules]]\nname = \"{}\"\nthen = {{ team = \"t\" }}\n",
+                name
+            )
+        };
+        std::fs::write(&path, rule("First"))?;
+        let rules = HotRules::load(path.clone())?;
+        assert!(!rules.reload_if_changed()?);
+
+        // Modification times can be coarse
+        let later = SystemTime::now() + Duration::from_secs(10);
+        std::fs::write(&path, rule("First") + &rule("Second"))?;
+        std::fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(later)?;
+        assert!(rules.reload_if_changed()?);
+        assert_eq!(rules.current().rules.len(), 2);
+
+        std::fs::write(&path, "[[rules]]\nname = \"Broken\"")?;
+        std::fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(later + Duration::from_secs(10))?;
+        assert!(rules.reload_if_changed().is_err());
+        assert_eq!(rules.current().rules.len(), 2);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
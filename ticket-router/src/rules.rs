@@ -0,0 +1,281 @@
+//! Routing rules, read from a TOML file:
+//!
+//! ```toml
+//! [[rules]]
+//! name = "Chargebacks"
+//! when = { tags = ["billing"], keywords = ["chargeback", "dispute"] }
+//! then = { team = "finance", priority = "Urgent", alert = "Chargeback to answer today" }
+//! stop = true
+//! ```
+//!
+//! Rules are applied in order. Every condition of `when` has to match, and one value of each list
+//! is enough; a rule without conditions matches every ticket. Later rules override the team and
+//! priority set by earlier ones, `stop` ends the evaluation.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use common::{
+    dto::{LabeledTicket, Origin, TicketAlert},
+    store::contact::{Identity, IdentityKind},
+    ticket::{Priority, Routing},
+};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSet {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    #[serde(default)]
+    pub when: Conditions,
+    pub then: Actions,
+    /// Skip the rules after this one when it matches
+    #[serde(default)]
+    pub stop: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Conditions {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub origins: Vec<Origin>,
+    /// Domains of email contacts, subdomains included
+    #[serde(default)]
+    pub contact_domains: Vec<String>,
+    /// Priority set by the rules applied so far, `Normal` when none did
+    #[serde(default)]
+    pub priorities: Vec<Priority>,
+    /// Looked for in the title, description, subject and body, translation included, ignoring case
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Actions {
+    pub team: Option<String>,
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub add_tags: Vec<String>,
+    /// Message of the alert sent to the team
+    pub alert: Option<String>,
+    /// Close the ticket with this reason
+    pub close: Option<String>,
+}
+
+impl Actions {
+    fn is_empty(&self) -> bool {
+        self.team.is_none()
+            && self.priority.is_none()
+            && self.add_tags.is_empty()
+            && self.alert.is_none()
+            && self.close.is_none()
+    }
+}
+
+impl RuleSet {
+    pub fn parse(text: &str) -> Result<RuleSet> {
+        let rule_set: RuleSet = toml::from_str(text)?;
+        for (i, rule) in rule_set.rules.iter().enumerate() {
+            if rule.name.trim().is_empty() {
+                bail!("Rule #{} has no name", i + 1);
+            }
+            if rule_set.rules[..i]
+                .iter()
+                .any(|other| other.name == rule.name)
+            {
+                bail!("Two rules are named '{}'", rule.name);
+            }
+            if rule.then.is_empty() {
+                bail!("Rule '{}' does nothing", rule.name);
+            }
+        }
+        Ok(rule_set)
+    }
+
+    pub fn load(path: &Path) -> Result<RuleSet> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read rules from {}", path.display()))?;
+        RuleSet::parse(&text).with_context(|| format!("Invalid rules in {}", path.display()))
+    }
+
+    /// Set the routing of the ticket and add the tags of the matching rules. Returns the alerts
+    /// they raised.
+    pub fn route(&self, ticket: &mut LabeledTicket, now: u64) -> Vec<TicketAlert> {
+        let mut routing = Routing::default();
+        let mut alerts = vec![];
+        for rule in &self.rules {
+            if !rule
+                .when
+                .matches(ticket, routing.priority.unwrap_or_default())
+            {
+                continue;
+            }
+            routing.rules.push(rule.name.clone());
+            let actions = &rule.then;
+            if let Some(team) = &actions.team {
+                routing.team = Some(team.clone());
+            }
+            if let Some(priority) = actions.priority {
+                routing.priority = Some(priority);
+            }
+            for tag in &actions.add_tags {
+                if !ticket.tags.contains(tag) {
+                    ticket.tags.push(tag.clone());
+                }
+            }
+            if let Some(reason) = &actions.close {
+                routing.close = Some(reason.clone());
+            }
+            if let Some(message) = &actions.alert {
+                alerts.push(TicketAlert {
+                    ticket_id: ticket.id.clone(),
+                    rule: rule.name.clone(),
+                    message: message.clone(),
+                    title: ticket.title.clone(),
+                    contact: ticket.original_message.contact.clone(),
+                    priority: Priority::Normal,
+                    raised_at: now,
+                });
+            }
+            if rule.stop {
+                break;
+            }
+        }
+        // With the priority the ticket ends up with
+        for alert in &mut alerts {
+            alert.priority = routing.priority.unwrap_or_default();
+        }
+        ticket.routing = Some(routing);
+        alerts
+    }
+}
+
+impl Conditions {
+    fn matches(&self, ticket: &LabeledTicket, priority: Priority) -> bool {
+        let message = &ticket.original_message;
+        let tags_match = self.tags.is_empty()
+            || self
+                .tags
+                .iter()
+                .any(|tag| ticket.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+        let origin_match = self.origins.is_empty() || self.origins.contains(&message.origin);
+        let domain_match = self.contact_domains.is_empty() || {
+            let identity = Identity::parse(&message.contact);
+            identity.kind == IdentityKind::Email
+                && identity.value.rsplit_once('@').is_some_and(|(_, domain)| {
+                    self.contact_domains.iter().any(|expected| {
+                        let expected = expected.trim_start_matches('@').to_lowercase();
+                        domain == expected || domain.ends_with(&format!(".{}", expected))
+                    })
+                })
+        };
+        let priority_match = self.priorities.is_empty() || self.priorities.contains(&priority);
+        let keyword_match = self.keywords.is_empty() || {
+            let text = [
+                Some(ticket.title.as_str()),
+                Some(ticket.description.as_str()),
+                message.subject.as_deref(),
+                Some(message.body.as_str()),
+                message.translated_body.as_deref(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join("\n")
+            .to_lowercase();
+            self.keywords
+                .iter()
+                .any(|keyword| text.contains(&keyword.to_lowercase()))
+        };
+        tags_match && origin_match && domain_match && priority_match && keyword_match
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::dto::CommonMessage;
+
+    const RULES: &str = r#"
+        [[rules]]
+        name = "Big accounts"
+        when = { contact_domains = ["bigcorp.example"] }
+        then = { priority = "High", add_tags = ["vip"] }
+
+        [[rules]]
+        name = "Billing"
+        when = { tags = ["billing", "refund"] }
+        then = { team = "finance" }
+
+        [[rules]]
+        name = "Angry big accounts"
+        when = { priorities = ["High"], keywords = ["lawyer", "chargeback"] }
+        then = { priority = "Urgent", alert = "Escalate today" }
+        stop = true
+
+        [[rules]]
+        name = "Newsletters"
+        when = { origins = ["Email"], keywords = ["unsubscribe"] }
+        then = { close = "Newsletter" }
+    "#;
+
+    fn ticket(contact: &str, tags: &[&str], body: &str) -> LabeledTicket {
+        LabeledTicket {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..LabeledTicket::test("12", CommonMessage::test(contact, body))
+        }
+    }
+
+    #[test]
+    fn test_route() -> Result<()> {
+        let rules = RuleSet::parse(RULES)?;
+
+        let mut vip = ticket(
+            "Paul <paul@eu.bigcorp.example>",
+            &["Billing"],
+            "My lawyer will hear about this. Unsubscribe me.",
+        );
+        let alerts = rules.route(&mut vip, 120);
+        let routing = vip.routing.unwrap();
+        assert_eq!(routing.team.as_deref(), Some("finance"));
+        assert_eq!(routing.priority, Some(Priority::Urgent));
+        // Stopped before the newsletter rule
+        assert_eq!(routing.close, None);
+        assert_eq!(
+            routing.rules,
+            vec!["Big accounts", "Billing", "Angry big accounts"]
+        );
+        assert_eq!(vip.tags, vec!["Billing", "vip"]);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].priority, Priority::Urgent);
+
+        // The keyword alone is not enough
+        let mut other = ticket("anna@shop.example", &[], "My lawyer. Unsubscribe");
+        assert!(rules.route(&mut other, 120).is_empty());
+        let routing = other.routing.unwrap();
+        assert_eq!(routing.priority, None);
+        assert_eq!(routing.close.as_deref(), Some("Newsletter"));
+        assert_eq!(routing.rules, vec!["Newsletters"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(RuleSet::parse("[[rules]]\nname = \"Nothing\"\nthen = {}").is_err());
+        assert!(RuleSet::parse("[[rules]]\nname = \"Typo\"\nthen = { teem = \"a\" }").is_err());
+        assert!(
+            RuleSet::parse("[[rules]]\nname = \"A\"\nthen = { priority = \"Whenever\" }").is_err()
+        );
+        assert!(RuleSet::parse("").unwrap().rules.is_empty());
+    }
+}
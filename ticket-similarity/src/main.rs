@@ -0,0 +1,227 @@
+mod similarity;
+
+use std::sync::Mutex;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use common::{
+    LABELED_TICKETS_QUEUE, MERGE_SUGGESTIONS_QUEUE, TICKET_MERGES_QUEUE,
+    dto::{LabeledTicket, MergeRequest, MergeSuggestion},
+    kb::embed::{Embedder, HashEmbedder},
+    queue::{Message, QueueManager, kafka::KafkaQueueManager},
+    store::{TicketRepository, postgres::PostgresTicketRepository, postgres_url_from_env},
+};
+use similarity::{DEFAULT_THRESHOLD, RECENT_WINDOW_SECS, RecentTicket, RecentTickets};
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Ask ticket-storage to merge a ticket into another, e.g. after accepting a suggestion
+    Merge {
+        /// Ticket to merge, its id will redirect to the target
+        source: String,
+        /// Ticket receiving the messages
+        target: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    if let Some(Command::Merge { source, target }) = args.command {
+        let queue_mgr = KafkaQueueManager::new().await?;
+        queue_mgr.create(TICKET_MERGES_QUEUE).await?;
+        let request = MergeRequest {
+            source_ticket_id: source,
+            target_ticket_id: target,
+        };
+        let sent_id = queue_mgr.send(TICKET_MERGES_QUEUE, &request).await?;
+        println!(
+            "Merge of ticket {} into {} requested (id={})",
+            request.source_ticket_id, request.target_ticket_id, sent_id
+        );
+        return Ok(());
+    }
+
+    println!("Starting ticket similarity service...");
+    // Own consumer groups: ticket-storage reads the same topics
+    let queue_mgr = KafkaQueueManager::with_group_id("ticket-similarity")
+        .await
+        .context("Failed to connect to Kafka")?;
+    queue_mgr.create(LABELED_TICKETS_QUEUE).await?;
+    queue_mgr.create(MERGE_SUGGESTIONS_QUEUE).await?;
+    let merges_mgr = KafkaQueueManager::with_group_id("ticket-similarity-merges")
+        .await
+        .context("Failed to connect to Kafka")?;
+    merges_mgr.create(TICKET_MERGES_QUEUE).await?;
+
+    let threshold = std::env::var("MERGE_SCORE_THRESHOLD")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(DEFAULT_THRESHOLD);
+    let embedder = HashEmbedder::default();
+    // The window is kept in memory, rebuilt from the stored tickets on start
+    let repository = PostgresTicketRepository::connect(&postgres_url_from_env()).await?;
+    let recent_tickets = Mutex::new(rebuild_window(&repository, &embedder).await?);
+
+    println!(
+        "Listening for labeled tickets on '{}' (threshold {})...",
+        LABELED_TICKETS_QUEUE, threshold
+    );
+    let compare_ticket = async |msg: Message<LabeledTicket>| {
+        let ticket = msg.message;
+        let text = text_of(
+            &ticket.title,
+            &ticket.description,
+            &ticket.original_message.body,
+        );
+        let recent = RecentTicket {
+            embedding: embedder.embed(&text).await?,
+            id: ticket.id,
+            contact: ticket.original_message.contact,
+            tags: ticket.tags,
+            labeled_at: ticket.labeled_at,
+        };
+
+        let ticket_id = recent.id.clone();
+        let candidates = {
+            let mut recent_tickets = recent_tickets.lock().unwrap();
+            // Follow-ups of a known ticket are not new requests
+            let candidates = if recent_tickets.contains(&ticket_id) {
+                Vec::new()
+            } else {
+                recent_tickets.candidates(&recent, threshold)
+            };
+            recent_tickets.insert(recent);
+            candidates
+        };
+
+        for candidate in candidates {
+            let suggestion = MergeSuggestion {
+                ticket_id: ticket_id.clone(),
+                candidate_ticket_id: candidate.ticket_id,
+                score: candidate.score,
+                reasons: candidate.reasons,
+                created_at: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)?
+                    .as_secs(),
+            };
+            let sent_id = queue_mgr.send(MERGE_SUGGESTIONS_QUEUE, &suggestion).await?;
+            println!(
+                "Suggested merging ticket {} into {} (score {:.2}: {}) (id={})",
+                suggestion.ticket_id,
+                suggestion.candidate_ticket_id,
+                suggestion.score,
+                suggestion.reasons.join("; "),
+                sent_id
+            );
+        }
+        Ok(())
+    };
+
+    let forget_merged = async |msg: Message<MergeRequest>| {
+        recent_tickets
+            .lock()
+            .unwrap()
+            .remove(&msg.message.source_ticket_id);
+        Ok(())
+    };
+
+    tokio::try_join!(
+        queue_mgr.register_read(LABELED_TICKETS_QUEUE, &compare_ticket),
+        merges_mgr.register_read(TICKET_MERGES_QUEUE, &forget_merged),
+    )?;
+    Ok(())
+}
+
+/// What a ticket is compared on
+fn text_of(title: &str, description: &str, body: &str) -> String {
+    format!("{} {} {}", title, description, body)
+}
+
+/// Tickets labeled within the window and not merged since
+async fn rebuild_window(
+    repository: &impl TicketRepository,
+    embedder: &impl Embedder,
+) -> anyhow::Result<RecentTickets> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let mut stored = repository
+        .find_created_since(now.saturating_sub(RECENT_WINDOW_SECS))
+        .await?;
+    stored.retain(|ticket| !ticket.is_tombstone());
+    // Oldest first, as they were received
+    stored.reverse();
+
+    let mut recent_tickets = RecentTickets::new(RECENT_WINDOW_SECS);
+    for ticket in stored {
+        let body = ticket
+            .messages
+            .first()
+            .map(|message| message.body.as_str())
+            .unwrap_or_default();
+        let text = text_of(&ticket.title, &ticket.description, body);
+        recent_tickets.insert(RecentTicket {
+            embedding: embedder.embed(&text).await?,
+            // When it was labeled, it entered the New status
+            labeled_at: ticket
+                .status_history
+                .first()
+                .map_or(ticket.created_at, |change| change.changed_at),
+            id: ticket.id,
+            contact: ticket.contact,
+            tags: ticket.tags,
+        });
+    }
+    println!(
+        "Rebuilt the window from {} stored ticket(s)",
+        recent_tickets.len()
+    );
+    Ok(recent_tickets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{dto::CommonMessage, store::memory::MemoryTicketRepository, ticket::Ticket};
+
+    #[tokio::test]
+    async fn test_rebuild_window() -> anyhow::Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let repository = MemoryTicketRepository::default();
+        for (id, created_at) in [
+            ("old", now - RECENT_WINDOW_SECS - 60),
+            ("1", now),
+            ("2", now),
+        ] {
+            let message = CommonMessage {
+                timestamp: created_at,
+                ..CommonMessage::test("jane@example.com", "Charged twice on my invoice")
+            };
+            repository
+                .save(&Ticket::from(LabeledTicket::test(id, message)))
+                .await?;
+        }
+        repository
+            .merge(&MergeRequest {
+                source_ticket_id: "2".to_string(),
+                target_ticket_id: "1".to_string(),
+            })
+            .await?;
+
+        let recent_tickets = rebuild_window(&repository, &HashEmbedder::default()).await?;
+        assert_eq!(recent_tickets.len(), 1);
+        assert!(recent_tickets.contains("1"));
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use common::kb::embed::cosine;
+
+/// Tickets older than this are not considered for merges anymore
+pub const RECENT_WINDOW_SECS: u64 = 7 * 24 * 3600;
+pub const DEFAULT_THRESHOLD: f32 = 0.6;
+
+const CONTACT_WEIGHT: f32 = 0.3;
+const TEXT_WEIGHT: f32 = 0.5;
+const TAGS_WEIGHT: f32 = 0.2;
+
+/// What is kept of an open ticket to compare it with the next ones
+pub struct RecentTicket {
+    pub id: String,
+    pub contact: String,
+    pub tags: Vec<String>,
+    pub embedding: Vec<f32>,
+    pub labeled_at: u64,
+}
+
+/// A recent ticket that looks like the same request
+#[derive(Debug)]
+pub struct Candidate {
+    pub ticket_id: String,
+    pub score: f32,
+    pub reasons: Vec<String>,
+}
+
+/// Share of tags in common (Jaccard index)
+pub fn tag_overlap(a: &[String], b: &[String]) -> f32 {
+    let shared = a.iter().filter(|tag| b.contains(tag)).count();
+    let total = a.len() + b.len() - shared;
+    if total == 0 {
+        0.0
+    } else {
+        shared as f32 / total as f32
+    }
+}
+
+/// Weighted score in [0, 1], with a human-readable reason for each signal that contributed
+pub fn score(a: &RecentTicket, b: &RecentTicket) -> (f32, Vec<String>) {
+    let mut score = 0.0;
+    let mut reasons = Vec::new();
+
+    if a.contact.eq_ignore_ascii_case(&b.contact) {
+        score += CONTACT_WEIGHT;
+        reasons.push("same contact".to_string());
+    }
+
+    let text = cosine(&a.embedding, &b.embedding).max(0.0);
+    if text > 0.0 {
+        score += TEXT_WEIGHT * text;
+        reasons.push(format!("similar content ({:.2})", text));
+    }
+
+    let tags = tag_overlap(&a.tags, &b.tags);
+    if tags > 0.0 {
+        score += TAGS_WEIGHT * tags;
+        let shared: Vec<&str> = a
+            .tags
+            .iter()
+            .filter(|tag| b.tags.contains(tag))
+            .map(String::as_str)
+            .collect();
+        reasons.push(format!("shared tags: {}", shared.join(", ")));
+    }
+
+    (score, reasons)
+}
+
+pub struct RecentTickets {
+    tickets: Vec<RecentTicket>,
+    window_secs: u64,
+}
+
+impl RecentTickets {
+    pub fn new(window_secs: u64) -> Self {
+        RecentTickets {
+            tickets: Vec::new(),
+            window_secs,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tickets.len()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.tickets.iter().any(|t| t.id == id)
+    }
+
+    /// Recent tickets scoring at least `threshold` against `ticket`, best first
+    pub fn candidates(&self, ticket: &RecentTicket, threshold: f32) -> Vec<Candidate> {
+        let mut candidates: Vec<Candidate> = self
+            .tickets
+            .iter()
+            .filter(|other| other.id != ticket.id)
+            .filter(|other| ticket.labeled_at.abs_diff(other.labeled_at) <= self.window_secs)
+            .filter_map(|other| {
+                let (score, reasons) = score(ticket, other);
+                (score >= threshold).then(|| Candidate {
+                    ticket_id: other.id.clone(),
+                    score,
+                    reasons,
+                })
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        candidates
+    }
+
+    /// Remember the ticket, and forget the ones that fell out of the window
+    pub fn insert(&mut self, ticket: RecentTicket) {
+        let oldest = ticket.labeled_at.saturating_sub(self.window_secs);
+        self.tickets
+            .retain(|t| t.id != ticket.id && t.labeled_at >= oldest);
+        self.tickets.push(ticket);
+    }
+
+    /// Forget a ticket that is no longer open, e.g. merged into another one
+    pub fn remove(&mut self, id: &str) {
+        self.tickets.retain(|t| t.id != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::kb::embed::{Embedder, HashEmbedder};
+
+    async fn recent(id: &str, contact: &str, text: &str, tags: &[&str]) -> RecentTicket {
+        RecentTicket {
+            id: id.to_string(),
+            contact: contact.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            embedding: HashEmbedder::default().embed(text).await.unwrap(),
+            labeled_at: 1772000000,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_candidates() {
+        let mut recent_tickets = RecentTickets::new(RECENT_WINDOW_SECS);
+        recent_tickets.insert(
+            recent(
+                "1",
+                "jane@example.com",
+                "Charged twice on my invoice, please refund the duplicate payment",
+                &["billing", "refund"],
+            )
+            .await,
+        );
+        recent_tickets.insert(
+            recent(
+                "2",
+                "bob@example.com",
+                "Cannot reset my password, the link has expired",
+                &["account"],
+            )
+            .await,
+        );
+
+        let ticket = recent(
+            "3",
+            "Jane@example.com",
+            "Still waiting for the refund of the duplicate payment on my invoice",
+            &["refund"],
+        )
+        .await;
+        let candidates = recent_tickets.candidates(&ticket, DEFAULT_THRESHOLD);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].ticket_id, "1");
+        assert!(candidates[0].reasons.contains(&"same contact".to_string()));
+        assert!(
+            candidates[0]
+                .reasons
+                .contains(&"shared tags: refund".to_string())
+        );
+
+        recent_tickets.remove("1");
+        assert!(
+            recent_tickets
+                .candidates(&ticket, DEFAULT_THRESHOLD)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_tag_overlap() {
+        let tags = |t: &[&str]| t.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            tag_overlap(&tags(&["a", "b"]), &tags(&["b", "c"])),
+            1.0 / 3.0
+        );
+        assert_eq!(tag_overlap(&[], &[]), 0.0);
+    }
+}
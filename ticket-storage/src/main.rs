@@ -1,15 +1,88 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
 use common::{
-    LABELED_TICKETS_QUEUE,
-    dto::LabeledTicket,
+    DELIVERY_STATUS_QUEUE, LABELED_TICKETS_QUEUE, OUTBOUND_MSG_QUEUE, TICKET_ASSIGNMENTS_QUEUE,
+    TICKET_EVENTS_QUEUE, TICKET_MERGES_QUEUE, TICKET_TRANSITIONS_QUEUE,
+    dto::{
+        DeliveryStatus, LabeledTicket, MergeRequest, OutboundMessage, TicketEvent,
+        TransitionRequest,
+    },
     queue::{kafka::KafkaQueueManager, Message, QueueManager},
+    store::{
+        postgres::PostgresTicketRepository, postgres_url_from_env, Agent, AgentDirectory,
+        ContactDirectory, ContactFlag, ContactFlags, Identity, Team, TicketRepository,
+        WorkingHours,
+    },
+    ticket::{
+        lifecycle::InvalidTransition, AssignmentStrategy, DeliveryState, InvalidMerge, TicketStatus,
+    },
 };
 use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Write};
-use std::path::Path;
 
 const STORAGE_DIR: &str = "./data/labeled_tickets";
+/// Seconds between two attempts to assign the tickets left triaged
+const DEFAULT_ASSIGN_INTERVAL_SECS: u64 = 60;
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the customer profile of a contact: phone number, email address or channel handle,
+    /// `unknown` when it never wrote
+    Customer { contact: String },
+    /// Record that two contacts are the same customer, their profiles become one
+    LinkContacts { contact: String, other_contact: String },
+    /// Create or update a team queue
+    Team {
+        id: String,
+        #[arg(long)]
+        name: Option<String>,
+        /// round-robin, least-loaded or skill-match
+        #[arg(long, default_value = "least-loaded")]
+        strategy: AssignmentStrategy,
+    },
+    /// Create or update an agent, what is not given stays as it was
+    Agent {
+        id: String,
+        #[arg(long)]
+        name: Option<String>,
+        /// Ids of the teams the agent takes tickets from
+        #[arg(long, value_delimiter = ',')]
+        teams: Option<Vec<String>>,
+        /// Ticket tags the agent knows best
+        #[arg(long, value_delimiter = ',')]
+        skills: Option<Vec<String>>,
+        /// Open tickets the agent can have at once, 10 for a new agent
+        #[arg(long)]
+        capacity: Option<usize>,
+        /// Working days, ISO weekdays: `1-5` for Monday to Friday, or `1,3,5`
+        #[arg(long, requires = "hours")]
+        days: Option<String>,
+        /// Working hours, e.g. `9-17`, or `22-6` for a night shift
+        #[arg(long)]
+        hours: Option<String>,
+        /// Time zone of the working hours, in minutes from UTC
+        #[arg(long, requires = "hours", allow_hyphen_values = true)]
+        utc_offset_minutes: Option<i32>,
+        /// Keep the agent's tickets but give them no new ones
+        #[arg(long)]
+        inactive: bool,
+        /// Give the agent new tickets again
+        #[arg(long, conflicts_with = "inactive")]
+        active: bool,
+    },
+    /// List the teams and agents, with their open tickets
+    Agents,
+    /// Give a triaged ticket to an agent of its team, e.g. once an agent is available again
+    Assign { ticket_id: String },
+}
 
 struct TicketStorage {
     current_date: String,
@@ -55,7 +128,7 @@ impl TicketStorage {
 
     fn store_ticket(&mut self, ticket: LabeledTicket) -> Result<()> {
         let date_time = DateTime::from_timestamp(ticket.labeled_at as i64, 0)
-            .unwrap_or_else(|| Utc::now());
+            .unwrap_or_else(Utc::now);
         let date = date_time.format("%Y-%m-%d").to_string();
         
         self.ensure_writer(&date)?;
@@ -74,53 +147,365 @@ impl TicketStorage {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+    if let Some(command) = args.command {
+        let repository = PostgresTicketRepository::connect(&postgres_url_from_env()).await?;
+        return run_command(&repository, command).await;
+    }
+
     println!("Starting Labeled Ticket Storage service...");
-    println!("Storage directory: {}", STORAGE_DIR);
+    // The daily JSONL files are now only an archive, on unless `JSONL_ARCHIVE=false`
+    let archive = std::env::var("JSONL_ARCHIVE").map_or(true, |v| v != "false");
+    if archive {
+        println!("Archive directory: {}", STORAGE_DIR);
+    }
+
+    let repository = PostgresTicketRepository::connect(&postgres_url_from_env()).await?;
 
-    let mut queue_mgr = KafkaQueueManager::new().await?;
+    let queue_mgr = KafkaQueueManager::new().await?;
     queue_mgr.create(LABELED_TICKETS_QUEUE).await?;
+    // Separate consumer, a Kafka consumer only follows one subscription
+    let merges_mgr = KafkaQueueManager::with_group_id("ticket-storage-merges").await?;
+    merges_mgr.create(TICKET_MERGES_QUEUE).await?;
+    let transitions_mgr = KafkaQueueManager::with_group_id("ticket-storage-transitions").await?;
+    transitions_mgr.create(TICKET_TRANSITIONS_QUEUE).await?;
+    let replies_mgr = KafkaQueueManager::with_group_id("ticket-storage-replies").await?;
+    replies_mgr.create(OUTBOUND_MSG_QUEUE).await?;
+    let deliveries_mgr = KafkaQueueManager::with_group_id("ticket-storage-deliveries").await?;
+    deliveries_mgr.create(DELIVERY_STATUS_QUEUE).await?;
+    queue_mgr.create(TICKET_EVENTS_QUEUE).await?;
+    queue_mgr.create(TICKET_ASSIGNMENTS_QUEUE).await?;
+    let assignments_mgr = KafkaQueueManager::with_group_id("ticket-storage-assignments").await?;
+    let assign_interval = std::env::var("ASSIGN_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_ASSIGN_INTERVAL_SECS);
+
+    let publish = async |event: &TicketEvent| -> Result<()> {
+        queue_mgr.send(TICKET_EVENTS_QUEUE, event).await?;
+        println!(
+            "Ticket {} is now {:?} (was {:?})",
+            event.ticket_id, event.to, event.from
+        );
+        Ok(())
+    };
 
     println!("Listening for labeled tickets on '{}'...", LABELED_TICKETS_QUEUE);
 
-    queue_mgr
-        .register_read(LABELED_TICKETS_QUEUE, &async |msg: Message<LabeledTicket>| {
-            let mut storage = TicketStorage::new()?;
-            
-            println!("Received labeled ticket (id={}): title='{}'", 
-                     msg.msg_id, msg.message.title);
-            
-            if let Err(e) = storage.store_ticket(msg.message) {
-                eprintln!("Failed to store labeled ticket {}: {}", msg.msg_id, e);
-            } else {
-                println!("Labeled ticket {} stored successfully", msg.msg_id);
+    let store_ticket = async |msg: Message<LabeledTicket>| {
+        println!("Received labeled ticket (id={}): title='{}'", 
+                 msg.msg_id, msg.message.title);
+
+        let (ticket, events) = repository.upsert_labeled(msg.message.clone()).await?;
+        println!(
+            "Ticket {} now has {} message(s)",
+            ticket.id,
+            ticket.messages.len()
+        );
+        for event in &events {
+            publish(event).await?;
+        }
+
+        if !archive {
+            return Ok(());
+        }
+        let mut storage = TicketStorage::new()?;
+        if let Err(e) = storage.store_ticket(msg.message) {
+            eprintln!("Failed to store labeled ticket {}: {}", msg.msg_id, e);
+        } else {
+            println!("Labeled ticket {} stored successfully", msg.msg_id);
+        }
+        
+        Ok(())
+    };
+
+    let merge_tickets = async |msg: Message<MergeRequest>| {
+        let request = msg.message;
+        match repository.merge(&request).await {
+            Ok((ticket, event)) => {
+                println!(
+                    "Merged ticket {} into {}, which now has {} message(s)",
+                    request.source_ticket_id,
+                    ticket.id,
+                    ticket.messages.len()
+                );
+                publish(&event).await?;
             }
-            
-            Ok(())
-        })
-        .await?;
+            // As for transitions, only the merges no retry would make possible are dropped
+            Err(e) if e.downcast_ref::<InvalidMerge>().is_some() => eprintln!(
+                "Rejected merge of ticket {} into {}: {}",
+                request.source_ticket_id, request.target_ticket_id, e
+            ),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    };
+
+    // Give a ticket triaged at `triaged_at` to an agent of its team. Assigned already, e.g. when
+    // the event is redelivered after a failed publish, the assignment is notified again.
+    let assign = async |ticket_id: &str, triaged_at: u64| -> Result<()> {
+        let assigned = match repository.auto_assign(ticket_id, now()).await? {
+            Some(assigned) => Some(assigned),
+            None => repository
+                .resolve(ticket_id)
+                .await?
+                .and_then(|ticket| ticket.assigned_since(triaged_at)),
+        };
+        match assigned {
+            Some((event, assigned)) => {
+                publish(&event).await?;
+                queue_mgr.send(TICKET_ASSIGNMENTS_QUEUE, &assigned).await?;
+            }
+            None => println!("No agent available for ticket {}", ticket_id),
+        }
+        Ok(())
+    };
+
+    // Triaged tickets are given to an agent right away, apart from the transition: a failed
+    // assignment is retried on its own
+    let assign_triaged = async |msg: Message<TicketEvent>| {
+        let event = msg.message;
+        if event.to == TicketStatus::Triaged {
+            assign(&event.ticket_id, event.changed_at).await?;
+        }
+        Ok(())
+    };
+
+    // Tickets nobody was available for, once agents are back or have room again
+    let assign_waiting = async || -> Result<()> {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(assign_interval)).await;
+            let waiting = match repository.find_by_status(TicketStatus::Triaged).await {
+                Ok(waiting) => waiting,
+                Err(e) => {
+                    eprintln!("Failed to list triaged tickets: {:?}", e);
+                    continue;
+                }
+            };
+            for ticket in waiting {
+                let Some(triaged) = ticket.status_history.last() else {
+                    continue;
+                };
+                if let Err(e) = assign(&ticket.id, triaged.changed_at).await {
+                    eprintln!("Failed to assign ticket {}: {:?}", ticket.id, e);
+                }
+            }
+        }
+    };
+
+    let transition_ticket = async |msg: Message<TransitionRequest>| {
+        match repository.transition(&msg.message).await {
+            Ok(event) => {
+                publish(&event).await?;
+            }
+            // Retrying would not make it valid, anything else goes through retries and the DLQ
+            Err(e) if e.downcast_ref::<InvalidTransition>().is_some() => {
+                eprintln!("Rejected transition: {}", e)
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    };
+
+    let add_reply = async |msg: Message<OutboundMessage>| {
+        let reply = msg.message;
+        let ticket = repository.add_reply(&reply).await?;
+        println!(
+            "Reply {} added to ticket {}, which now has {} reply(ies)",
+            reply.id,
+            ticket.id,
+            ticket.replies.len()
+        );
+        Ok(())
+    };
+
+    let update_delivery = async |msg: Message<DeliveryStatus>| {
+        let status = msg.message;
+        if !repository.apply_delivery(&status).await? {
+            return Ok(());
+        }
+        println!(
+            "Reply {} on ticket {} is {:?}",
+            status.outbound_id, status.ticket_id, status.state
+        );
+        // Agents see that the contact cannot be reached, until a reply gets through again
+        match status.state {
+            DeliveryState::Bounced => {
+                repository
+                    .flag(&status.contact, ContactFlag::Bounced, status.reason.as_deref())
+                    .await?;
+                println!("Contact {} flagged as bounced", status.contact);
+            }
+            DeliveryState::Delivered | DeliveryState::Read => {
+                repository.unflag(&status.contact, ContactFlag::Bounced).await?
+            }
+            _ => {}
+        }
+        Ok(())
+    };
+
+    tokio::try_join!(
+        queue_mgr.register_read(LABELED_TICKETS_QUEUE, &store_ticket),
+        merges_mgr.register_read(TICKET_MERGES_QUEUE, &merge_tickets),
+        transitions_mgr.register_read(TICKET_TRANSITIONS_QUEUE, &transition_ticket),
+        replies_mgr.register_read(OUTBOUND_MSG_QUEUE, &add_reply),
+        deliveries_mgr.register_read(DELIVERY_STATUS_QUEUE, &update_delivery),
+        assignments_mgr.register_read(TICKET_EVENTS_QUEUE, &assign_triaged),
+        assign_waiting(),
+    )?;
 
     Ok(())
 }
 
+async fn run_command(repository: &PostgresTicketRepository, command: Command) -> Result<()> {
+    match command {
+        Command::Customer { contact } => {
+            // Looking a contact up must not register it
+            match repository.customer_of(&Identity::parse(&contact)).await? {
+                Some(customer_id) => print_profile(repository, &customer_id).await?,
+                None => println!("unknown"),
+            }
+        }
+        Command::LinkContacts { contact, other_contact } => {
+            let customer_id = repository.link(&contact, &other_contact).await?;
+            print_profile(repository, &customer_id).await?;
+        }
+        Command::Team { id, name, strategy } => {
+            let team = Team {
+                name: name.unwrap_or_else(|| id.clone()),
+                id,
+                strategy,
+            };
+            repository.save_team(&team).await?;
+            println!("{}", serde_json::to_string_pretty(&team)?);
+        }
+        Command::Agent {
+            id,
+            name,
+            teams,
+            skills,
+            capacity,
+            days,
+            hours,
+            utc_offset_minutes,
+            inactive,
+            active,
+        } => {
+            let previous = repository.agent(&id).await?;
+            let working_hours = match hours {
+                Some(hours) => Some(parse_working_hours(
+                    days.as_deref().unwrap_or("1-7"),
+                    &hours,
+                    utc_offset_minutes.unwrap_or(0),
+                )?),
+                None => previous.as_ref().and_then(|agent| agent.working_hours.clone()),
+            };
+            let agent = match previous {
+                Some(previous) => Agent {
+                    name: name.unwrap_or(previous.name),
+                    teams: teams.unwrap_or(previous.teams),
+                    skills: skills.unwrap_or(previous.skills),
+                    capacity: capacity.unwrap_or(previous.capacity),
+                    working_hours,
+                    active: (previous.active || active) && !inactive,
+                    ..previous
+                },
+                None => Agent {
+                    name: name.unwrap_or_else(|| id.clone()),
+                    id,
+                    teams: teams.unwrap_or_default(),
+                    skills: skills.unwrap_or_default(),
+                    capacity: capacity.unwrap_or(10),
+                    working_hours,
+                    active: !inactive,
+                    last_assigned_at: None,
+                },
+            };
+            repository.save_agent(&agent).await?;
+            println!("{}", serde_json::to_string_pretty(&agent)?);
+        }
+        Command::Agents => {
+            for team in repository.teams().await? {
+                println!("Team {} ({}): {}", team.id, team.name, team.strategy);
+            }
+            for agent in repository.agents().await? {
+                println!(
+                    "Agent {} ({}): {}/{} open ticket(s), teams [{}], skills [{}]{}{}",
+                    agent.id,
+                    agent.name,
+                    repository.open_tickets_of(&agent.id).await?,
+                    agent.capacity,
+                    agent.teams.join(", "),
+                    agent.skills.join(", "),
+                    if agent.is_available(now()) { "" } else { ", away" },
+                    if agent.active { "" } else { ", inactive" },
+                );
+            }
+        }
+        Command::Assign { ticket_id } => match repository.auto_assign(&ticket_id, now()).await? {
+            Some((_, assigned)) => println!("{}", serde_json::to_string_pretty(&assigned)?),
+            None => println!(
+                "Ticket {} was not assigned: not triaged, or no agent available",
+                ticket_id
+            ),
+        },
+    }
+    Ok(())
+}
+
+async fn print_profile(repository: &PostgresTicketRepository, customer_id: &str) -> Result<()> {
+    let profile = repository.profile(customer_id).await?;
+    println!("{}", serde_json::to_string_pretty(&profile)?);
+    Ok(())
+}
+
+/// `1-5` and `9-17`, or lists of days like `1,3,5`
+fn parse_working_hours(days: &str, hours: &str, utc_offset_minutes: i32) -> Result<WorkingHours> {
+    let mut weekdays = vec![];
+    for part in days.split(',') {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let (first, last): (u8, u8) = (first.trim().parse()?, last.trim().parse()?);
+        if !(1..=7).contains(&first) || !(1..=7).contains(&last) || first > last {
+            anyhow::bail!("Invalid days '{}', expected ISO weekdays from 1 to 7", days);
+        }
+        weekdays.extend(first..=last);
+    }
+    let Some((start, end)) = hours.split_once('-') else {
+        anyhow::bail!("Invalid hours '{}', expected e.g. 9-17", hours);
+    };
+    let (start_hour, end_hour): (u8, u8) = (start.trim().parse()?, end.trim().parse()?);
+    if start_hour > 23 || end_hour > 24 || start_hour == end_hour {
+        anyhow::bail!("Invalid hours '{}', expected e.g. 9-17", hours);
+    }
+    Ok(WorkingHours {
+        days: weekdays,
+        start_hour,
+        end_hour,
+        utc_offset_minutes,
+    })
+}
+
+fn now() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use common::dto::{CommonMessage, Origin};
+    use common::dto::CommonMessage;
+    use std::path::Path;
 
     fn create_test_labeled_ticket() -> LabeledTicket {
+        let message = CommonMessage {
+            timestamp: 1772000000,
+            ticket_hint: Some("TEST-123".to_string()),
+            ..CommonMessage::test("test@example.com", "Test message for storage")
+        };
         LabeledTicket {
-            id: "test-123".to_string(),
-            original_message: CommonMessage {
-                contact: "test@example.com".to_string(),
-                origin: Origin::Email,
-                body: "Test message for storage".to_string(),
-                timestamp: 1772000000,
-                ticket_hint: Some("TEST-123".to_string()),
-            },
             title: "Test Support Request".to_string(),
             tags: vec!["test".to_string(), "support".to_string()],
             description: "This is a test ticket for storage verification".to_string(),
-            labeled_at: 1772000000,
+            ..LabeledTicket::test("test-123", message)
         }
     }
 
@@ -139,4 +524,14 @@ mod tests {
         
         Ok(())
     }
+
+    #[test]
+    fn test_parse_working_hours() -> Result<()> {
+        let hours = parse_working_hours("1-3,5", "22-6", 120)?;
+        assert_eq!(hours.days, vec![1, 2, 3, 5]);
+        assert_eq!((hours.start_hour, hours.end_hour), (22, 6));
+        assert!(parse_working_hours("0-5", "9-17", 0).is_err());
+        assert!(parse_working_hours("1-5", "9", 0).is_err());
+        Ok(())
+    }
 }
\ No newline at end of file
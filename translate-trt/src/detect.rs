@@ -0,0 +1,94 @@
+/// Languages our customers write in, with their most frequent function words
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "fr",
+        &[
+            "le", "la", "les", "un", "une", "des", "du", "de", "et", "est", "je", "vous", "nous",
+            "il", "elle", "pas", "ne", "que", "qui", "pour", "dans", "sur", "avec", "mon", "ma",
+            "mes", "votre", "vos", "ce", "cette", "au", "aux", "bonjour", "merci", "depuis",
+        ],
+    ),
+    (
+        "en",
+        &[
+            "the", "an", "and", "is", "are", "i", "you", "we", "it", "not", "to", "of", "for",
+            "in", "on", "with", "my", "your", "this", "that", "have", "has", "was", "be", "hello",
+            "hi", "thanks", "please", "since", "can", "do",
+        ],
+    ),
+];
+
+/// Minimum number of stopwords before trusting the guess
+const MIN_EVIDENCE: usize = 2;
+
+/// ISO 639-1 code of the language the text is most likely written in, based on stopword
+/// frequency. `None` when the text is too short or the languages are tied.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut scores: Vec<(&'static str, usize)> = STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let count = words
+                .iter()
+                .filter(|w| stopwords.contains(&w.as_str()))
+                .count();
+            (*language, count)
+        })
+        .collect();
+    scores.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    match scores.as_slice() {
+        [(language, best), (_, second), ..] if *best >= MIN_EVIDENCE && best > second => {
+            Some(language)
+        }
+        _ => None,
+    }
+}
+
+/// Whether the text has words at all: emojis, numbers and punctuation are in no language
+pub fn has_words(text: &str) -> bool {
+    text.chars().any(char::is_alphabetic)
+}
+
+/// ISO 639-1 code in the answer of a model asked for one, e.g. `FR.` or `"de"`
+pub fn parse_language_code(answer: &str) -> Option<String> {
+    let code = answer
+        .trim()
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+    (code.len() == 2 && code.chars().all(|c| c.is_ascii_lowercase())).then_some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(
+            detect_language("Bonjour, je n'arrive pas à me connecter depuis hier."),
+            Some("fr")
+        );
+        assert_eq!(
+            detect_language("Hello, I cannot log in since yesterday, can you help?"),
+            Some("en")
+        );
+        assert_eq!(detect_language("Merci !"), None);
+        assert_eq!(detect_language(""), None);
+    }
+
+    #[test]
+    fn test_parse_language_code() {
+        assert_eq!(parse_language_code(" FR.\n").as_deref(), Some("fr"));
+        assert_eq!(parse_language_code("\"de\"").as_deref(), Some("de"));
+        assert_eq!(parse_language_code("French"), None);
+        assert_eq!(parse_language_code(""), None);
+        assert!(has_words("Merci !"));
+        assert!(!has_words("👍 12 ?"));
+    }
+}
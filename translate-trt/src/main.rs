@@ -0,0 +1,104 @@
+mod detect;
+
+use anyhow::Context;
+use common::{
+    ACCEPTED_MSG_QUEUE, TRANSLATED_MSG_QUEUE,
+    dto::CommonMessage,
+    llm,
+    queue::{Message, QueueManager, kafka::KafkaQueueManager},
+};
+use detect::{detect_language, has_words, parse_language_code};
+use openrouter_rs::OpenRouterClient;
+
+/// Language agents work in, bodies in any other language get translated to it
+const TARGET_LANGUAGE: &str = "en";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    println!("Starting translation service...");
+    let queue_mgr = KafkaQueueManager::with_group_id("translate-trt")
+        .await
+        .context("Failed to connect to Kafka")?;
+    queue_mgr.create(ACCEPTED_MSG_QUEUE).await?;
+    queue_mgr.create(TRANSLATED_MSG_QUEUE).await?;
+
+    // `OPENROUTER_BASE_URL` can point to a local model server, `TRANSLATION_MODEL`
+    // allows a smaller model than the one used for labeling
+    let client = llm::client_from_env()?;
+    let model = std::env::var("TRANSLATION_MODEL").unwrap_or_else(|_| llm::model_from_env());
+
+    println!("Listening for messages on '{}'...", ACCEPTED_MSG_QUEUE);
+    queue_mgr
+        .register_read(ACCEPTED_MSG_QUEUE, &async |msg: Message<CommonMessage>| {
+            let message = translate(&client, &model, msg.message).await;
+            let sent_id = queue_mgr.send(TRANSLATED_MSG_QUEUE, &message).await?;
+            println!(
+                "Message from {} ({}) forwarded (id={})",
+                message.contact,
+                message.language.as_deref().unwrap_or("unknown language"),
+                sent_id
+            );
+            Ok(())
+        })
+        .await
+}
+
+fn build_detection_prompt(body: &str) -> String {
+    format!(
+        "Give the ISO 639-1 code of the language this customer support message is written in.\n\
+         Output ONLY the two-letter code, without any additional text.\n\n{}",
+        body
+    )
+}
+
+/// Language of a message too short for the stopwords, asked to the model
+async fn ask_language(client: &OpenRouterClient, model: &str, body: &str) -> Option<String> {
+    if !has_words(body) {
+        return None;
+    }
+    match llm::complete(client, model, build_detection_prompt(body), None).await {
+        Ok(completion) => parse_language_code(&completion.content),
+        Err(e) => {
+            eprintln!("Failed to detect the language of a message: {}", e);
+            None
+        }
+    }
+}
+
+fn build_prompt(language: &str, body: &str) -> String {
+    format!(
+        "Translate this customer support message from the language with ISO 639-1 code '{}' to English.\n\
+         Keep names, numbers and references unchanged. Output ONLY the translation, without any additional text.\n\n{}",
+        language, body
+    )
+}
+
+/// Set the detected language and, for other languages than English, the translated body. The
+/// model is asked for the language of messages too short to detect it from their stopwords.
+/// A failed translation is logged and the message goes through untranslated.
+async fn translate(
+    client: &OpenRouterClient,
+    model: &str,
+    mut message: CommonMessage,
+) -> CommonMessage {
+    let language = match detect_language(&message.body) {
+        Some(language) => language.to_string(),
+        None => match ask_language(client, model, &message.body).await {
+            Some(language) => language,
+            None => return message,
+        },
+    };
+    message.language = Some(language.clone());
+    if language == TARGET_LANGUAGE {
+        return message;
+    }
+
+    match llm::complete(client, model, build_prompt(&language, &message.body), None).await {
+        Ok(completion) => message.translated_body = Some(completion.content.trim().to_string()),
+        Err(e) => eprintln!(
+            "Failed to translate message from {}: {}",
+            message.contact, e
+        ),
+    }
+    message
+}
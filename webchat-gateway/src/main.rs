@@ -0,0 +1,243 @@
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use axum::{
+    Form, Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+};
+use common::{
+    WEBCHAT_MSG_QUEUE,
+    dto::WebChatMessage,
+    queue::{QueueManager, kafka::KafkaQueueManager},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:8092";
+/// Longer messages are rejected, the form says so too
+const MAX_MESSAGE_CHARS: usize = 5000;
+const FORM: &str = include_str!("form.html");
+
+pub trait Publisher: Send + Sync + 'static {
+    fn publish(&self, message: &WebChatMessage) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Publishes the messages on the web chat topic, where webchat-trt normalizes them
+struct KafkaPublisher {
+    queue_mgr: KafkaQueueManager,
+}
+
+impl Publisher for KafkaPublisher {
+    async fn publish(&self, message: &WebChatMessage) -> Result<()> {
+        let msg_id = self.queue_mgr.send(WEBCHAT_MSG_QUEUE, message).await?;
+        println!(
+            "Message of session {} published (id={})",
+            message.session_id, msg_id
+        );
+        Ok(())
+    }
+}
+
+/// Sent by the contact form, or as JSON by the chat widget, which keeps its session id across
+/// the messages of a conversation
+#[derive(Deserialize)]
+struct Submission {
+    session_id: Option<String>,
+    name: Option<String>,
+    email: Option<String>,
+    subject: Option<String>,
+    message: String,
+}
+
+impl Submission {
+    fn into_message(self) -> Result<WebChatMessage, &'static str> {
+        let message = self.message.trim();
+        if message.is_empty() {
+            return Err("The message is empty");
+        }
+        if message.chars().count() > MAX_MESSAGE_CHARS {
+            return Err("The message is too long");
+        }
+        // Empty form fields come as empty strings
+        let filled = |field: Option<String>| {
+            field
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let email = filled(self.email);
+        if email.as_ref().is_some_and(|email| !email.contains('@')) {
+            return Err("The email address is invalid");
+        }
+        Ok(WebChatMessage {
+            session_id: filled(self.session_id).unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            name: filled(self.name),
+            email,
+            subject: filled(self.subject),
+            message: message.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Failed to get current timestamp")
+                .as_secs(),
+        })
+    }
+}
+
+fn router<P: Publisher>(publisher: Arc<P>) -> Router {
+    Router::new()
+        .route("/", get(Html(FORM)))
+        .route("/form", post(submit_form::<P>))
+        .route("/messages", post(submit_json::<P>))
+        .with_state(publisher)
+}
+
+async fn submit_form<P: Publisher>(
+    State(publisher): State<Arc<P>>,
+    Form(submission): Form<Submission>,
+) -> Response {
+    let message = match submission.into_message() {
+        Ok(message) => message,
+        Err(reason) => return (StatusCode::UNPROCESSABLE_ENTITY, Html(reason)).into_response(),
+    };
+    if let Err(e) = publisher.publish(&message).await {
+        println!("Failed to publish form message: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    Html("<p>Thank you, we will get back to you soon.</p>").into_response()
+}
+
+async fn submit_json<P: Publisher>(
+    State(publisher): State<Arc<P>>,
+    Json(submission): Json<Submission>,
+) -> Response {
+    let message = match submission.into_message() {
+        Ok(message) => message,
+        Err(reason) => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "error": reason })),
+            )
+                .into_response();
+        }
+    };
+    if let Err(e) = publisher.publish(&message).await {
+        println!("Failed to publish chat message: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({ "session_id": message.session_id })),
+    )
+        .into_response()
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let queue_mgr = KafkaQueueManager::new()
+        .await
+        .context("Failed to connect to Kafka")?;
+    queue_mgr.create(WEBCHAT_MSG_QUEUE).await?;
+    let publisher = Arc::new(KafkaPublisher { queue_mgr });
+
+    let addr = env::var("WEBCHAT_GATEWAY_ADDR").unwrap_or_else(|_| DEFAULT_LISTEN_ADDR.to_string());
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    println!(
+        "Contact form on http://{}/, chat messages on /messages",
+        addr
+    );
+    axum::serve(listener, router(publisher)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    #[derive(Default)]
+    struct Recorder {
+        messages: Mutex<Vec<WebChatMessage>>,
+    }
+
+    impl Publisher for Recorder {
+        async fn publish(&self, message: &WebChatMessage) -> Result<()> {
+            self.messages.lock().unwrap().push(message.clone());
+            Ok(())
+        }
+    }
+
+    async fn post(
+        recorder: &Arc<Recorder>,
+        uri: &str,
+        content_type: &str,
+        body: &str,
+    ) -> (StatusCode, String) {
+        let request = Request::post(uri)
+            .header("content-type", content_type)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router(recorder.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[tokio::test]
+    async fn test_form() {
+        let recorder = Arc::new(Recorder::default());
+        let (status, _) = post(
+            &recorder,
+            "/form",
+            "application/x-www-form-urlencoded",
+            "name=Anna&email=Anna%40shop.example&subject=&message=Where+is+my+parcel%3F",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let messages = recorder.messages.lock().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].email.as_deref(), Some("Anna@shop.example"));
+        assert_eq!(messages[0].subject, None);
+        assert_eq!(messages[0].message, "Where is my parcel?");
+        assert!(!messages[0].session_id.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chat_keeps_session() {
+        let recorder = Arc::new(Recorder::default());
+        let (status, body) = post(
+            &recorder,
+            "/messages",
+            "application/json",
+            r#"{"session_id": "s-42", "message": "Hello"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert!(body.contains("s-42"));
+        assert_eq!(recorder.messages.lock().unwrap()[0].session_id, "s-42");
+
+        let (status, _) = post(
+            &recorder,
+            "/messages",
+            "application/json",
+            r#"{"message": "   "}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let too_long = json!({ "message": "a".repeat(MAX_MESSAGE_CHARS + 1) }).to_string();
+        let (status, _) = post(&recorder, "/messages", "application/json", &too_long).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(recorder.messages.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,31 @@
+use common::{
+    WEBCHAT_MSG_QUEUE,
+    channel::{self, ChannelAdapter},
+    dto::{CommonMessage, WebChatMessage},
+};
+
+struct WebChat;
+
+impl ChannelAdapter for WebChat {
+    type Input = WebChatMessage;
+    type Parts = ();
+    const NAME: &'static str = "web chat";
+    const SOURCE_QUEUE: &'static str = WEBCHAT_MSG_QUEUE;
+
+    async fn normalize(
+        &self,
+        input: WebChatMessage,
+    ) -> anyhow::Result<Option<(CommonMessage, ())>> {
+        Ok(Some((input.try_into()?, ())))
+    }
+
+    /// The browser session, even for visitors who left their email address
+    fn thread_key(&self, input: &WebChatMessage, _message: &CommonMessage) -> Option<String> {
+        Some(input.session_id.trim().to_string())
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    channel::run(WebChat).await
+}
@@ -0,0 +1,301 @@
+mod signature;
+mod webhook;
+
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use common::{
+    DELIVERY_STATUS_QUEUE, WHATSAPP_MSG_QUEUE,
+    dto::{DeliveryStatus, WhatsAppMessage},
+    queue::{QueueManager, kafka::KafkaQueueManager},
+};
+use serde::Deserialize;
+use webhook::Notification;
+
+const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:8090";
+
+pub trait Publisher: Send + Sync + 'static {
+    fn publish(&self, message: &WhatsAppMessage) -> impl Future<Output = Result<()>> + Send;
+
+    fn publish_status(&self, status: &DeliveryStatus) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Publishes received messages on the WhatsApp topic, where whatsapp-trt normalizes them, and
+/// the statuses of the replies on the delivery topic
+struct KafkaPublisher {
+    queue_mgr: KafkaQueueManager,
+}
+
+impl Publisher for KafkaPublisher {
+    async fn publish(&self, message: &WhatsAppMessage) -> Result<()> {
+        let msg_id = self.queue_mgr.send(WHATSAPP_MSG_QUEUE, message).await?;
+        println!(
+            "Message from {} published (id={}, {} attachments)",
+            message.sender,
+            msg_id,
+            message.attachments.len()
+        );
+        Ok(())
+    }
+
+    async fn publish_status(&self, status: &DeliveryStatus) -> Result<()> {
+        self.queue_mgr.send(DELIVERY_STATUS_QUEUE, status).await?;
+        Ok(())
+    }
+}
+
+struct AppState<P> {
+    /// Token entered in the Meta app dashboard when subscribing the webhook
+    verify_token: String,
+    /// App secret signing the notifications, unsigned notifications are rejected
+    app_secret: String,
+    publisher: P,
+}
+
+fn router<P: Publisher>(state: Arc<AppState<P>>) -> Router {
+    Router::new()
+        .route("/webhook", get(verify::<P>).post(receive::<P>))
+        .with_state(state)
+}
+
+#[derive(Deserialize)]
+struct Verification {
+    #[serde(rename = "hub.mode")]
+    mode: Option<String>,
+    #[serde(rename = "hub.verify_token")]
+    verify_token: Option<String>,
+    #[serde(rename = "hub.challenge")]
+    challenge: Option<String>,
+}
+
+/// Subscription handshake: echo the challenge when the token matches
+async fn verify<P: Publisher>(
+    State(state): State<Arc<AppState<P>>>,
+    Query(verification): Query<Verification>,
+) -> Response {
+    match verification {
+        Verification {
+            mode: Some(mode),
+            verify_token: Some(token),
+            challenge: Some(challenge),
+        } if mode == "subscribe" && token == state.verify_token => {
+            (StatusCode::OK, challenge).into_response()
+        }
+        _ => StatusCode::FORBIDDEN.into_response(),
+    }
+}
+
+/// Notifications. Anything but a 200 makes Meta retry the delivery later, the whole batch: the
+/// messages published before the failure are published again, ticket-storage skips them by their
+/// WhatsApp message id and statuses apply once.
+async fn receive<P: Publisher>(
+    State(state): State<Arc<AppState<P>>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let header = headers
+        .get("x-hub-signature-256")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !signature::verify(&state.app_secret, &body, header) {
+        println!("Rejected a notification with an invalid signature");
+        return StatusCode::UNAUTHORIZED;
+    }
+    let notification: Notification = match serde_json::from_slice(&body) {
+        Ok(notification) => notification,
+        Err(e) => {
+            println!("Invalid notification: {}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+
+    for status in notification.statuses() {
+        let errors: Vec<String> = status
+            .errors
+            .iter()
+            .map(|error| format!("{} ({})", error.title, error.code))
+            .collect();
+        println!(
+            "Message {} to {} is {} since {} {}",
+            status.id,
+            status.recipient_id,
+            status.status,
+            status.timestamp,
+            errors.join(", ")
+        );
+        let Some(delivery) = status.to_delivery_status() else {
+            continue;
+        };
+        if let Err(e) = state.publisher.publish_status(&delivery).await {
+            println!("Failed to publish status of message {}: {:?}", status.id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    for message in notification.messages() {
+        let Some(whatsapp_message) = message.to_whatsapp_message() else {
+            println!("Skipping {} message {}", message.kind, message.id);
+            continue;
+        };
+        if let Err(e) = state.publisher.publish(&whatsapp_message).await {
+            println!("Failed to publish message {}: {:?}", message.id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    StatusCode::OK
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let verify_token =
+        env::var("WHATSAPP_VERIFY_TOKEN").context("WHATSAPP_VERIFY_TOKEN must be set")?;
+    // Anyone knowing the callback URL could post notifications otherwise
+    let app_secret = env::var("WHATSAPP_APP_SECRET").context("WHATSAPP_APP_SECRET must be set")?;
+
+    let queue_mgr = KafkaQueueManager::new()
+        .await
+        .context("Failed to connect to Kafka")?;
+    queue_mgr.create(WHATSAPP_MSG_QUEUE).await?;
+    queue_mgr.create(DELIVERY_STATUS_QUEUE).await?;
+    let state = Arc::new(AppState {
+        verify_token,
+        app_secret,
+        publisher: KafkaPublisher { queue_mgr },
+    });
+
+    let addr =
+        env::var("WHATSAPP_GATEWAY_ADDR").unwrap_or_else(|_| DEFAULT_LISTEN_ADDR.to_string());
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    println!("WhatsApp webhook listening on {}/webhook", addr);
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    #[derive(Default)]
+    struct Recorder {
+        messages: Mutex<Vec<WhatsAppMessage>>,
+        statuses: Mutex<Vec<DeliveryStatus>>,
+    }
+
+    impl Publisher for Arc<Recorder> {
+        async fn publish(&self, message: &WhatsAppMessage) -> Result<()> {
+            self.messages.lock().unwrap().push(message.clone());
+            Ok(())
+        }
+
+        async fn publish_status(&self, status: &DeliveryStatus) -> Result<()> {
+            self.statuses.lock().unwrap().push(status.clone());
+            Ok(())
+        }
+    }
+
+    fn app() -> (Router, Arc<Recorder>) {
+        let recorder = Arc::new(Recorder::default());
+        let state = Arc::new(AppState {
+            verify_token: "verify-me".to_string(),
+            app_secret: "app-secret".to_string(),
+            publisher: recorder.clone(),
+        });
+        (router(state), recorder)
+    }
+
+    async fn post(app: Router, body: &str, signature: &str) -> StatusCode {
+        let request = Request::post("/webhook")
+            .header("content-type", "application/json")
+            .header("x-hub-signature-256", signature)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_verification() {
+        let (app, _) = app();
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get(
+                    "/webhook?hub.mode=subscribe&hub.verify_token=verify-me&hub.challenge=1158201444",
+                )
+                .body(Body::empty())
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"1158201444");
+
+        let response = app
+            .oneshot(
+                Request::get("/webhook?hub.mode=subscribe&hub.verify_token=guess&hub.challenge=1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_signed_notifications_are_published() {
+        let (app, recorder) = app();
+        for fixture in [
+            include_str!("../fixtures/text.json"),
+            include_str!("../fixtures/media.json"),
+            include_str!("../fixtures/status.json"),
+        ] {
+            let signature = signature::sign("app-secret", fixture.as_bytes());
+            assert_eq!(post(app.clone(), fixture, &signature).await, StatusCode::OK);
+        }
+        let published = recorder.messages.lock().unwrap();
+        assert_eq!(published.len(), 4);
+        assert_eq!(published[0].sender, "+33612345678");
+        let statuses = recorder.statuses.lock().unwrap();
+        assert_eq!(statuses.len(), 3);
+        assert_eq!(statuses[0].outbound_id, "r1");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_bad_signatures_and_payloads() {
+        let (app, recorder) = app();
+        let fixture = include_str!("../fixtures/text.json");
+        let forged = signature::sign("guessed-secret", fixture.as_bytes());
+        assert_eq!(
+            post(app.clone(), fixture, &forged).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            post(app.clone(), fixture, "").await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let garbage = "not json";
+        let signature = signature::sign("app-secret", garbage.as_bytes());
+        assert_eq!(
+            post(app, garbage, &signature).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert!(recorder.messages.lock().unwrap().is_empty());
+    }
+}
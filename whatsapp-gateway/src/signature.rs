@@ -0,0 +1,46 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Check `X-Hub-Signature-256: sha256=<hex HMAC-SHA256 of the body keyed with the app secret>`,
+/// in constant time
+pub fn verify(app_secret: &str, body: &[u8], header: &str) -> bool {
+    let Some(signature) = header
+        .strip_prefix("sha256=")
+        .and_then(|hex_signature| hex::decode(hex_signature).ok())
+    else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(app_secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Header value for a body, as Meta computes it
+#[cfg(test)]
+pub fn sign(app_secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(app_secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let body = br#"{"object":"whatsapp_business_account"}"#;
+        let header = sign("app-secret", body);
+        assert!(verify("app-secret", body, &header));
+        assert!(!verify("other-secret", body, &header));
+        assert!(!verify("app-secret", b"{}", &header));
+        assert!(!verify(
+            "app-secret",
+            body,
+            header.trim_start_matches("sha256=")
+        ));
+        assert!(!verify("app-secret", body, "sha256=not-hex"));
+    }
+}
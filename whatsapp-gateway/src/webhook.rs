@@ -0,0 +1,316 @@
+//! Payloads of the WhatsApp Business Cloud API webhook (`messages` field), and their conversion
+//! to `WhatsAppMessage`. Only the parts the pipeline uses are modelled.
+
+use base64::prelude::{BASE64_STANDARD, Engine};
+use common::dto::{Attachment, DeliveryStatus, Origin, OutboundRef, WhatsAppMessage};
+use common::ticket::DeliveryState;
+use serde::Deserialize;
+
+/// Cloud API error of a message sent to a number that is not on WhatsApp
+const UNDELIVERABLE: i64 = 131026;
+
+#[derive(Deserialize, Debug)]
+pub struct Notification {
+    #[serde(default)]
+    pub entry: Vec<Entry>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Entry {
+    #[serde(default)]
+    pub changes: Vec<Change>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Change {
+    pub field: String,
+    pub value: Value,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Value {
+    #[serde(default)]
+    pub messages: Vec<InboundMessage>,
+    #[serde(default)]
+    pub statuses: Vec<Status>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InboundMessage {
+    /// Sender phone number, without the leading `+`
+    pub from: String,
+    pub id: String,
+    /// Unix seconds, as a string
+    pub timestamp: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: Option<Text>,
+    pub image: Option<Media>,
+    pub audio: Option<Media>,
+    pub video: Option<Media>,
+    pub document: Option<Media>,
+    pub sticker: Option<Media>,
+    pub location: Option<Location>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Text {
+    pub body: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Media {
+    pub id: String,
+    pub mime_type: String,
+    /// Base64-encoded SHA-256 of the content
+    pub sha256: Option<String>,
+    pub caption: Option<String>,
+    pub filename: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub name: Option<String>,
+    pub address: Option<String>,
+}
+
+/// Delivery status of a message sent by the business
+#[derive(Deserialize, Debug)]
+pub struct Status {
+    /// Id of the message sent
+    pub id: String,
+    /// sent, delivered, read or failed
+    pub status: String,
+    pub timestamp: String,
+    pub recipient_id: String,
+    /// Set by whatsapp-sender to the `OutboundRef` of the reply
+    pub biz_opaque_callback_data: Option<String>,
+    #[serde(default)]
+    pub errors: Vec<StatusError>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusError {
+    pub code: i64,
+    pub title: String,
+}
+
+impl Notification {
+    pub fn messages(&self) -> impl Iterator<Item = &InboundMessage> {
+        self.values().flat_map(|value| &value.messages)
+    }
+
+    pub fn statuses(&self) -> impl Iterator<Item = &Status> {
+        self.values().flat_map(|value| &value.statuses)
+    }
+
+    fn values(&self) -> impl Iterator<Item = &Value> {
+        self.entry
+            .iter()
+            .flat_map(|entry| &entry.changes)
+            .filter(|change| change.field == "messages")
+            .map(|change| &change.value)
+    }
+}
+
+impl InboundMessage {
+    /// `None` for the kinds of messages that are not forwarded, e.g. reactions, and for
+    /// malformed ones
+    pub fn to_whatsapp_message(&self) -> Option<WhatsAppMessage> {
+        let (content, media) = match self.kind.as_str() {
+            "text" => (self.text.as_ref()?.body.clone(), None),
+            "image" => media_message(self.image.as_ref()?),
+            "audio" => media_message(self.audio.as_ref()?),
+            "video" => media_message(self.video.as_ref()?),
+            "document" => media_message(self.document.as_ref()?),
+            "sticker" => media_message(self.sticker.as_ref()?),
+            "location" => {
+                let location = self.location.as_ref()?;
+                let place = [location.name.as_deref(), location.address.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (
+                    format!(
+                        "Location: {}, {} {}",
+                        location.latitude, location.longitude, place
+                    )
+                    .trim_end()
+                    .to_string(),
+                    None,
+                )
+            }
+            _ => return None,
+        };
+        Some(WhatsAppMessage {
+            sender: format!("+{}", self.from.trim_start_matches('+')),
+            content,
+            timestamp: self.timestamp.parse().ok()?,
+            attachments: media.into_iter().collect(),
+            message_id: Some(self.id.clone()),
+        })
+    }
+}
+
+impl Status {
+    /// `None` for the messages that were not sent by whatsapp-sender, and for malformed statuses
+    pub fn to_delivery_status(&self) -> Option<DeliveryStatus> {
+        let outbound_ref: OutboundRef =
+            serde_json::from_str(self.biz_opaque_callback_data.as_deref()?).ok()?;
+        let state = match self.status.as_str() {
+            "sent" => DeliveryState::Sent,
+            "delivered" => DeliveryState::Delivered,
+            "read" => DeliveryState::Read,
+            "failed" if self.errors.iter().any(|e| e.code == UNDELIVERABLE) => {
+                DeliveryState::Bounced
+            }
+            "failed" => DeliveryState::Failed,
+            _ => return None,
+        };
+        let reason = (!self.errors.is_empty()).then(|| {
+            self.errors
+                .iter()
+                .map(|error| format!("{} ({})", error.title, error.code))
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+        Some(DeliveryStatus {
+            outbound_id: outbound_ref.outbound_id,
+            ticket_id: outbound_ref.ticket_id,
+            contact: format!("+{}", self.recipient_id.trim_start_matches('+')),
+            origin: Origin::WhatsApp,
+            state,
+            at: self.timestamp.parse().ok()?,
+            reason,
+        })
+    }
+}
+
+/// Caption as content, and the media described. whatsapp-trt downloads its content with its id.
+fn media_message(media: &Media) -> (String, Option<Attachment>) {
+    let extension = media
+        .mime_type
+        .split(';')
+        .next()
+        .and_then(|mime| mime.split('/').nth(1))
+        .unwrap_or("bin");
+    let attachment = Attachment {
+        filename: media
+            .filename
+            .clone()
+            .unwrap_or_else(|| format!("{}.{}", media.id, extension)),
+        content_type: media.mime_type.clone(),
+        // Not given by the webhook
+        size: 0,
+        checksum: media
+            .sha256
+            .as_deref()
+            .and_then(|sha256| BASE64_STANDARD.decode(sha256).ok())
+            .map(hex::encode)
+            .unwrap_or_default(),
+        storage_key: None,
+        source_id: Some(media.id.clone()),
+    };
+    (media.caption.clone().unwrap_or_default(), Some(attachment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Notification {
+        let json = match name {
+            "text" => include_str!("../fixtures/text.json"),
+            "media" => include_str!("../fixtures/media.json"),
+            "status" => include_str!("../fixtures/status.json"),
+            _ => unreachable!(),
+        };
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_text_message() {
+        let messages: Vec<WhatsAppMessage> = fixture("text")
+            .messages()
+            .filter_map(InboundMessage::to_whatsapp_message)
+            .collect();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].sender, "+33612345678");
+        assert_eq!(
+            messages[0].content,
+            "Bonjour, ma commande n'est jamais arrivée"
+        );
+        assert_eq!(messages[0].timestamp, 1772000000);
+        assert!(messages[0].attachments.is_empty());
+        assert_eq!(
+            messages[0].message_id.as_deref(),
+            Some("wamid.HBgLMzM2MTIzNDU2NzgVAgASGBQzQTdCNTg5RjY1RDMxMjg2QkI0MQA=")
+        );
+
+        // Rather than a message from 1970
+        let mut notification = fixture("text");
+        notification.entry[0].changes[0].value.messages[0].timestamp = "soon".to_string();
+        assert_eq!(notification.messages().count(), 1);
+        assert!(
+            notification
+                .messages()
+                .all(|message| message.to_whatsapp_message().is_none())
+        );
+    }
+
+    #[test]
+    fn test_media_messages() {
+        let messages: Vec<WhatsAppMessage> = fixture("media")
+            .messages()
+            .filter_map(InboundMessage::to_whatsapp_message)
+            .collect();
+        // The reaction is left out
+        assert_eq!(messages.len(), 3);
+
+        assert_eq!(messages[0].content, "Le colis abîmé");
+        let image = &messages[0].attachments[0];
+        assert_eq!(image.filename, "1479537139650973.jpeg");
+        assert_eq!(image.content_type, "image/jpeg");
+        assert_eq!(image.checksum.len(), 64);
+        assert_eq!(image.source_id.as_deref(), Some("1479537139650973"));
+
+        assert_eq!(messages[1].content, "");
+        assert_eq!(messages[1].attachments[0].filename, "917424913495385.ogg");
+        assert_eq!(messages[2].attachments[0].filename, "facture-1042.pdf");
+        assert_eq!(
+            messages[2].attachments[0].checksum,
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+        );
+    }
+
+    #[test]
+    fn test_statuses() {
+        let notification = fixture("status");
+        assert_eq!(notification.messages().count(), 0);
+        let statuses: Vec<&Status> = notification.statuses().collect();
+        assert_eq!(statuses[0].status, "delivered");
+        assert_eq!(statuses[1].status, "failed");
+        assert_eq!(statuses[1].errors[0].code, 131047);
+
+        let delivery: Vec<DeliveryStatus> = statuses
+            .iter()
+            .filter_map(|status| status.to_delivery_status())
+            .collect();
+        // The last status is for a message sent outside of whatsapp-sender
+        assert_eq!(delivery.len(), 3);
+        assert_eq!(delivery[0].outbound_id, "r1");
+        assert_eq!(delivery[0].ticket_id, "12");
+        assert_eq!(delivery[0].contact, "+33612345678");
+        assert_eq!(delivery[0].state, DeliveryState::Delivered);
+        assert_eq!(delivery[0].at, 1772000120);
+        assert_eq!(delivery[1].state, DeliveryState::Failed);
+        assert_eq!(
+            delivery[1].reason.as_deref(),
+            Some("Re-engagement message (131047)")
+        );
+        assert_eq!(delivery[2].state, DeliveryState::Bounced);
+    }
+}
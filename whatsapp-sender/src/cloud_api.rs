@@ -0,0 +1,124 @@
+//! Client of the WhatsApp Business Cloud API `messages` endpoint, for text messages
+
+use anyhow::{Context, Result, bail};
+use common::dto::OutboundMessage;
+use serde_json::{Value, json};
+
+pub const DEFAULT_BASE_URL: &str = "https://graph.facebook.com/v21.0";
+
+pub struct CloudApiClient {
+    http: reqwest::Client,
+    /// Graph API root, version included
+    base_url: String,
+    phone_number_id: String,
+    access_token: String,
+}
+
+impl CloudApiClient {
+    pub fn new(base_url: &str, phone_number_id: &str, access_token: &str) -> Self {
+        CloudApiClient {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            phone_number_id: phone_number_id.to_string(),
+            access_token: access_token.to_string(),
+        }
+    }
+
+    /// Send the reply as a text message, quoting the message it answers. Returns the id of the
+    /// message.
+    pub async fn send_reply(&self, reply: &OutboundMessage) -> Result<String> {
+        let url = format!("{}/{}/messages", self.base_url, self.phone_number_id);
+        let response = self
+            .http
+            .post(&url)
+            .bearer_auth(&self.access_token)
+            .json(&reply_payload(reply)?)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", url))?;
+        let status = response.status();
+        let answer: Value = response.json().await.unwrap_or_default();
+        if !status.is_success() {
+            // e.g. 131047 when the customer has not written in the last 24 hours
+            bail!(
+                "Cloud API answered {}: {} ({})",
+                status,
+                answer["error"]["message"].as_str().unwrap_or("no details"),
+                answer["error"]["code"]
+            );
+        }
+        answer["messages"][0]["id"]
+            .as_str()
+            .map(str::to_string)
+            .context("Cloud API answer without message id")
+    }
+}
+
+/// Text message payload. Its statuses come back to whatsapp-gateway with the reply's
+/// `OutboundRef` as `biz_opaque_callback_data`.
+pub fn reply_payload(reply: &OutboundMessage) -> Result<Value> {
+    let mut payload = json!({
+        "messaging_product": "whatsapp",
+        "recipient_type": "individual",
+        "to": reply.contact.trim_start_matches('+'),
+        "type": "text",
+        "text": { "preview_url": false, "body": reply.body },
+        "biz_opaque_callback_data": serde_json::to_string(&reply.outbound_ref())?,
+    });
+    if let Some(message_id) = &reply.in_reply_to {
+        payload["context"] = json!({ "message_id": message_id });
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub;
+    use common::dto::{Origin, OutboundRef};
+    use tokio::net::TcpListener;
+
+    fn reply(in_reply_to: Option<&str>) -> OutboundMessage {
+        OutboundMessage {
+            id: "r1".to_string(),
+            ticket_id: "12".to_string(),
+            contact: "+33612345678".to_string(),
+            origin: Origin::WhatsApp,
+            body: "Bonjour".to_string(),
+            subject: None,
+            in_reply_to: in_reply_to.map(str::to_string),
+            suggestion_id: None,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_reply_payload() -> Result<()> {
+        let payload = reply_payload(&reply(Some("wamid.ABC")))?;
+        assert_eq!(payload["to"], "33612345678");
+        assert_eq!(payload["text"]["body"], "Bonjour");
+        assert_eq!(payload["context"]["message_id"], "wamid.ABC");
+        let outbound_ref: OutboundRef =
+            serde_json::from_str(payload["biz_opaque_callback_data"].as_str().unwrap())?;
+        assert_eq!(outbound_ref.outbound_id, "r1");
+        assert_eq!(outbound_ref.ticket_id, "12");
+        assert!(reply_payload(&reply(None))?["context"].is_null());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_reply_to_stub() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let base_url = format!("http://{}/v21.0", listener.local_addr()?);
+        tokio::spawn(stub::serve(listener));
+
+        let client = CloudApiClient::new(&base_url, "106540352242922", "token");
+        let id = client.send_reply(&reply(None)).await?;
+        assert!(id.starts_with("wamid."));
+
+        let unauthorized = CloudApiClient::new(&base_url, "106540352242922", "");
+        let error = unauthorized.send_reply(&reply(None)).await.unwrap_err();
+        assert!(error.to_string().contains("401"));
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+mod cloud_api;
+mod stub;
+
+use std::env;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use cloud_api::{CloudApiClient, DEFAULT_BASE_URL};
+use common::{
+    DELIVERY_STATUS_QUEUE, OUTBOUND_MSG_QUEUE,
+    dto::{Origin, OutboundMessage},
+    queue::{Message, QueueManager, kafka::KafkaQueueManager},
+    ticket::DeliveryState,
+};
+
+#[derive(Parser)]
+#[command()]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve a local Cloud API stub, to point `WHATSAPP_API_BASE_URL` at
+    ServeStub {
+        #[arg(long, default_value_t = 8091)]
+        port: u16,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    if let Some(Command::ServeStub { port }) = args.command {
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+        println!(
+            "Stub Cloud API listening on {}, base URL http://{}/v21.0",
+            listener.local_addr()?,
+            listener.local_addr()?
+        );
+        return stub::serve(listener).await;
+    }
+
+    println!("Starting WhatsApp sender...");
+    let base_url =
+        env::var("WHATSAPP_API_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+    let phone_number_id =
+        env::var("WHATSAPP_PHONE_NUMBER_ID").context("WHATSAPP_PHONE_NUMBER_ID must be set")?;
+    let access_token =
+        env::var("WHATSAPP_ACCESS_TOKEN").context("WHATSAPP_ACCESS_TOKEN must be set")?;
+    let client = CloudApiClient::new(&base_url, &phone_number_id, &access_token);
+
+    // Own consumer group: the sender of each channel reads the whole topic
+    let queue_mgr = KafkaQueueManager::with_group_id("whatsapp-sender")
+        .await
+        .context("Failed to connect to Kafka")?;
+    queue_mgr.create(OUTBOUND_MSG_QUEUE).await?;
+    queue_mgr.create(DELIVERY_STATUS_QUEUE).await?;
+
+    println!(
+        "Listening for outbound messages on '{}', sending through {}...",
+        OUTBOUND_MSG_QUEUE, base_url
+    );
+    queue_mgr
+        .register_read(OUTBOUND_MSG_QUEUE, &async |msg: Message<
+            OutboundMessage,
+        >| {
+            let reply = msg.message;
+            // Email replies are email-sender's, which also dead-letters those of other channels
+            if reply.origin != Origin::WhatsApp {
+                return Ok(());
+            }
+            // Its statuses come back through whatsapp-gateway, once the Cloud API accepted it
+            let message_id = match client
+                .send_reply(&reply)
+                .await
+                .with_context(|| format!("Failed to send reply {}", reply.id))
+            {
+                Ok(message_id) => message_id,
+                Err(e) => {
+                    let status = reply.status(DeliveryState::Failed, Some(format!("{:#}", e)));
+                    queue_mgr.send(DELIVERY_STATUS_QUEUE, &status).await?;
+                    return Err(e);
+                }
+            };
+            println!(
+                "Reply {} on ticket {} sent to {} (id={})",
+                reply.id, reply.ticket_id, reply.contact, message_id
+            );
+            Ok(())
+        })
+        .await
+}
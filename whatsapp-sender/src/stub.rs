@@ -0,0 +1,92 @@
+use axum::{
+    Json, Router,
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+};
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+
+/// Serve a minimal Cloud API `/{version}/{phone_number_id}/messages` endpoint accepting every
+/// message, so that replies can be sent without a WhatsApp Business account, and a media endpoint
+/// serving a placeholder for any media id
+pub async fn serve(listener: TcpListener) -> anyhow::Result<()> {
+    let app = Router::new()
+        .route("/{version}/{phone_number_id}/messages", post(messages))
+        .route("/{version}/{media_id}", get(media))
+        .route("/media/{media_id}", get(media_content));
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn messages(
+    Path((_, phone_number_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(request): Json<Value>,
+) -> (StatusCode, Json<Value>) {
+    if !authorized(&headers) {
+        return error(StatusCode::UNAUTHORIZED, 190, "Invalid OAuth access token");
+    }
+    let (Some(to), Some(body)) = (request["to"].as_str(), request["text"]["body"].as_str()) else {
+        return error(StatusCode::BAD_REQUEST, 100, "Invalid parameter");
+    };
+
+    let id = format!("wamid.stub-{}", uuid::Uuid::new_v4());
+    println!("[stub] {} -> {}: {} (id={})", phone_number_id, to, body, id);
+    (
+        StatusCode::OK,
+        Json(json!({
+            "messaging_product": "whatsapp",
+            "contacts": [{ "input": to, "wa_id": to }],
+            "messages": [{ "id": id }],
+        })),
+    )
+}
+
+fn authorized(headers: &HeaderMap) -> bool {
+    headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| !token.is_empty())
+}
+
+/// URL the content of the media is downloaded from, on the stub itself
+async fn media(
+    Path((_, media_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    if !authorized(&headers) {
+        return error(StatusCode::UNAUTHORIZED, 190, "Invalid OAuth access token");
+    }
+    let host = headers
+        .get("host")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("localhost:8091");
+    (
+        StatusCode::OK,
+        Json(json!({
+            "messaging_product": "whatsapp",
+            "url": format!("http://{}/media/{}", host, media_id),
+            "mime_type": "application/octet-stream",
+            "id": media_id,
+        })),
+    )
+}
+
+async fn media_content(Path(media_id): Path<String>, headers: HeaderMap) -> (StatusCode, Vec<u8>) {
+    if !authorized(&headers) {
+        return (StatusCode::UNAUTHORIZED, vec![]);
+    }
+    (
+        StatusCode::OK,
+        format!("stub media {}", media_id).into_bytes(),
+    )
+}
+
+fn error(status: StatusCode, code: u32, message: &str) -> (StatusCode, Json<Value>) {
+    (
+        status,
+        Json(json!({ "error": { "message": message, "type": "OAuthException", "code": code } })),
+    )
+}
@@ -1,8 +1,9 @@
 use clap::Parser;
 use common::{
     WHATSAPP_MSG_QUEUE,
-    dto::WhatsAppMessage,
+    dto::{Attachment, WhatsAppMessage},
     queue::{QueueManager, kafka::KafkaQueueManager},
+    storage::checksum,
 };
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -18,6 +19,22 @@ struct Args {
     loop_send: bool,
 }
 
+/// Every third customer sends a voice note along; content is not uploaded, only described
+fn fake_attachments(i: u32) -> Vec<Attachment> {
+    if !i.is_multiple_of(3) {
+        return vec![];
+    }
+    let content = format!("fake OGG voice note #{}", i).into_bytes();
+    vec![Attachment {
+        filename: format!("voice-note-{}.ogg", i),
+        content_type: "audio/ogg".to_string(),
+        size: content.len() as u64,
+        checksum: checksum(&content),
+        storage_key: None,
+        source_id: None,
+    }]
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
@@ -31,7 +48,7 @@ async fn main() -> anyhow::Result<()> {
     queue_mgr
         .create(WHATSAPP_MSG_QUEUE)
         .await
-        .expect(&format!("Failed to create topic '{}'", WHATSAPP_MSG_QUEUE));
+        .unwrap_or_else(|_| panic!("Failed to create topic '{}'", WHATSAPP_MSG_QUEUE));
     println!("Topic '{}' ready", WHATSAPP_MSG_QUEUE);
 
     if args.loop_send {
@@ -44,12 +61,14 @@ async fn main() -> anyhow::Result<()> {
                     .duration_since(UNIX_EPOCH)
                     .expect("Failed to get current timestamp")
                     .as_secs(),
+                attachments: fake_attachments(i),
+                message_id: None,
             };
 
             let msg_id = queue_mgr
                 .send(WHATSAPP_MSG_QUEUE, &msg)
                 .await
-                .expect(&format!("Failed to send WhatsApp message {} to Kafka", i));
+                .unwrap_or_else(|_| panic!("Failed to send WhatsApp message {} to Kafka", i));
             
             println!("Sent message {} (id={}): from={}, content={}", 
                      i, msg_id, msg.sender, msg.content);
@@ -66,12 +85,14 @@ async fn main() -> anyhow::Result<()> {
                     .duration_since(UNIX_EPOCH)
                     .expect("Failed to get current timestamp")
                     .as_secs(),
+                attachments: fake_attachments(i),
+                message_id: None,
             };
 
             let msg_id = queue_mgr
                 .send(WHATSAPP_MSG_QUEUE, &msg)
                 .await
-                .expect(&format!("Failed to send WhatsApp message {} to Kafka", i));
+                .unwrap_or_else(|_| panic!("Failed to send WhatsApp message {} to Kafka", i));
             
             println!("Sent message {}/{} (id={}): from={}, content={}", 
                      i, count, msg_id, msg.sender, msg.content);
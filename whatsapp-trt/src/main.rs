@@ -1,47 +1,71 @@
-use anyhow::Context;
+mod media;
+
+use std::env;
+
 use common::{
+    channel::{self, ChannelAdapter},
     dto::{CommonMessage, WhatsAppMessage},
-    queue::{kafka::KafkaQueueManager, Message, QueueManager},
-    COMMON_MSG_QUEUE, WHATSAPP_MSG_QUEUE,
+    storage::{archive_raw, store_channel_attachments, ObjectStore},
+    WHATSAPP_MSG_QUEUE,
 };
+use media::MediaClient;
+
+const DEFAULT_API_BASE_URL: &str = "https://graph.facebook.com/v21.0";
+
+struct WhatsApp {
+    /// `None` without `WHATSAPP_ACCESS_TOKEN`, media then stay on WhatsApp
+    media: Option<MediaClient>,
+}
+
+impl ChannelAdapter for WhatsApp {
+    type Input = WhatsAppMessage;
+    type Parts = ();
+    const NAME: &'static str = "WhatsApp";
+    const SOURCE_QUEUE: &'static str = WHATSAPP_MSG_QUEUE;
+
+    async fn normalize(
+        &self,
+        input: WhatsAppMessage,
+    ) -> anyhow::Result<Option<(CommonMessage, ())>> {
+        Ok(Some((input.try_into()?, ())))
+    }
+
+    /// WhatsApp conversations are per sender
+    fn thread_key(&self, _input: &WhatsAppMessage, message: &CommonMessage) -> Option<String> {
+        Some(message.contact.clone())
+    }
+
+    /// The media of the message are downloaded along, WhatsApp only keeps them for a while
+    async fn archive(
+        &self,
+        store: &impl ObjectStore,
+        input: &WhatsAppMessage,
+        message: &mut CommonMessage,
+        _parts: (),
+    ) -> anyhow::Result<()> {
+        if let Some(media) = &self.media {
+            store_channel_attachments(store, message, &async |media_id: &str| {
+                media.download(media_id).await
+            })
+            .await?;
+        }
+        archive_raw(store, input, message).await?;
+        Ok(())
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    println!("Starting WhatsApp processor with Kafka...");
-    let queue_mgr = KafkaQueueManager::new()
-        .await
-        .expect("Failed to connect to Kafka - ensure Kafka is running on localhost:9092");
-
-    // Create queues
-    queue_mgr.create(WHATSAPP_MSG_QUEUE).await.expect(&format!(
-        "Failed to create WhatsApp topic '{}'",
-        WHATSAPP_MSG_QUEUE
-    ));
-    queue_mgr.create(COMMON_MSG_QUEUE).await.expect(&format!(
-        "Failed to create common topic '{}'",
-        COMMON_MSG_QUEUE
-    ));
-
-    println!(
-        "Listening for WhatsApp messages on topic '{}'...",
-        WHATSAPP_MSG_QUEUE
-    );
-
-    queue_mgr
-        .register_read(WHATSAPP_MSG_QUEUE, &async |wrapper: Message<
-            WhatsAppMessage,
-        >| {
-            // Transform to common message
-            let common_msg: CommonMessage = wrapper.message.into();
-            println!("Transformed to: {:?}", common_msg);
-
-            // Send to common queue
-            let forwarded_id = queue_mgr
-                .send(COMMON_MSG_QUEUE, &common_msg)
-                .await
-                .context("Failed to forward message to common queue")?;
-            println!("Forwarded to common queue (id={})", forwarded_id);
-            Ok(())
-        })
-        .await
+    let media = match env::var("WHATSAPP_ACCESS_TOKEN") {
+        Ok(access_token) => {
+            let base_url = env::var("WHATSAPP_API_BASE_URL")
+                .unwrap_or_else(|_| DEFAULT_API_BASE_URL.to_string());
+            Some(MediaClient::new(&base_url, &access_token))
+        }
+        Err(_) => {
+            println!("WHATSAPP_ACCESS_TOKEN is not set, media are not downloaded");
+            None
+        }
+    };
+    channel::run(WhatsApp { media }).await
 }
@@ -0,0 +1,121 @@
+//! Client of the WhatsApp Business Cloud API media endpoint: the webhook only gives the id of a
+//! media, its content is downloaded from the short-lived URL the endpoint hands out for it
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+pub struct MediaClient {
+    http: reqwest::Client,
+    /// Graph API root, version included
+    base_url: String,
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct MediaUrl {
+    url: String,
+}
+
+impl MediaClient {
+    pub fn new(base_url: &str, access_token: &str) -> Self {
+        MediaClient {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            access_token: access_token.to_string(),
+        }
+    }
+
+    /// Content of the media, both requests need the access token
+    pub async fn download(&self, media_id: &str) -> Result<Vec<u8>> {
+        let url = format!("{}/{}", self.base_url, media_id);
+        let media: MediaUrl = self
+            .get(&url)
+            .await?
+            .json()
+            .await
+            .with_context(|| format!("Invalid answer from {}", url))?;
+        let content = self.get(&media.url).await?.bytes().await?;
+        Ok(content.to_vec())
+    }
+
+    async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        let response = self
+            .http
+            .get(url)
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", url))?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!(
+                "Cloud API answered {} for {}: {}",
+                status,
+                url,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        extract::Path,
+        http::{HeaderMap, StatusCode},
+        routing::get,
+        Json, Router,
+    };
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+
+    fn authorized(headers: &HeaderMap) -> bool {
+        headers
+            .get("authorization")
+            .is_some_and(|value| value == "Bearer token")
+    }
+
+    #[tokio::test]
+    async fn test_download() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let root = format!("http://{}", listener.local_addr()?);
+        let media_root = root.clone();
+        let app = Router::new()
+            .route(
+                "/v21.0/{media_id}",
+                get(
+                    move |Path(media_id): Path<String>, headers: HeaderMap| async move {
+                        if !authorized(&headers) || media_id != "1234" {
+                            return Err(StatusCode::NOT_FOUND);
+                        }
+                        Ok(Json::<Value>(json!({
+                            "url": format!("{}/download/{}", media_root, media_id),
+                            "mime_type": "audio/ogg",
+                            "id": media_id,
+                        })))
+                    },
+                ),
+            )
+            .route(
+                "/download/{media_id}",
+                get(|headers: HeaderMap| async move {
+                    if !authorized(&headers) {
+                        return Err(StatusCode::UNAUTHORIZED);
+                    }
+                    Ok("OggS")
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = MediaClient::new(&format!("{}/v21.0/", root), "token");
+        assert_eq!(client.download("1234").await?, b"OggS");
+        assert!(client.download("5678").await.is_err());
+        assert!(MediaClient::new(&format!("{}/v21.0", root), "expired")
+            .download("1234")
+            .await
+            .is_err());
+        Ok(())
+    }
+}